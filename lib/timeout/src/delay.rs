@@ -0,0 +1,208 @@
+use futures::{Async, Future, Poll};
+use std::time::Duration;
+use tokio_timer::{clock, Delay};
+
+use linkerd2_stack as stk;
+use svc;
+
+/// Creates a layer that delays each response by the duration that `delay`
+/// returns for the stack's target, e.g. to inject latency when testing an
+/// application's resilience.
+///
+/// Responses are delayed from when the request is dispatched, so that a
+/// response that takes longer than the delay is not delayed further. Failures
+/// are delayed as well. Targets without a delay are not affected.
+pub fn layer<F>(delay: F) -> Layer<F> {
+    Layer { delay }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer<F> {
+    delay: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<F, M> {
+    delay: F,
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    inner: F,
+    delay: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    delay: Option<Duration>,
+}
+
+pub struct ResponseFuture<F: Future> {
+    inner: F,
+    delay: Option<Delay>,
+    /// Holds the inner future's result until the delay has elapsed.
+    result: Option<Result<F::Item, F::Error>>,
+}
+
+// === impl Layer ===
+
+impl<F: Clone, M> stk::Layer<M> for Layer<F> {
+    type Service = Stack<F, M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            delay: self.delay.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<F, T, M> svc::Service<T> for Stack<F, M>
+where
+    F: Fn(&T) -> Option<Duration>,
+    M: svc::Service<T>,
+{
+    type Response = Service<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let delay = (self.delay)(&target).filter(|d| *d > Duration::from_secs(0));
+        let inner = self.inner.call(target);
+        MakeFuture { inner, delay }
+    }
+}
+
+// === impl MakeFuture ===
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = Service<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Service {
+            inner,
+            delay: self.delay,
+        }
+        .into())
+    }
+}
+
+// === impl Service ===
+
+impl<S> Service<S> {
+    /// Delays each of `inner`'s responses by `delay`, if it is set.
+    pub fn new(inner: S, delay: Option<Duration>) -> Self {
+        Self { inner, delay }
+    }
+}
+
+impl<S, Req> svc::Service<Req> for Service<S>
+where
+    S: svc::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            delay: self.delay.map(|d| Delay::new(clock::now() + d)),
+            result: None,
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F: Future> Future for ResponseFuture<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.result.is_none() {
+            match self.inner.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(rsp)) => self.result = Some(Ok(rsp)),
+                Err(e) => self.result = Some(Err(e)),
+            }
+        }
+
+        if let Some(ref mut delay) = self.delay {
+            // If the timer fails, the response is not delayed further.
+            if let Ok(Async::NotReady) = delay.poll() {
+                return Ok(Async::NotReady);
+            }
+        }
+
+        self.result
+            .take()
+            .expect("polled after ready")
+            .map(Async::Ready)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use std::time::Instant;
+    use svc::Service as _;
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+    use error::Error;
+    use linkerd2_stack::Layer as _;
+
+    /// Responds immediately.
+    #[derive(Clone)]
+    struct Ready;
+
+    impl svc::Service<()> for Ready {
+        type Response = ();
+        type Error = Error;
+        type Future = future::FutureResult<(), Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            future::ok(())
+        }
+    }
+
+    #[test]
+    fn delays_responses() {
+        let delay = Duration::from_millis(20);
+        let mut svc = Service::new(Ready, Some(delay));
+
+        let mut rt = Runtime::new().expect("runtime");
+        let t0 = Instant::now();
+        rt.block_on(svc.call(())).expect("must respond");
+        assert!(t0.elapsed() >= delay);
+    }
+
+    #[test]
+    fn passes_through_without_delay() {
+        let mut make = layer(|_: &()| Some(Duration::from_secs(0))).layer(stk::shared(Ready));
+        let mut svc = make.call(()).wait().expect("must make");
+        assert!(svc.delay.is_none());
+
+        let mut rsp = svc.call(());
+        assert!(rsp.delay.is_none());
+        assert_eq!(rsp.poll().expect("must respond"), Async::Ready(()));
+    }
+}
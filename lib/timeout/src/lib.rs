@@ -5,11 +5,15 @@ extern crate tokio_connect;
 extern crate tokio_timer;
 extern crate tower_service as svc;
 
+#[cfg(test)]
+extern crate tokio;
+
 use futures::{Future, Poll};
 use std::time::Duration;
 use tokio_connect::Connect;
 use tokio_timer as timer;
 
+pub mod delay;
 pub mod error;
 pub mod stack;
 
@@ -99,3 +103,92 @@ where
         self.inner.poll().map_err(|e| self.timeout_error(e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use svc::Service;
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+
+    /// Responds after `delay`, or never. Records whether the response future
+    /// was dropped before it completed.
+    struct Slow {
+        delay: Option<Duration>,
+        canceled: Arc<AtomicBool>,
+    }
+
+    struct Canceled(Option<Arc<AtomicBool>>);
+
+    impl svc::Service<()> for Slow {
+        type Response = ();
+        type Error = Error;
+        type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            let canceled = Canceled(Some(self.canceled.clone()));
+            let rsp: Self::Future = match self.delay {
+                Some(d) => {
+                    Box::new(timer::Delay::new(::std::time::Instant::now() + d).map_err(Into::into))
+                }
+                None => Box::new(future::empty()),
+            };
+            Box::new(rsp.map(move |()| canceled.complete()))
+        }
+    }
+
+    impl Canceled {
+        fn complete(mut self) {
+            self.0 = None;
+        }
+    }
+
+    impl Drop for Canceled {
+        fn drop(&mut self) {
+            if let Some(c) = self.0.take() {
+                c.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[test]
+    fn slow_future_is_canceled_at_deadline() {
+        let canceled = Arc::new(AtomicBool::new(false));
+        let mut svc = Timeout::new(
+            Slow {
+                delay: None,
+                canceled: canceled.clone(),
+            },
+            Duration::from_millis(10),
+        );
+
+        let mut rt = Runtime::new().expect("runtime");
+        let err = rt.block_on(svc.call(())).expect_err("must time out");
+        assert!(err.downcast_ref::<Timedout>().is_some());
+        assert!(canceled.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn fast_future_completes() {
+        let canceled = Arc::new(AtomicBool::new(false));
+        let mut svc = Timeout::new(
+            Slow {
+                delay: Some(Duration::from_millis(1)),
+                canceled: canceled.clone(),
+            },
+            Duration::from_secs(10),
+        );
+
+        let mut rt = Runtime::new().expect("runtime");
+        rt.block_on(svc.call(())).expect("must not time out");
+        assert!(!canceled.load(Ordering::SeqCst));
+    }
+}
@@ -2,13 +2,15 @@
 //!
 //! * `/metrics` -- reports prometheus-formatted metrics.
 //! * `/ready` -- returns 200 when the proxy is ready to participate in meshed traffic.
+//! * `/tap/sessions` -- describes active tap sessions as JSON.
 
 use futures::future::{self, FutureResult};
-use http::StatusCode;
+use http::{header, StatusCode};
 use hyper::{service::Service, Body, Request, Response};
 use std::io;
 
 use metrics;
+use tap;
 
 mod readiness;
 pub use self::readiness::{Latch, Readiness};
@@ -20,16 +22,18 @@ where
 {
     metrics: metrics::Serve<M>,
     ready: Readiness,
+    tap_sessions: tap::Sessions,
 }
 
 impl<M> Admin<M>
 where
     M: metrics::FmtMetrics,
 {
-    pub fn new(m: M, ready: Readiness) -> Self {
+    pub fn new(m: M, ready: Readiness, tap_sessions: tap::Sessions) -> Self {
         Self {
             metrics: metrics::Serve::new(m),
             ready,
+            tap_sessions,
         }
     }
 
@@ -46,6 +50,14 @@ where
                 .expect("builder with known status code must not fail")
         }
     }
+
+    fn tap_sessions_rsp(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(self.tap_sessions.to_json().into())
+            .expect("builder with known status code must not fail")
+    }
 }
 
 impl<M> Service for Admin<M>
@@ -61,6 +73,7 @@ where
         match req.uri().path() {
             "/metrics" => self.metrics.call(req),
             "/ready" => future::ok(self.ready_rsp()),
+            "/tap/sessions" => future::ok(self.tap_sessions_rsp()),
             _ => future::ok(
                 Response::builder()
                     .status(StatusCode::NOT_FOUND)
@@ -88,7 +101,7 @@ mod tests {
         let l1 = l0.clone();
 
         let mut rt = Runtime::new().unwrap();
-        let mut srv = Admin::new((), r);
+        let mut srv = Admin::new((), r, tap::Sessions::default());
         macro_rules! call {
             () => {{
                 let r = Request::builder()
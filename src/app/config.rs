@@ -6,7 +6,7 @@ use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 
 use super::control::ControlAddr;
 use super::identity;
@@ -14,10 +14,12 @@ use addr;
 use convert::TryFrom;
 use dns;
 use proxy::reconnect::Backoff;
+use tap;
 use transport::tls;
 use {Addr, Conditional};
 
 const INBOUND_CONNECT_BASE: &str = "INBOUND_CONNECT";
+const INBOUND_CONNECT_RETRY_BASE: &str = "INBOUND_CONNECT_RETRY";
 const OUTBOUND_CONNECT_BASE: &str = "OUTBOUND_CONNECT";
 pub const CONTROL_BASE: &str = "CONTROL";
 
@@ -33,12 +35,31 @@ pub struct Config {
     /// Where to listen for connections initiated by the control plane.
     pub control_listener: Option<Listener>,
 
+    /// Where to write all tap events as newline-delimited JSON, if anywhere.
+    pub tap_json_log: Option<fs::File>,
+
+    /// Where to write each tapped stream as a newline-delimited span, if
+    /// anywhere.
+    pub tap_span_log: Option<fs::File>,
+
+    /// Labels all tap events with this direction, if set, rather than the
+    /// direction that each request is inferred to have.
+    pub tap_direction: Option<tap::DirectionMatch>,
+
+    /// Whether tap clients may request that events be delivered with
+    /// backpressure, delaying the streams that they tap.
+    pub tap_backpressure_enabled: bool,
+
     /// Where to serve admin HTTP.
     pub admin_listener: Listener,
 
     /// Where to forward externally received connections.
     pub inbound_forward: Option<SocketAddr>,
 
+    /// Additional forwarding addresses, tried after `inbound_forward` when
+    /// selecting an address in the same family as a connection's source.
+    pub inbound_forward_alternates: Vec<SocketAddr>,
+
     /// The maximum amount of time that an inbound request can spend buffered in the inbound proxy.
     pub inbound_dispatch_timeout: Duration,
 
@@ -58,6 +79,15 @@ pub struct Config {
     /// between inbound connection attempts.
     pub inbound_connect_backoff: Backoff,
 
+    /// The number of times a failed connection to a local peer is retried
+    /// before the connection fails. Retries are bounded by
+    /// `inbound_connect_timeout`.
+    pub inbound_connect_retries: usize,
+
+    /// Settings for the back-off used to determine the amount of time to wait
+    /// between inbound connect retries.
+    pub inbound_connect_retry_backoff: Backoff,
+
     /// Settings for the back-off used to determine the amount of time to wait
     /// between outbound connection attempts.
     pub outbound_connect_backoff: Backoff,
@@ -78,6 +108,41 @@ pub struct Config {
 
     pub outbound_ports_disable_protocol_detection: IndexSet<u16>,
 
+    /// Inbound ports that are not routed by the inbound proxy.
+    pub inbound_ports_skip: IndexSet<u16>,
+
+    /// Maps inbound original destination ports to the ports on which the
+    /// local application is served.
+    pub inbound_ports_remap: IndexMap<u16, u16>,
+
+    /// Maps the hosts of inbound requests to the addresses of the local
+    /// applications that serve them, overriding the original destination.
+    pub inbound_authority_addrs: IndexMap<String, SocketAddr>,
+
+    /// Overrides the HTTP/2 flow control windows used by the inbound client,
+    /// by the port on which the local application is served.
+    pub inbound_ports_h2_settings: IndexMap<u16, H2Settings>,
+
+    /// The identity of the local application, if inbound connections to it
+    /// should be secured with TLS.
+    pub inbound_local_identity: Option<identity::Name>,
+
+    /// Whether inbound requests may name their endpoint with the
+    /// `l5d-dst-override` header.
+    pub inbound_dst_override_enabled: bool,
+
+    /// Whether inbound requests may choose the protocol used to reach the
+    /// local application with the `l5d-force-proto` header.
+    pub inbound_force_proto_enabled: bool,
+
+    /// Configures how idle HTTP/1 connections to the local application are
+    /// pooled for reuse.
+    pub inbound_h1_settings: H1Settings,
+
+    /// Identifies the inbound HTTP client in diagnostics, so that multiple
+    /// proxies' inbound stacks may be distinguished.
+    pub inbound_client_name: String,
+
     pub inbound_router_capacity: usize,
 
     pub outbound_router_capacity: usize,
@@ -88,8 +153,21 @@ pub struct Config {
 
     pub inbound_max_requests_in_flight: usize,
 
+    /// Bounds the number of requests in flight to each inbound endpoint.
+    /// When unset, each endpoint may have as many requests in flight as the
+    /// inbound proxy as a whole.
+    pub inbound_max_requests_in_flight_per_endpoint: Option<usize>,
+
     pub outbound_max_requests_in_flight: usize,
 
+    /// Bounds the size of each inbound request's headers, in bytes.
+    /// Unbounded when unset.
+    pub inbound_max_request_header_bytes: Option<usize>,
+
+    /// Whether the responses that the proxy returns for failed requests
+    /// describe the failure in an `l5d-proxy-error` header.
+    pub proxy_error_header_enabled: bool,
+
     /// Age after which metrics may be dropped.
     pub metrics_retain_idle: Duration,
 
@@ -147,7 +225,16 @@ pub struct Config {
     pub h2_settings: H2Settings,
 }
 
+/// Configures the reuse of idle HTTP/1 client connections.
+///
+/// When unset, the HTTP client's defaults are used.
 #[derive(Copy, Clone, Debug, Default)]
+pub struct H1Settings {
+    pub max_idle_per_endpoint: Option<usize>,
+    pub idle_timeout: Option<Duration>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct H2Settings {
     pub initial_stream_window_size: Option<u32>,
     pub initial_connection_window_size: Option<u32>,
@@ -174,6 +261,12 @@ pub enum ParseError {
     NotADuration,
     NotADomainSuffix,
     NotANumber,
+    NotAPortMapping,
+    NotAnAuthorityMapping,
+    NotADirection,
+    NotAWindowSizeMapping,
+    EmptyName,
+    NotAWritableFile,
     HostIsNotAnIpAddress,
     NotUnicode,
     AddrError(addr::Error),
@@ -201,6 +294,7 @@ pub struct TestEnv {
 // Environment variables to look at when loading the configuration
 pub const ENV_OUTBOUND_LISTEN_ADDR: &str = "LINKERD2_PROXY_OUTBOUND_LISTEN_ADDR";
 pub const ENV_INBOUND_FORWARD: &str = "LINKERD2_PROXY_INBOUND_FORWARD";
+pub const ENV_INBOUND_FORWARD_ALTERNATES: &str = "LINKERD2_PROXY_INBOUND_FORWARD_ALTERNATES";
 pub const ENV_INBOUND_LISTEN_ADDR: &str = "LINKERD2_PROXY_INBOUND_LISTEN_ADDR";
 pub const ENV_CONTROL_LISTEN_ADDR: &str = "LINKERD2_PROXY_CONTROL_LISTEN_ADDR";
 pub const ENV_ADMIN_LISTEN_ADDR: &str = "LINKERD2_PROXY_ADMIN_LISTEN_ADDR";
@@ -213,6 +307,7 @@ const ENV_INBOUND_ACCEPT_KEEPALIVE: &str = "LINKERD2_PROXY_INBOUND_ACCEPT_KEEPAL
 const ENV_OUTBOUND_ACCEPT_KEEPALIVE: &str = "LINKERD2_PROXY_OUTBOUND_ACCEPT_KEEPALIVE";
 
 const ENV_INBOUND_CONNECT_KEEPALIVE: &str = "LINKERD2_PROXY_INBOUND_CONNECT_KEEPALIVE";
+pub const ENV_INBOUND_CONNECT_RETRIES: &str = "LINKERD2_PROXY_INBOUND_CONNECT_RETRIES";
 const ENV_OUTBOUND_CONNECT_KEEPALIVE: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_KEEPALIVE";
 
 pub const DEPRECATED_ENV_PRIVATE_LISTEN_ADDR: &str = "LINKERD2_PROXY_PRIVATE_LISTEN_ADDR";
@@ -228,7 +323,12 @@ pub const ENV_INBOUND_ROUTER_MAX_IDLE_AGE: &str = "LINKERD2_PROXY_INBOUND_ROUTER
 pub const ENV_OUTBOUND_ROUTER_MAX_IDLE_AGE: &str = "LINKERD2_PROXY_OUTBOUND_ROUTER_MAX_IDLE_AGE";
 
 pub const ENV_INBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_INBOUND_MAX_IN_FLIGHT";
+pub const ENV_INBOUND_MAX_IN_FLIGHT_PER_ENDPOINT: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_IN_FLIGHT_PER_ENDPOINT";
 pub const ENV_OUTBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_OUTBOUND_MAX_IN_FLIGHT";
+pub const ENV_INBOUND_MAX_REQUEST_HEADER_BYTES: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_REQUEST_HEADER_BYTES";
+pub const ENV_PROXY_ERROR_HEADER_ENABLED: &str = "LINKERD2_PROXY_ERROR_HEADER_ENABLED";
 
 /// Constrains which destination names are resolved through the destination
 /// service.
@@ -259,6 +359,19 @@ pub const ENV_INBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str =
     "LINKERD2_PROXY_INBOUND_PORTS_DISABLE_PROTOCOL_DETECTION";
 pub const ENV_OUTBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str =
     "LINKERD2_PROXY_OUTBOUND_PORTS_DISABLE_PROTOCOL_DETECTION";
+pub const ENV_INBOUND_PORTS_SKIP: &str = "LINKERD2_PROXY_INBOUND_PORTS_SKIP";
+pub const ENV_INBOUND_PORTS_REMAP: &str = "LINKERD2_PROXY_INBOUND_PORTS_REMAP";
+pub const ENV_INBOUND_AUTHORITY_ADDRS: &str = "LINKERD2_PROXY_INBOUND_AUTHORITY_ADDRS";
+pub const ENV_INBOUND_PORTS_HTTP2_WINDOW_SIZES: &str =
+    "LINKERD2_PROXY_INBOUND_PORTS_HTTP2_WINDOW_SIZES";
+pub const ENV_INBOUND_LOCAL_IDENTITY: &str = "LINKERD2_PROXY_INBOUND_LOCAL_IDENTITY";
+pub const ENV_INBOUND_DST_OVERRIDE_ENABLED: &str = "LINKERD2_PROXY_INBOUND_DST_OVERRIDE_ENABLED";
+pub const ENV_INBOUND_FORCE_PROTO_ENABLED: &str = "LINKERD2_PROXY_INBOUND_FORCE_PROTO_ENABLED";
+pub const ENV_INBOUND_MAX_IDLE_CONNECTIONS_PER_ENDPOINT: &str =
+    "LINKERD2_PROXY_INBOUND_MAX_IDLE_CONNECTIONS_PER_ENDPOINT";
+pub const ENV_INBOUND_CONNECTION_IDLE_TIMEOUT: &str =
+    "LINKERD2_PROXY_INBOUND_CONNECTION_IDLE_TIMEOUT";
+pub const ENV_INBOUND_CLIENT_NAME: &str = "LINKERD2_PROXY_INBOUND_CLIENT_NAME";
 
 pub const ENV_IDENTITY_DISABLED: &str = "LINKERD2_PROXY_IDENTITY_DISABLED";
 pub const ENV_IDENTITY_DIR: &str = "LINKERD2_PROXY_IDENTITY_DIR";
@@ -279,6 +392,22 @@ pub const ENV_CONTROL_EXP_BACKOFF_MIN: &str = "LINKERD2_PROXY_CONTROL_EXP_BACKOF
 pub const ENV_CONTROL_EXP_BACKOFF_MAX: &str = "LINKERD2_PROXY_CONTROL_EXP_BACKOFF_MAX";
 pub const ENV_CONTROL_EXP_BACKOFF_JITTER: &str = "LINKERD2_PROXY_CONTROL_EXP_BACKOFF_JITTER";
 pub const ENV_TAP_DISABLED: &str = "LINKERD2_PROXY_TAP_DISABLED";
+
+/// Configures a file to which all tap events are written as newline-delimited
+/// JSON. This is intended for local debugging.
+const ENV_TAP_JSON_LOG: &str = "LINKERD2_PROXY_TAP_JSON_LOG";
+
+/// Configures a file to which each tapped stream is written as a span, in the
+/// JSON encoding of the OpenTelemetry protocol.
+const ENV_TAP_SPAN_LOG: &str = "LINKERD2_PROXY_TAP_SPAN_LOG";
+
+/// Configures the direction, `inbound` or `outbound`, of all tap events, e.g.
+/// for gateways, where it cannot be inferred from each request.
+const ENV_TAP_DIRECTION: &str = "LINKERD2_PROXY_TAP_DIRECTION";
+
+/// Allows tap clients to request that tapped streams wait for them to read
+/// each stream's events, rather than dropping events that cannot be buffered.
+const ENV_TAP_BACKPRESSURE_ENABLED: &str = "LINKERD2_PROXY_TAP_BACKPRESSURE_ENABLED";
 const ENV_CONTROL_CONNECT_TIMEOUT: &str = "LINKERD2_PROXY_CONTROL_CONNECT_TIMEOUT";
 const ENV_CONTROL_DISPATCH_TIMEOUT: &str = "LINKERD2_PROXY_CONTROL_DISPATCH_TIMEOUT";
 const ENV_RESOLV_CONF: &str = "LINKERD2_PROXY_RESOLV_CONF";
@@ -316,6 +445,11 @@ const DEFAULT_INBOUND_CONNECT_BACKOFF: Backoff = Backoff::Exponential {
     max: Duration::from_millis(500),
     jitter: 0.1,
 };
+const DEFAULT_INBOUND_CONNECT_RETRY_BACKOFF: Backoff = Backoff::Exponential {
+    min: Duration::from_millis(10),
+    max: Duration::from_millis(50),
+    jitter: 0.1,
+};
 const DEFAULT_OUTBOUND_DISPATCH_TIMEOUT: Duration = Duration::from_secs(1);
 const DEFAULT_OUTBOUND_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
 const DEFAULT_OUTBOUND_CONNECT_BACKOFF: Backoff = Backoff::Exponential {
@@ -343,6 +477,7 @@ const DEFAULT_OUTBOUND_ROUTER_MAX_IDLE_AGE: Duration = Duration::from_secs(60);
 
 // 10_000 is arbitrarily chosen for now...
 const DEFAULT_INBOUND_MAX_IN_FLIGHT: usize = 10_000;
+const DEFAULT_INBOUND_CLIENT_NAME: &str = "in";
 const DEFAULT_OUTBOUND_MAX_IN_FLIGHT: usize = 10_000;
 
 const DEFAULT_DESTINATION_BUFFER_CAPACITY: usize = 100;
@@ -385,6 +520,11 @@ impl Config {
         let inbound_listener_addr = parse(strings, ENV_INBOUND_LISTEN_ADDR, parse_socket_addr);
         let admin_listener_addr = parse(strings, ENV_ADMIN_LISTEN_ADDR, parse_socket_addr);
         let inbound_forward = parse(strings, ENV_INBOUND_FORWARD, parse_socket_addr);
+        let inbound_forward_alternates = parse(
+            strings,
+            ENV_INBOUND_FORWARD_ALTERNATES,
+            parse_socket_addr_list,
+        );
 
         let inbound_dispatch_timeout = parse(strings, ENV_INBOUND_DISPATCH_TIMEOUT, parse_duration);
         let inbound_connect_timeout = parse(strings, ENV_INBOUND_CONNECT_TIMEOUT, parse_duration);
@@ -402,6 +542,8 @@ impl Config {
 
         let inbound_connect_keepalive =
             parse(strings, ENV_INBOUND_CONNECT_KEEPALIVE, parse_duration);
+
+        let inbound_connect_retries = parse(strings, ENV_INBOUND_CONNECT_RETRIES, parse_number);
         let outbound_connect_keepalive =
             parse(strings, ENV_OUTBOUND_CONNECT_KEEPALIVE, parse_duration);
 
@@ -415,6 +557,30 @@ impl Config {
             ENV_OUTBOUND_PORTS_DISABLE_PROTOCOL_DETECTION,
             parse_port_set,
         );
+        let inbound_skip_ports = parse(strings, ENV_INBOUND_PORTS_SKIP, parse_port_set);
+        let inbound_remap_ports = parse(strings, ENV_INBOUND_PORTS_REMAP, parse_port_map);
+        let inbound_authority_addrs =
+            parse(strings, ENV_INBOUND_AUTHORITY_ADDRS, parse_authority_map);
+        let inbound_ports_h2_settings = parse(
+            strings,
+            ENV_INBOUND_PORTS_HTTP2_WINDOW_SIZES,
+            parse_window_size_map,
+        );
+        let inbound_local_identity = parse(strings, ENV_INBOUND_LOCAL_IDENTITY, parse_identity);
+        let inbound_dst_override_enabled = parse(strings, ENV_INBOUND_DST_OVERRIDE_ENABLED, |s| {
+            Ok(!s.is_empty())
+        });
+        let inbound_force_proto_enabled = parse(strings, ENV_INBOUND_FORCE_PROTO_ENABLED, |s| {
+            Ok(!s.is_empty())
+        });
+        let inbound_max_idle_per_endpoint = parse(
+            strings,
+            ENV_INBOUND_MAX_IDLE_CONNECTIONS_PER_ENDPOINT,
+            parse_number,
+        );
+        let inbound_idle_timeout =
+            parse(strings, ENV_INBOUND_CONNECTION_IDLE_TIMEOUT, parse_duration);
+        let inbound_client_name = parse(strings, ENV_INBOUND_CLIENT_NAME, parse_name);
 
         let inbound_router_capacity = parse(strings, ENV_INBOUND_ROUTER_CAPACITY, parse_number);
         let outbound_router_capacity = parse(strings, ENV_OUTBOUND_ROUTER_CAPACITY, parse_number);
@@ -425,7 +591,16 @@ impl Config {
             parse(strings, ENV_OUTBOUND_ROUTER_MAX_IDLE_AGE, parse_duration);
 
         let inbound_max_in_flight = parse(strings, ENV_INBOUND_MAX_IN_FLIGHT, parse_number);
+        let inbound_max_in_flight_per_endpoint = parse(
+            strings,
+            ENV_INBOUND_MAX_IN_FLIGHT_PER_ENDPOINT,
+            parse_number,
+        );
         let outbound_max_in_flight = parse(strings, ENV_OUTBOUND_MAX_IN_FLIGHT, parse_number);
+        let inbound_max_request_header_bytes =
+            parse(strings, ENV_INBOUND_MAX_REQUEST_HEADER_BYTES, parse_number);
+        let proxy_error_header_enabled =
+            parse(strings, ENV_PROXY_ERROR_HEADER_ENABLED, |s| Ok(!s.is_empty()));
 
         let metrics_retain_idle = parse(strings, ENV_METRICS_RETAIN_IDLE, parse_duration);
 
@@ -465,6 +640,11 @@ impl Config {
             parse(strings, ENV_INITIAL_CONNECTION_WINDOW_SIZE, parse_number);
 
         let control_listener = parse_control_listener(strings);
+        let tap_json_log = parse(strings, ENV_TAP_JSON_LOG, parse_log_file);
+        let tap_span_log = parse(strings, ENV_TAP_SPAN_LOG, parse_log_file);
+        let tap_direction = parse(strings, ENV_TAP_DIRECTION, parse_tap_direction);
+        let tap_backpressure_enabled =
+            parse(strings, ENV_TAP_BACKPRESSURE_ENABLED, |s| Ok(!s.is_empty()));
 
         Ok(Config {
             outbound_listener: Listener {
@@ -476,11 +656,16 @@ impl Config {
                     .unwrap_or_else(|| parse_socket_addr(DEFAULT_INBOUND_LISTEN_ADDR).unwrap()),
             },
             control_listener: control_listener?,
+            tap_json_log: tap_json_log?,
+            tap_span_log: tap_span_log?,
+            tap_direction: tap_direction?,
+            tap_backpressure_enabled: tap_backpressure_enabled?.unwrap_or(false),
             admin_listener: Listener {
                 addr: admin_listener_addr?
                     .unwrap_or_else(|| parse_socket_addr(DEFAULT_ADMIN_LISTEN_ADDR).unwrap()),
             },
             inbound_forward: inbound_forward?,
+            inbound_forward_alternates: inbound_forward_alternates?.unwrap_or_default(),
 
             inbound_connect_timeout: inbound_connect_timeout?
                 .unwrap_or(DEFAULT_INBOUND_CONNECT_TIMEOUT),
@@ -501,6 +686,12 @@ impl Config {
                 INBOUND_CONNECT_BASE,
                 DEFAULT_INBOUND_CONNECT_BACKOFF,
             )?,
+            inbound_connect_retries: inbound_connect_retries?.unwrap_or_default(),
+            inbound_connect_retry_backoff: parse_backoff(
+                strings,
+                INBOUND_CONNECT_RETRY_BASE,
+                DEFAULT_INBOUND_CONNECT_RETRY_BACKOFF,
+            )?,
             outbound_connect_backoff: parse_backoff(
                 strings,
                 OUTBOUND_CONNECT_BASE,
@@ -518,6 +709,19 @@ impl Config {
                 .unwrap_or_else(|| default_disable_ports_protocol_detection()),
             outbound_ports_disable_protocol_detection: outbound_disable_ports?
                 .unwrap_or_else(|| default_disable_ports_protocol_detection()),
+            inbound_ports_skip: inbound_skip_ports?.unwrap_or_default(),
+            inbound_ports_remap: inbound_remap_ports?.unwrap_or_default(),
+            inbound_authority_addrs: inbound_authority_addrs?.unwrap_or_default(),
+            inbound_ports_h2_settings: inbound_ports_h2_settings?.unwrap_or_default(),
+            inbound_local_identity: inbound_local_identity?,
+            inbound_dst_override_enabled: inbound_dst_override_enabled?.unwrap_or(false),
+            inbound_force_proto_enabled: inbound_force_proto_enabled?.unwrap_or(false),
+            inbound_h1_settings: H1Settings {
+                max_idle_per_endpoint: inbound_max_idle_per_endpoint?,
+                idle_timeout: inbound_idle_timeout?,
+            },
+            inbound_client_name: inbound_client_name?
+                .unwrap_or_else(|| DEFAULT_INBOUND_CLIENT_NAME.to_owned()),
 
             inbound_router_capacity: inbound_router_capacity?
                 .unwrap_or(DEFAULT_INBOUND_ROUTER_CAPACITY),
@@ -531,8 +735,11 @@ impl Config {
 
             inbound_max_requests_in_flight: inbound_max_in_flight?
                 .unwrap_or(DEFAULT_INBOUND_MAX_IN_FLIGHT),
+            inbound_max_requests_in_flight_per_endpoint: inbound_max_in_flight_per_endpoint?,
             outbound_max_requests_in_flight: outbound_max_in_flight?
                 .unwrap_or(DEFAULT_OUTBOUND_MAX_IN_FLIGHT),
+            inbound_max_request_header_bytes: inbound_max_request_header_bytes?,
+            proxy_error_header_enabled: proxy_error_header_enabled?.unwrap_or(false),
 
             destination_buffer_capacity: DEFAULT_DESTINATION_BUFFER_CAPACITY,
 
@@ -664,6 +871,10 @@ fn parse_socket_addr(s: &str) -> Result<SocketAddr, ParseError> {
     }
 }
 
+fn parse_socket_addr_list(s: &str) -> Result<Vec<SocketAddr>, ParseError> {
+    s.split(',').map(parse_socket_addr).collect()
+}
+
 fn parse_addr(s: &str) -> Result<Addr, ParseError> {
     addr::Addr::from_str(s).map_err(|e| {
         error!("Not a valid address: {}", s);
@@ -679,6 +890,84 @@ fn parse_port_set(s: &str) -> Result<IndexSet<u16>, ParseError> {
     Ok(set)
 }
 
+/// Parses a non-empty name.
+fn parse_name(s: &str) -> Result<String, ParseError> {
+    if s.is_empty() {
+        return Err(ParseError::EmptyName);
+    }
+    Ok(s.to_owned())
+}
+
+/// Opens the named file for appending, creating it if it does not exist.
+fn parse_log_file(s: &str) -> Result<fs::File, ParseError> {
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(s)
+        .map_err(|e| {
+            error!("failed to open {}: {}", s, e);
+            ParseError::NotAWritableFile
+        })
+}
+
+/// Parses a comma-separated list of `from:to` port pairs.
+fn parse_port_map(s: &str) -> Result<IndexMap<u16, u16>, ParseError> {
+    let mut map = IndexMap::new();
+    for pair in s.split(',') {
+        let mut parts = pair.trim().splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(from), Some(to)) => {
+                map.insert(
+                    parse_number::<u16>(from.trim())?,
+                    parse_number::<u16>(to.trim())?,
+                );
+            }
+            _ => return Err(ParseError::NotAPortMapping),
+        }
+    }
+    Ok(map)
+}
+
+/// Parses a comma-separated list of `host=ip:port` pairs.
+fn parse_authority_map(s: &str) -> Result<IndexMap<String, SocketAddr>, ParseError> {
+    let mut map = IndexMap::new();
+    for pair in s.split(',') {
+        let mut parts = pair.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(host), Some(addr)) if !host.trim().is_empty() => {
+                map.insert(host.trim().to_owned(), parse_socket_addr(addr.trim())?);
+            }
+            _ => return Err(ParseError::NotAnAuthorityMapping),
+        }
+    }
+    Ok(map)
+}
+
+/// Parses a comma-separated list of `port=stream:connection` HTTP/2 window
+/// sizes.
+fn parse_window_size_map(s: &str) -> Result<IndexMap<u16, H2Settings>, ParseError> {
+    let mut map = IndexMap::new();
+    for pair in s.split(',') {
+        let mut parts = pair.splitn(2, '=');
+        let (port, windows) = match (parts.next(), parts.next()) {
+            (Some(port), Some(windows)) => (port, windows),
+            _ => return Err(ParseError::NotAWindowSizeMapping),
+        };
+        let mut windows = windows.splitn(2, ':');
+        match (windows.next(), windows.next()) {
+            (Some(stream), Some(connection)) => {
+                let settings = H2Settings {
+                    initial_stream_window_size: Some(parse_number(stream)?),
+                    initial_connection_window_size: Some(parse_number(connection)?),
+                };
+                map.insert(parse_number::<u16>(port)?, settings);
+            }
+            _ => return Err(ParseError::NotAWindowSizeMapping),
+        }
+    }
+    Ok(map)
+}
+
 pub(super) fn parse_identity(s: &str) -> Result<identity::Name, ParseError> {
     identity::Name::from_hostname(s.as_bytes()).map_err(|identity::InvalidName| {
         error!("Not a valid identity name: {}", s);
@@ -729,6 +1018,10 @@ where
     }
 }
 
+fn parse_tap_direction(s: &str) -> Result<tap::DirectionMatch, ParseError> {
+    s.parse().map_err(|_| ParseError::NotADirection)
+}
+
 fn parse_dns_suffixes(list: &str) -> Result<Vec<dns::Suffix>, ParseError> {
     let mut suffixes = Vec::new();
     for item in list.split(',') {
@@ -1031,6 +1324,78 @@ mod tests {
         assert_eq!(parse_duration("1"), Err(ParseError::NotADuration));
     }
 
+    #[test]
+    fn parse_port_map_pairs() {
+        let map = parse_port_map("8080:80,9090:9091").unwrap();
+        assert_eq!(map.get(&8080), Some(&80));
+        assert_eq!(map.get(&9090), Some(&9091));
+        assert_eq!(map.len(), 2);
+
+        let map = parse_port_map("80:8080, 443 : 8443").unwrap();
+        assert_eq!(map.get(&80), Some(&8080));
+        assert_eq!(map.get(&443), Some(&8443));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn parse_port_map_invalid() {
+        assert_eq!(parse_port_map("8080"), Err(ParseError::NotAPortMapping));
+        assert_eq!(parse_port_map("8080:http"), Err(ParseError::NotANumber));
+    }
+
+    #[test]
+    fn parse_authority_map_pairs() {
+        let map = parse_authority_map("a.local=127.0.0.1:8080, b.local=127.0.0.1:8081").unwrap();
+        assert_eq!(map.get("a.local"), Some(&"127.0.0.1:8080".parse().unwrap()));
+        assert_eq!(map.get("b.local"), Some(&"127.0.0.1:8081".parse().unwrap()));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn parse_authority_map_invalid() {
+        assert_eq!(
+            parse_authority_map("a.local"),
+            Err(ParseError::NotAnAuthorityMapping)
+        );
+        assert_eq!(
+            parse_authority_map("=127.0.0.1:8080"),
+            Err(ParseError::NotAnAuthorityMapping)
+        );
+        assert_eq!(
+            parse_authority_map("a.local=b.local:8080"),
+            Err(ParseError::HostIsNotAnIpAddress)
+        );
+    }
+
+    #[test]
+    fn parse_window_size_map_pairs() {
+        let map = parse_window_size_map("8080=1048576:4194304").unwrap();
+        assert_eq!(
+            map.get(&8080),
+            Some(&H2Settings {
+                initial_stream_window_size: Some(1048576),
+                initial_connection_window_size: Some(4194304),
+            })
+        );
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn parse_window_size_map_invalid() {
+        assert_eq!(
+            parse_window_size_map("8080"),
+            Err(ParseError::NotAWindowSizeMapping)
+        );
+        assert_eq!(
+            parse_window_size_map("8080=65535"),
+            Err(ParseError::NotAWindowSizeMapping)
+        );
+        assert_eq!(
+            parse_window_size_map("http=65535:65535"),
+            Err(ParseError::NotANumber)
+        );
+    }
+
     #[test]
     fn dns_suffixes() {
         fn p(s: &str) -> Result<Vec<String>, ParseError> {
@@ -166,6 +166,12 @@ impl Route {
     pub fn labels(&self) -> &Arc<IndexMap<String, String>> {
         self.route.labels()
     }
+
+    /// The name of the destination whose profile configured this route, if
+    /// the route was not for a bare socket address.
+    pub fn profile_name(&self) -> Option<&NameAddr> {
+        self.dst_addr.as_ref().name_addr()
+    }
 }
 
 impl fmt::Display for Route {
@@ -3,36 +3,87 @@
 use futures::{Future, Poll};
 use http::{header, Request, Response, StatusCode};
 
+use proxy::http::{ProxyResponse, L5D_PROXY_ERROR};
 use svc;
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
 /// Layer to map HTTP service errors into appropriate `http::Response`s.
-pub fn layer() -> Layer {
-    Layer
+///
+/// If `describe` is true, each response describes its error in an
+/// `l5d-proxy-error` header.
+pub fn layer(describe: bool) -> Layer {
+    Layer {
+        settings: Settings {
+            describe,
+            connect_timeouts: false,
+        },
+    }
 }
 
 #[derive(Clone, Debug)]
-pub struct Layer;
+pub struct Layer {
+    settings: Settings,
+}
 
 #[derive(Clone, Debug)]
 pub struct Stack<M> {
     inner: M,
+    settings: Settings,
+}
+
+#[derive(Debug)]
+pub struct MakeFuture<F> {
+    inner: F,
+    settings: Settings,
 }
 
 #[derive(Clone, Debug)]
-pub struct Service<S>(S);
+pub struct Service<S> {
+    inner: S,
+    settings: Settings,
+}
 
 #[derive(Debug)]
 pub struct ResponseFuture<F> {
     inner: F,
+    settings: Settings,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Settings {
+    describe: bool,
+    /// Whether connect timeouts are reported as `504 Gateway Timeout` rather
+    /// than `502 Bad Gateway`.
+    connect_timeouts: bool,
+}
+
+// === impl Layer ===
+
+impl Layer {
+    /// Responds to requests that fail because their connection timed out with
+    /// `504 Gateway Timeout`.
+    ///
+    /// This is only correct for stacks whose only timeout is their connect
+    /// timeout, i.e. the inbound proxy's.
+    pub fn with_connect_timeouts(self) -> Self {
+        Self {
+            settings: Settings {
+                connect_timeouts: true,
+                ..self.settings
+            },
+        }
+    }
 }
 
 impl<M> svc::Layer<M> for Layer {
     type Service = Stack<M>;
 
     fn layer(&self, inner: M) -> Self::Service {
-        Stack { inner }
+        Stack {
+            inner,
+            settings: self.settings,
+        }
     }
 }
 
@@ -42,13 +93,30 @@ where
 {
     type Response = Service<M::Response>;
     type Error = M::Error;
-    type Future = futures::future::Map<M::Future, fn(M::Response) -> Self::Response>;
+    type Future = MakeFuture<M::Future>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
         self.inner.poll_ready()
     }
     fn call(&mut self, target: T) -> Self::Future {
-        self.inner.call(target).map(Service)
+        MakeFuture {
+            inner: self.inner.call(target),
+            settings: self.settings,
+        }
+    }
+}
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = Service<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Service {
+            inner,
+            settings: self.settings,
+        }
+        .into())
     }
 }
 
@@ -63,12 +131,15 @@ where
     type Future = ResponseFuture<S::Future>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
-        self.0.poll_ready().map_err(Into::into)
+        self.inner.poll_ready().map_err(Into::into)
     }
 
     fn call(&mut self, req: Request<B1>) -> Self::Future {
-        let inner = self.0.call(req);
-        ResponseFuture { inner }
+        let inner = self.inner.call(req);
+        ResponseFuture {
+            inner,
+            settings: self.settings,
+        }
     }
 }
 
@@ -85,11 +156,17 @@ where
         match self.inner.poll() {
             Ok(ok) => Ok(ok),
             Err(err) => {
-                let response = Response::builder()
-                    .status(map_err_to_5xx(err.into()))
+                let (status, reason) = map_err_to_5xx(err.into(), self.settings);
+                let mut builder = Response::builder();
+                builder.status(status);
+                if self.settings.describe {
+                    builder.header(L5D_PROXY_ERROR, reason);
+                }
+                let mut response = builder
                     .header(header::CONTENT_LENGTH, "0")
                     .body(B::default())
                     .expect("app::errors response is valid");
+                ProxyResponse::mark(&mut response);
 
                 Ok(response.into())
             }
@@ -97,26 +174,169 @@ where
     }
 }
 
-fn map_err_to_5xx(e: Error) -> StatusCode {
+fn map_err_to_5xx(e: Error, settings: Settings) -> (StatusCode, &'static str) {
     use proxy::buffer;
+    use proxy::http::header_limit::HeadersTooLarge;
     use proxy::http::router::error as router;
     use tower::load_shed::error as shed;
+    use tower::timeout::error::Elapsed;
 
     if let Some(ref c) = e.downcast_ref::<router::NoCapacity>() {
         warn!("router at capacity ({})", c.0);
-        http::StatusCode::SERVICE_UNAVAILABLE
+        (http::StatusCode::SERVICE_UNAVAILABLE, "router at capacity")
     } else if let Some(_) = e.downcast_ref::<shed::Overloaded>() {
         warn!("server overloaded, max-in-flight reached");
-        http::StatusCode::SERVICE_UNAVAILABLE
+        (http::StatusCode::SERVICE_UNAVAILABLE, "proxy overloaded")
     } else if let Some(_) = e.downcast_ref::<buffer::Aborted>() {
         warn!("request aborted because it reached the configured dispatch deadline");
-        http::StatusCode::SERVICE_UNAVAILABLE
+        (
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            "dispatch deadline exceeded",
+        )
+    } else if let Some(h) = e.downcast_ref::<HeadersTooLarge>() {
+        debug!("request rejected: {}", h);
+        (
+            http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            "request headers too large",
+        )
+    } else if settings.connect_timeouts && find_source::<Elapsed>(&*e).is_some() {
+        warn!("request failed: connection timed out");
+        (http::StatusCode::GATEWAY_TIMEOUT, "connection timed out")
     } else if let Some(_) = e.downcast_ref::<router::NotRecognized>() {
         error!("could not recognize request");
-        http::StatusCode::BAD_GATEWAY
+        (
+            http::StatusCode::BAD_GATEWAY,
+            "no destination could be determined for the request",
+        )
     } else {
         // we probably should have handled this before?
         error!("unexpected error: {}", e);
-        http::StatusCode::BAD_GATEWAY
+        (http::StatusCode::BAD_GATEWAY, "unexpected error")
+    }
+}
+
+/// Finds an error of type `E` in `e`'s chain of sources.
+///
+/// Errors such as connect timeouts are not returned directly, but are wrapped
+/// by the clients that encounter them.
+fn find_source<'a, E>(e: &'a (dyn std::error::Error + 'static)) -> Option<&'a E>
+where
+    E: std::error::Error + 'static,
+{
+    let mut source = Some(e);
+    while let Some(err) = source {
+        if let Some(e) = err.downcast_ref::<E>() {
+            return Some(e);
+        }
+        source = err.source();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use std::time::Duration;
+    use std::{error, fmt};
+    use svc::Service as _Service;
+    use tokio::runtime::current_thread::Runtime;
+    use tower::timeout::Timeout;
+
+    use super::*;
+
+    /// Wraps an error as its source, as e.g. the HTTP/1 client does with the
+    /// errors of the connect stack.
+    #[derive(Debug)]
+    struct Wrapped(Error);
+
+    impl fmt::Display for Wrapped {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "wrapped: {}", self.0)
+        }
+    }
+
+    impl error::Error for Wrapped {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            Some(&*self.0)
+        }
+    }
+
+    /// Fails with the error that a connect stack's timeout produces when a
+    /// connection is not established in time.
+    fn connect_timeout(rt: &mut Runtime) -> Error {
+        let hang = svc::mk(|_: ()| future::empty::<(), Error>());
+        let mut connect = Timeout::new(hang, Duration::from_millis(1));
+        rt.block_on(connect.call(()))
+            .expect_err("connect must time out")
+    }
+
+    /// Fails the request that it is called with.
+    struct Fails(Option<Error>);
+
+    impl svc::Service<Request<()>> for Fails {
+        type Response = Response<()>;
+        type Error = Error;
+        type Future = future::FutureResult<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, _: Request<()>) -> Self::Future {
+            future::err(self.0.take().expect("called once"))
+        }
+    }
+
+    /// Responds as the errors layer does when the inner service fails with
+    /// `err`.
+    fn respond(rt: &mut Runtime, err: Error, layer: Layer) -> Response<()> {
+        let mut svc = Service {
+            inner: Fails(Some(err)),
+            settings: layer.settings,
+        };
+        rt.block_on(svc.call(Request::new(())))
+            .expect("errors must be responses")
+    }
+
+    #[test]
+    fn inbound_connect_timeout_is_gateway_timeout() {
+        // The client reports the connect stack's timeout as the source of
+        // its own error.
+        let mut rt = Runtime::new().unwrap();
+        let err = Wrapped(connect_timeout(&mut rt)).into();
+        let rsp = respond(&mut rt, err, layer(false).with_connect_timeouts());
+        assert_eq!(rsp.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[test]
+    fn outbound_connect_timeout_is_bad_gateway() {
+        let mut rt = Runtime::new().unwrap();
+        let err = Wrapped(connect_timeout(&mut rt)).into();
+        let rsp = respond(&mut rt, err, layer(false));
+        assert_eq!(rsp.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn other_errors_are_bad_gateway() {
+        let mut rt = Runtime::new().unwrap();
+        let err = Wrapped("connection refused".into()).into();
+        let rsp = respond(&mut rt, err, layer(false).with_connect_timeouts());
+        assert_eq!(rsp.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn describes_errors_only_when_enabled() {
+        let mut rt = Runtime::new().unwrap();
+
+        let err = connect_timeout(&mut rt);
+        let rsp = respond(&mut rt, err, layer(false).with_connect_timeouts());
+        assert!(rsp.headers().get(L5D_PROXY_ERROR).is_none());
+
+        let err = connect_timeout(&mut rt);
+        let rsp = respond(&mut rt, err, layer(true).with_connect_timeouts());
+        assert_eq!(
+            rsp.headers().get(L5D_PROXY_ERROR).unwrap(),
+            "connection timed out"
+        );
     }
 }
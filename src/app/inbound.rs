@@ -1,44 +1,137 @@
 use http;
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use std::fmt;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use super::classify;
+use super::config::H2Settings;
 use super::dst::DstAddr;
 use super::identity;
-use proxy::http::{router, settings};
+use super::{classify, L5D_FORCE_PROTO};
+use metrics::{Counter, FmtMetrics};
+use proxy::http::{orig_proto, router, settings};
 use proxy::server::Source;
 use tap;
 use transport::{connect, tls};
 use {Conditional, NameAddr};
 
+metrics! {
+    inbound_default_forward_total: Counter {
+        "Total count of inbound requests routed to the default forwarding address"
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Endpoint {
     pub addr: SocketAddr,
     pub dst_name: Option<NameAddr>,
     pub http_settings: settings::Settings,
+    /// Overrides the proxy's default HTTP/2 client settings, so that e.g.
+    /// large-streaming applications may be given larger flow control windows.
+    pub h2_settings: Option<H2Settings>,
     pub tls_client_id: tls::PeerIdentity,
+    /// The identity of the local application, when connections to it are
+    /// secured with TLS.
+    pub tls_server_id: tls::PeerIdentity,
+    /// Describes how the endpoint was recognized from the request's `Source`.
+    ///
+    /// This is included in the endpoint's equality so that each endpoint
+    /// stack reports accurate metadata. Because it can only take a few
+    /// values, this doesn't meaningfully fragment the router's cache.
+    pub source_meta: SourceMeta,
+}
+
+/// Metadata about the `Source` of requests to an inbound `Endpoint`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SourceMeta {
+    /// True when the endpoint was determined by the connection's original
+    /// destination address, rather than the default forwarding address.
+    pub orig_dst: bool,
+    /// True when the source connection was secured with mutual TLS.
+    pub tls: bool,
+    /// True when the endpoint's address was named by the request's
+    /// `l5d-dst-override` header, so that it is connected to as-is.
+    pub dst_override: bool,
+}
+
+/// The address named by a request's `l5d-dst-override` header.
+///
+/// The header is stripped before requests are tapped or routed, so its value
+/// is carried to `RecognizeEndpoint` as a request extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DstOverride(pub SocketAddr);
+
+/// The protocol named by a request's `l5d-force-proto` header.
+///
+/// Like `DstOverride`, this is carried to `RecognizeEndpoint` as a request
+/// extension once the header has been stripped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ForceProto {
+    Http1,
+    Http2,
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct RecognizeEndpoint {
-    default_addr: Option<SocketAddr>,
+    /// Candidate default forwarding addresses, in order of preference.
+    default_addrs: Arc<Vec<SocketAddr>>,
+    /// Addresses of local applications, by the lowercase host of the
+    /// requests that they serve.
+    authority_addrs: Arc<IndexMap<String, SocketAddr>>,
+    skip_ports: Arc<IndexSet<u16>>,
+    port_remap: Arc<IndexMap<u16, u16>>,
+    /// HTTP/2 client settings, by the port on which the local application
+    /// is served.
+    h2_settings: Arc<IndexMap<u16, H2Settings>>,
+    local_identity: Option<identity::Name>,
+    dst_override: bool,
+    force_proto: bool,
+    default_forwards: DefaultForwardMetrics,
 }
 
+/// Describes the requests of an inbound connection that are rejected before an
+/// `Endpoint` is recognized, so that they may be tapped.
+#[derive(Clone, Debug)]
+pub struct RejectedSource(Source);
+
+/// Counts the requests that are routed to the default forwarding address
+/// because they have no original destination address, so that a
+/// misconfigured traffic capture can be detected.
+#[derive(Clone, Debug, Default)]
+pub struct DefaultForwardMetrics(Arc<AtomicUsize>);
+
 // === impl Endpoint ===
 
-impl From<SocketAddr> for Endpoint {
-    fn from(addr: SocketAddr) -> Self {
+impl Endpoint {
+    /// Builds an endpoint for the local application at `addr`, e.g. so that
+    /// requests may be routed to it without being recognized from a `Source`.
+    ///
+    /// The endpoint has no destination name or client identity, and its
+    /// connections are not secured with TLS.
+    pub fn new(addr: SocketAddr, http_settings: settings::Settings) -> Self {
+        let no_client_id = match http_settings {
+            settings::Settings::NotHttp => tls::ReasonForNoPeerName::NotHttp,
+            _ => tls::ReasonForNoPeerName::NotProvidedByRemote,
+        };
         Self {
             addr,
             dst_name: None,
-            http_settings: settings::Settings::NotHttp,
-            tls_client_id: Conditional::None(tls::ReasonForNoPeerName::NotHttp.into()),
+            http_settings,
+            h2_settings: None,
+            tls_client_id: Conditional::None(no_client_id.into()),
+            tls_server_id: Conditional::None(tls::ReasonForNoPeerName::Loopback.into()),
+            source_meta: SourceMeta::default(),
         }
     }
 }
 
+impl From<SocketAddr> for Endpoint {
+    fn from(addr: SocketAddr) -> Self {
+        Self::new(addr, settings::Settings::NotHttp)
+    }
+}
+
 impl connect::HasPeerAddr for Endpoint {
     fn peer_addr(&self) -> SocketAddr {
         self.addr
@@ -47,7 +140,7 @@ impl connect::HasPeerAddr for Endpoint {
 
 impl tls::HasPeerIdentity for Endpoint {
     fn peer_identity(&self) -> tls::PeerIdentity {
-        Conditional::None(tls::ReasonForNoPeerName::Loopback.into())
+        self.tls_server_id.clone()
     }
 }
 
@@ -55,6 +148,10 @@ impl settings::HasSettings for Endpoint {
     fn http_settings(&self) -> &settings::Settings {
         &self.http_settings
     }
+
+    fn h2_settings(&self) -> Option<H2Settings> {
+        self.h2_settings
+    }
 }
 
 impl classify::CanClassify for Endpoint {
@@ -92,7 +189,7 @@ impl tap::Inspect for Endpoint {
         &self,
         _: &http::Request<B>,
     ) -> Conditional<&identity::Name, tls::ReasonForNoIdentity> {
-        Conditional::None(tls::ReasonForNoPeerName::Loopback.into())
+        self.tls_server_id.as_ref()
     }
 
     fn route_labels<B>(&self, req: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
@@ -101,14 +198,86 @@ impl tap::Inspect for Endpoint {
             .map(|r| r.labels().clone())
     }
 
+    fn protocol<B>(&self, _: &http::Request<B>) -> Option<settings::Settings> {
+        Some(self.http_settings)
+    }
+
+    /// Requests are downgraded before they are routed, so the endpoint
+    /// describes the original protocol.
+    fn is_orig_proto<B>(&self, req: &http::Request<B>) -> bool {
+        orig_proto::Downgraded::is_marked(req)
+    }
+
+    fn profile_name<B>(&self, req: &http::Request<B>) -> Option<String> {
+        req.extensions()
+            .get::<super::dst::Route>()
+            .and_then(|r| r.profile_name())
+            .map(|n| n.to_string())
+    }
+
     fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
         false
     }
 }
 
+/// Renders the endpoint as `addr/settings`, e.g. `127.0.0.1:8080/h2`.
+///
+/// Settings are omitted for non-HTTP endpoints, which are rendered as only
+/// their address.
 impl fmt::Display for Endpoint {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.addr.fmt(f)
+        match self.http_settings {
+            settings::Settings::NotHttp => self.addr.fmt(f),
+            ref s => write!(f, "{}/{}", self.addr, s),
+        }
+    }
+}
+
+// === impl RejectedSource ===
+
+impl RejectedSource {
+    pub fn new(source: &Source) -> Self {
+        RejectedSource(source.clone())
+    }
+}
+
+impl tap::Inspect for RejectedSource {
+    fn src_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+        Some(self.0.remote)
+    }
+
+    /// Rejected requests carry their `RejectedSource`.
+    fn src_tls<'a, B>(
+        &self,
+        req: &'a http::Request<B>,
+    ) -> Conditional<&'a identity::Name, tls::ReasonForNoIdentity> {
+        req.extensions()
+            .get::<RejectedSource>()
+            .map(|s| s.0.tls_peer.as_ref())
+            .unwrap_or_else(|| Conditional::None(tls::ReasonForNoIdentity::Disabled))
+    }
+
+    fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+        self.0.orig_dst_if_not_local()
+    }
+
+    fn dst_labels<B>(&self, _: &http::Request<B>) -> Option<&IndexMap<String, String>> {
+        None
+    }
+
+    fn dst_tls<B>(
+        &self,
+        _: &http::Request<B>,
+    ) -> Conditional<&identity::Name, tls::ReasonForNoIdentity> {
+        Conditional::None(tls::ReasonForNoPeerName::Loopback.into())
+    }
+
+    fn route_labels<B>(&self, _: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
+        None
+    }
+
+    fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
+        false
     }
 }
 
@@ -116,7 +285,144 @@ impl fmt::Display for Endpoint {
 
 impl RecognizeEndpoint {
     pub fn new(default_addr: Option<SocketAddr>) -> Self {
-        Self { default_addr }
+        Self {
+            default_addrs: Arc::new(default_addr.into_iter().collect()),
+            authority_addrs: Arc::new(IndexMap::new()),
+            skip_ports: Arc::new(IndexSet::new()),
+            port_remap: Arc::new(IndexMap::new()),
+            h2_settings: Arc::new(IndexMap::new()),
+            local_identity: None,
+            dst_override: false,
+            force_proto: false,
+            default_forwards: DefaultForwardMetrics::default(),
+        }
+    }
+
+    /// Requests whose original destination port is in `skip_ports` are not
+    /// recognized, and so are not routed.
+    pub fn with_skip_ports(self, skip_ports: IndexSet<u16>) -> Self {
+        Self {
+            skip_ports: Arc::new(skip_ports),
+            ..self
+        }
+    }
+
+    /// Replaces the default forwarding address with an ordered list of
+    /// candidates, e.g. for an application that listens on both IPv4 and IPv6.
+    ///
+    /// Requests without an original destination are forwarded to the first
+    /// candidate in the same address family as the request's source, or else
+    /// to the first candidate.
+    pub fn with_default_addrs(self, default_addrs: Vec<SocketAddr>) -> Self {
+        Self {
+            default_addrs: Arc::new(default_addrs),
+            ..self
+        }
+    }
+
+    /// Routes requests by their authority's host, so that a proxy may front
+    /// several local applications that share an original destination.
+    ///
+    /// Requests whose host is not in the table are routed to their original
+    /// destination (or a default address) as usual.
+    pub fn with_authority_addrs(self, authority_addrs: IndexMap<String, SocketAddr>) -> Self {
+        let authority_addrs = authority_addrs
+            .into_iter()
+            .map(|(host, addr)| (host.to_ascii_lowercase(), addr))
+            .collect();
+        Self {
+            authority_addrs: Arc::new(authority_addrs),
+            ..self
+        }
+    }
+
+    /// When set, connections to the local application are secured with TLS,
+    /// expecting the application to have the given identity. Otherwise,
+    /// connections are in plaintext.
+    pub fn with_local_identity(self, local_identity: Option<identity::Name>) -> Self {
+        Self {
+            local_identity,
+            ..self
+        }
+    }
+
+    /// When enabled, a request's `l5d-dst-override` header, if it contains a
+    /// valid socket address, is used instead of the original destination.
+    /// Unlike other addresses, the override is not rewritten to the loopback
+    /// address, so that requests may be routed beyond the local application.
+    ///
+    /// This lets clients route to arbitrary addresses, so it should only be
+    /// enabled when all inbound clients are trusted.
+    pub fn with_dst_override(self, dst_override: bool) -> Self {
+        Self {
+            dst_override,
+            ..self
+        }
+    }
+
+    /// When enabled, a request's `l5d-force-proto` header chooses the protocol
+    /// used to reach the local application, e.g. when debugging.
+    ///
+    /// This lets clients choose how the application is reached, so it should
+    /// only be enabled when all inbound clients are trusted.
+    pub fn with_force_proto(self, force_proto: bool) -> Self {
+        Self {
+            force_proto,
+            ..self
+        }
+    }
+
+    pub fn with_default_forward_metrics(self, default_forwards: DefaultForwardMetrics) -> Self {
+        Self {
+            default_forwards,
+            ..self
+        }
+    }
+
+    /// Rewrites the port of recognized endpoints according to `port_remap`,
+    /// so that traffic to one port may be served by the application on
+    /// another. Ports that are not in the table are left unchanged.
+    pub fn with_port_remap(self, port_remap: IndexMap<u16, u16>) -> Self {
+        Self {
+            port_remap: Arc::new(port_remap),
+            ..self
+        }
+    }
+
+    /// Configures HTTP/2 clients by the (remapped) port of the local
+    /// application. Ports that are not in the table use the proxy's default
+    /// HTTP/2 settings.
+    pub fn with_h2_settings(self, h2_settings: IndexMap<u16, H2Settings>) -> Self {
+        Self {
+            h2_settings: Arc::new(h2_settings),
+            ..self
+        }
+    }
+
+    fn authority_addr<A>(&self, req: &http::Request<A>) -> Option<SocketAddr> {
+        if self.authority_addrs.is_empty() {
+            return None;
+        }
+
+        let host = match req.uri().authority_part() {
+            Some(a) => a.host().to_ascii_lowercase(),
+            None => req
+                .headers()
+                .get(http::header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.parse::<http::uri::Authority>().ok())
+                .map(|a| a.host().to_ascii_lowercase())?,
+        };
+        self.authority_addrs.get(&host).cloned()
+    }
+
+    fn default_addr(&self, src: Option<&Source>) -> Option<SocketAddr> {
+        let src_is_ipv4 = src.map(|s| Source::unmapped_ip(s.remote.ip()).is_ipv4());
+        self.default_addrs
+            .iter()
+            .find(|a| Some(a.is_ipv4()) == src_is_ipv4)
+            .or_else(|| self.default_addrs.first())
+            .cloned()
     }
 }
 
@@ -126,21 +432,72 @@ impl<A> router::Recognize<http::Request<A>> for RecognizeEndpoint {
     fn recognize(&self, req: &http::Request<A>) -> Option<Self::Target> {
         let src = req.extensions().get::<Source>();
         debug!("inbound endpoint: src={:?}", src);
-        let addr = src
-            .and_then(Source::orig_dst_if_not_local)
-            .or(self.default_addr)?;
+        let dst_override = if self.dst_override {
+            req.extensions().get::<DstOverride>().map(|o| o.0)
+        } else {
+            None
+        };
+        let authority_addr = if dst_override.is_none() {
+            self.authority_addr(req)
+        } else {
+            None
+        };
+        let orig_dst = src.and_then(Source::orig_dst_if_not_local);
+        let mut addr = dst_override
+            .or(authority_addr)
+            .or(orig_dst)
+            .or_else(|| self.default_addr(src))?;
+        let is_default = dst_override.is_none() && authority_addr.is_none() && orig_dst.is_none();
+
+        if self.skip_ports.contains(&addr.port()) {
+            debug!("inbound endpoint: skipping port {}", addr.port());
+            return None;
+        }
+
+        if is_default {
+            self.default_forwards.0.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Some(&port) = self.port_remap.get(&addr.port()) {
+            debug!(
+                "inbound endpoint: remapping port {} to {}",
+                addr.port(),
+                port
+            );
+            addr.set_port(port);
+        }
 
         let tls_client_id = src
             .map(|s| s.tls_peer.clone())
             .unwrap_or_else(|| Conditional::None(tls::ReasonForNoIdentity::Disabled));
 
+        let tls_server_id = match self.local_identity {
+            Some(ref id) => Conditional::Some(id.clone()),
+            None => Conditional::None(tls::ReasonForNoPeerName::Loopback.into()),
+        };
+
+        let source_meta = SourceMeta {
+            orig_dst: dst_override.is_none() && authority_addr.is_none() && orig_dst.is_some(),
+            tls: tls_client_id.is_some(),
+            dst_override: dst_override.is_some(),
+        };
+
         let dst_addr = req
             .extensions()
             .get::<DstAddr>()
             .expect("request extensions should have DstAddr");
 
         let dst_name = dst_addr.as_ref().name_addr().cloned();
-        let http_settings = dst_addr.http_settings;
+        let forced_http_settings = if self.force_proto {
+            forced_http_settings(req)
+        } else {
+            None
+        };
+        let http_settings = forced_http_settings.unwrap_or_else(|| dst_addr.http_settings);
+        let h2_settings = match http_settings {
+            settings::Settings::Http2 => self.h2_settings.get(&addr.port()).cloned(),
+            _ => None,
+        };
 
         debug!(
             "inbound endpoint: dst={:?}, proto={:?}",
@@ -151,11 +508,72 @@ impl<A> router::Recognize<http::Request<A>> for RecognizeEndpoint {
             addr,
             dst_name,
             http_settings,
+            h2_settings,
             tls_client_id,
+            tls_server_id,
+            source_meta,
         })
     }
 }
 
+// === impl DstOverride ===
+
+impl DstOverride {
+    /// Parses a `l5d-dst-override` header value, ignoring values that are not
+    /// socket addresses.
+    pub fn from_header(value: &http::header::HeaderValue) -> Option<Self> {
+        let addr = value.to_str().ok()?.parse().ok()?;
+        Some(DstOverride(addr))
+    }
+}
+
+// === impl ForceProto ===
+
+impl ForceProto {
+    /// Parses a `l5d-force-proto` header value, ignoring unknown protocols.
+    pub fn from_header(value: &http::header::HeaderValue) -> Option<Self> {
+        if value == "HTTP/2" {
+            return Some(ForceProto::Http2);
+        }
+        if value == "HTTP/1.1" {
+            return Some(ForceProto::Http1);
+        }
+        debug!("ignoring invalid {} header: {:?}", L5D_FORCE_PROTO, value);
+        None
+    }
+}
+
+// === impl DefaultForwardMetrics ===
+
+impl FmtMetrics for DefaultForwardMetrics {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.0.load(Ordering::Relaxed) as u64;
+        inbound_default_forward_total.fmt_help(f)?;
+        inbound_default_forward_total.fmt_metric(f, Counter::from(count))?;
+        Ok(())
+    }
+}
+
+/// Determines HTTP settings from a request's `ForceProto` extension, so that
+/// the protocol used to reach the local application may be chosen explicitly
+/// when debugging.
+///
+/// Returns `None` if the request's `l5d-force-proto` header was absent or had
+/// an unknown value.
+fn forced_http_settings<B>(req: &http::Request<B>) -> Option<settings::Settings> {
+    match req.extensions().get::<ForceProto>()? {
+        ForceProto::Http2 => Some(settings::Settings::Http2),
+        ForceProto::Http1 => match settings::Settings::from_request(req) {
+            s @ settings::Settings::Http1 { .. } => Some(s),
+            _ => Some(settings::Settings::Http1 {
+                keep_alive: true,
+                wants_h1_upgrade: false,
+                was_absolute_form: false,
+            }),
+        },
+    }
+}
+
 pub mod orig_proto_downgrade {
     use futures::{Future, Poll};
     use http;
@@ -235,19 +653,36 @@ pub mod orig_proto_downgrade {
     }
 }
 
-/// Rewrites connect `SocketAddr`s IP address to the loopback address (`127.0.0.1`),
-/// with the same port still set.
+/// Rewrites connect `SocketAddr`s IP address to the loopback address of the
+/// same family (`127.0.0.1` or `::1`), with the same port still set.
+///
+/// Addresses named by a request's `l5d-dst-override` header are not rewritten.
 pub mod rewrite_loopback_addr {
     use super::Endpoint;
-    use std::net::SocketAddr;
+    use proxy::server::Source;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
     use svc::stack::map_target;
 
     pub fn layer() -> map_target::Layer<impl Fn(Endpoint) -> Endpoint + Copy> {
-        map_target::layer(|mut ep: Endpoint| {
-            debug!("rewriting inbound address to loopback; addr={:?}", ep.addr);
-            ep.addr = SocketAddr::from(([127, 0, 0, 1], ep.addr.port()));
-            ep
-        })
+        map_target::layer(rewrite)
+    }
+
+    pub(super) fn rewrite(mut ep: Endpoint) -> Endpoint {
+        if ep.source_meta.dst_override {
+            debug!(
+                "not rewriting overridden inbound address; addr={:?}",
+                ep.addr
+            );
+            return ep;
+        }
+
+        debug!("rewriting inbound address to loopback; addr={:?}", ep.addr);
+        let loopback: IpAddr = match Source::unmapped_ip(ep.addr.ip()) {
+            IpAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            IpAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        };
+        ep.addr = SocketAddr::new(loopback, ep.addr.port());
+        ep
     }
 }
 
@@ -305,21 +740,33 @@ pub mod set_remote_ip_on_req {
 #[cfg(test)]
 mod tests {
     use http;
+    use indexmap::IndexMap;
     use std::net;
 
-    use super::{Endpoint, RecognizeEndpoint};
+    use super::{
+        rewrite_loopback_addr, DefaultForwardMetrics, DstOverride, Endpoint, ForceProto,
+        RecognizeEndpoint, SourceMeta,
+    };
+    use app::config::H2Settings;
     use proxy::http::{router::Recognize, Settings};
     use proxy::server::Source;
     use transport::tls;
     use Conditional;
 
     fn make_test_endpoint(addr: net::SocketAddr) -> Endpoint {
-        let tls_client_id = TLS_DISABLED;
         Endpoint {
-            addr,
-            dst_name: None,
-            http_settings: Settings::Http2,
-            tls_client_id,
+            tls_client_id: TLS_DISABLED,
+            ..Endpoint::new(addr, Settings::Http2)
+        }
+    }
+
+    fn make_orig_dst_endpoint(addr: net::SocketAddr) -> Endpoint {
+        Endpoint {
+            source_meta: SourceMeta {
+                orig_dst: true,
+                ..SourceMeta::default()
+            },
+            ..make_test_endpoint(addr)
         }
     }
 
@@ -332,6 +779,140 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn builds_endpoints_directly() {
+        let addr = ([127, 0, 0, 1], 8080).into();
+
+        let tcp = Endpoint::new(addr, Settings::NotHttp);
+        assert_eq!(tcp, Endpoint::from(addr));
+        assert_eq!(tcp.to_string(), "127.0.0.1:8080");
+
+        let h2 = Endpoint::new(addr, Settings::Http2);
+        assert_ne!(h2, tcp);
+        assert_eq!(h2, Endpoint::new(addr, Settings::Http2));
+        assert_eq!(
+            h2.to_string(),
+            format!("127.0.0.1:8080/{}", Settings::Http2)
+        );
+    }
+
+    #[test]
+    fn describes_orig_proto_downgrades() {
+        use proxy::http::orig_proto::Downgraded;
+        use tap::Inspect;
+
+        let ep = Endpoint {
+            http_settings: Settings::Http1 {
+                keep_alive: true,
+                wants_h1_upgrade: false,
+                was_absolute_form: false,
+            },
+            ..make_test_endpoint(([127, 0, 0, 1], 8080).into())
+        };
+        let mut req = http::Request::new(());
+        assert_eq!(ep.protocol(&req), Some(ep.http_settings));
+        assert!(!ep.is_orig_proto(&req));
+
+        Downgraded::mark(&mut req);
+        assert!(ep.is_orig_proto(&req));
+    }
+
+    #[test]
+    fn names_the_profile_that_routed_a_request() {
+        use app::dst::{DstAddr, Route};
+        use proxy::http::profiles;
+        use tap::Inspect;
+        use Addr;
+
+        let ep = make_test_endpoint(([127, 0, 0, 1], 8080).into());
+        let mut req = http::Request::new(());
+        assert_eq!(ep.profile_name(&req), None);
+
+        let name = "web.ns.svc.cluster.local:8080";
+        req.extensions_mut().insert(Route {
+            dst_addr: DstAddr::inbound(
+                Addr::from_str(name).expect("name must parse"),
+                Settings::Http2,
+            ),
+            route: profiles::Route::default(),
+        });
+        assert_eq!(ep.profile_name(&req), Some(name.to_owned()));
+    }
+
+    #[test]
+    fn counts_default_forwards() {
+        use std::sync::atomic::Ordering;
+
+        let default = net::SocketAddr::from(([127, 0, 0, 1], 8080));
+        let metrics = DefaultForwardMetrics::default();
+        let rec =
+            RecognizeEndpoint::new(Some(default)).with_default_forward_metrics(metrics.clone());
+
+        let mut req = http::Request::new(());
+        dst_addr(&mut req);
+        assert!(rec.recognize(&req).is_some());
+        assert!(rec.recognize(&req).is_some());
+        assert_eq!(metrics.0.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn rewrites_to_loopback_in_the_same_family() {
+        let ep = rewrite_loopback_addr::rewrite(make_test_endpoint(([10, 1, 2, 3], 8080).into()));
+        assert_eq!(ep.addr, net::SocketAddr::from(([127, 0, 0, 1], 8080)));
+
+        let ipv6 = net::Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1);
+        let ep = rewrite_loopback_addr::rewrite(make_test_endpoint((ipv6, 8080).into()));
+        assert_eq!(
+            ep.addr,
+            net::SocketAddr::from((net::Ipv6Addr::LOCALHOST, 8080))
+        );
+
+        let mapped = net::Ipv4Addr::new(10, 1, 2, 3).to_ipv6_mapped();
+        let ep = rewrite_loopback_addr::rewrite(make_test_endpoint((mapped, 8080).into()));
+        assert_eq!(ep.addr, net::SocketAddr::from(([127, 0, 0, 1], 8080)));
+
+        let overridden = Endpoint {
+            source_meta: SourceMeta {
+                dst_override: true,
+                ..SourceMeta::default()
+            },
+            ..make_test_endpoint(([10, 1, 2, 3], 8080).into())
+        };
+        let ep = rewrite_loopback_addr::rewrite(overridden);
+        assert_eq!(ep.addr, net::SocketAddr::from(([10, 1, 2, 3], 8080)));
+    }
+
+    #[test]
+    fn remaps_ports_to_the_loopback_addr_of_the_orig_dst_family() {
+        let remote = net::SocketAddr::from(([10, 0, 0, 2], 40000));
+        let local = net::SocketAddr::from(([10, 0, 0, 1], 4143));
+        let ipv6 = net::Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1);
+        let cases = vec![
+            (
+                net::SocketAddr::from(([10, 0, 0, 3], 8080)),
+                net::SocketAddr::from(([127, 0, 0, 1], 9090)),
+            ),
+            (
+                net::SocketAddr::from((ipv6, 8080)),
+                net::SocketAddr::from((net::Ipv6Addr::LOCALHOST, 9090)),
+            ),
+        ];
+
+        let mut remap = IndexMap::new();
+        remap.insert(8080, 9090);
+        let rec = RecognizeEndpoint::default().with_port_remap(remap);
+
+        for (orig_dst, expected) in cases {
+            let src = Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED);
+            let mut req = http::Request::new(());
+            req.extensions_mut().insert(src);
+            dst_addr(&mut req);
+
+            let ep = rec.recognize(&req).expect("must be recognized");
+            assert_eq!(rewrite_loopback_addr::rewrite(ep).addr, expected);
+        }
+    }
+
     const TLS_DISABLED: tls::PeerIdentity = Conditional::None(tls::ReasonForNoIdentity::Disabled);
 
     quickcheck! {
@@ -341,7 +922,7 @@ mod tests {
             remote: net::SocketAddr
         ) -> bool {
             let src = Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED);
-            let rec = src.orig_dst_if_not_local().map(make_test_endpoint);
+            let rec = src.orig_dst_if_not_local().map(make_orig_dst_endpoint);
 
             let mut req = http::Request::new(());
             req.extensions_mut().insert(src);
@@ -381,5 +962,301 @@ mod tests {
 
             RecognizeEndpoint::new(default).recognize(&req) == default.map(make_test_endpoint)
         }
+
+        fn recognize_default_no_loop_ipv6(
+            default: Option<net::SocketAddr>,
+            local: net::SocketAddrV6,
+            remote: net::SocketAddr
+        ) -> bool {
+            let local = net::SocketAddr::V6(local);
+            let mut req = http::Request::new(());
+            req.extensions_mut()
+                .insert(Source::for_test(remote, local, Some(local), TLS_DISABLED));
+            dst_addr(&mut req);
+
+            RecognizeEndpoint::new(default).recognize(&req) == default.map(make_test_endpoint)
+        }
+
+        fn recognize_default_no_loop_ipv4_mapped(
+            default: Option<net::SocketAddr>,
+            local: net::SocketAddrV4,
+            remote: net::SocketAddr
+        ) -> bool {
+            let mapped = net::SocketAddr::new(local.ip().to_ipv6_mapped().into(), local.port());
+            let mut req = http::Request::new(());
+            req.extensions_mut().insert(Source::for_test(
+                remote,
+                net::SocketAddr::V4(local),
+                Some(mapped),
+                TLS_DISABLED,
+            ));
+            dst_addr(&mut req);
+
+            RecognizeEndpoint::new(default).recognize(&req) == default.map(make_test_endpoint)
+        }
+
+        fn recognize_default_no_loop_ipv6_loopback(
+            default: Option<net::SocketAddr>,
+            port: u16,
+            remote: net::SocketAddr
+        ) -> bool {
+            let local = net::SocketAddr::from(([127, 0, 0, 1], port));
+            let orig_dst = net::SocketAddr::new(net::Ipv6Addr::LOCALHOST.into(), port);
+            let mut req = http::Request::new(());
+            req.extensions_mut()
+                .insert(Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED));
+            dst_addr(&mut req);
+
+            RecognizeEndpoint::new(default).recognize(&req) == default.map(make_test_endpoint)
+        }
+
+        fn recognize_default_in_source_family(
+            default_v4: net::SocketAddrV4,
+            default_v6: net::SocketAddrV6,
+            local: net::SocketAddr,
+            remote: net::SocketAddr
+        ) -> bool {
+            let mut req = http::Request::new(());
+            req.extensions_mut()
+                .insert(Source::for_test(remote, local, None, TLS_DISABLED));
+            dst_addr(&mut req);
+
+            let expected = if Source::unmapped_ip(remote.ip()).is_ipv4() {
+                net::SocketAddr::V4(default_v4)
+            } else {
+                net::SocketAddr::V6(default_v6)
+            };
+            RecognizeEndpoint::default()
+                .with_default_addrs(vec![default_v6.into(), default_v4.into()])
+                .recognize(&req) == Some(make_test_endpoint(expected))
+        }
+
+        fn recognize_first_default_without_source_family(
+            defaults: Vec<net::SocketAddrV6>,
+            local: net::SocketAddr,
+            remote: net::SocketAddrV4
+        ) -> bool {
+            let defaults = defaults.into_iter().map(net::SocketAddr::V6).collect::<Vec<_>>();
+            let mut req = http::Request::new(());
+            req.extensions_mut().insert(Source::for_test(
+                net::SocketAddr::V4(remote),
+                local,
+                None,
+                TLS_DISABLED,
+            ));
+            dst_addr(&mut req);
+
+            let expected = defaults.first().cloned().map(make_test_endpoint);
+            RecognizeEndpoint::default()
+                .with_default_addrs(defaults)
+                .recognize(&req) == expected
+        }
+
+        fn recognize_authority_table_hit(
+            app: u16,
+            app_addr: net::SocketAddr,
+            orig_dst: net::SocketAddr,
+            local: net::SocketAddr,
+            remote: net::SocketAddr
+        ) -> bool {
+            let host = format!("app{}.local", app);
+            let mut req = http::Request::builder()
+                .uri(format!("http://{}/", host.to_uppercase()).as_str())
+                .body(())
+                .unwrap();
+            req.extensions_mut()
+                .insert(Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED));
+            dst_addr(&mut req);
+
+            let mut table = IndexMap::new();
+            table.insert(host, app_addr);
+            let rec = RecognizeEndpoint::new(None)
+                .with_authority_addrs(table)
+                .recognize(&req);
+            rec.map(|ep| (ep.addr, ep.source_meta.orig_dst)) == Some((app_addr, false))
+        }
+
+        fn recognize_authority_table_miss(
+            app: u16,
+            app_addr: net::SocketAddr,
+            orig_dst: net::SocketAddr,
+            local: net::SocketAddr,
+            remote: net::SocketAddr
+        ) -> bool {
+            let mut req = http::Request::builder()
+                .header(http::header::HOST, format!("other{}.local:8080", app).as_str())
+                .body(())
+                .unwrap();
+            req.extensions_mut()
+                .insert(Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED));
+            dst_addr(&mut req);
+
+            let mut table = IndexMap::new();
+            table.insert(format!("app{}.local", app), app_addr);
+            let rec = RecognizeEndpoint::new(None)
+                .with_authority_addrs(table)
+                .recognize(&req);
+            rec == RecognizeEndpoint::new(None).recognize(&req)
+        }
+
+        fn recognize_skip_ports(
+            orig_dst: net::SocketAddr,
+            local: net::SocketAddr,
+            remote: net::SocketAddr,
+            skip: Vec<u16>
+        ) -> bool {
+            let src = Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED);
+            let rec = src
+                .orig_dst_if_not_local()
+                .filter(|a| !skip.contains(&a.port()))
+                .map(make_orig_dst_endpoint);
+
+            let mut req = http::Request::new(());
+            req.extensions_mut().insert(src);
+            dst_addr(&mut req);
+
+            let skip_ports = skip.iter().cloned().collect();
+            RecognizeEndpoint::default()
+                .with_skip_ports(skip_ports)
+                .recognize(&req) == rec
+        }
+
+        fn recognize_dst_override(
+            override_dst: net::SocketAddr,
+            orig_dst: net::SocketAddr,
+            local: net::SocketAddr,
+            remote: net::SocketAddr,
+            enabled: bool
+        ) -> bool {
+            let src = Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED);
+            let rec = if enabled {
+                Some(Endpoint {
+                    source_meta: SourceMeta {
+                        dst_override: true,
+                        ..SourceMeta::default()
+                    },
+                    ..make_test_endpoint(override_dst)
+                })
+            } else {
+                src.orig_dst_if_not_local().map(make_orig_dst_endpoint)
+            };
+
+            let header = override_dst.to_string().parse().unwrap();
+            let dst_override = DstOverride::from_header(&header);
+
+            let mut req = http::Request::new(());
+            req.extensions_mut().insert(dst_override.expect("must parse"));
+            req.extensions_mut().insert(src);
+            dst_addr(&mut req);
+
+            RecognizeEndpoint::default()
+                .with_dst_override(enabled)
+                .recognize(&req) == rec
+        }
+
+        fn recognize_invalid_dst_override(
+            orig_dst: net::SocketAddr,
+            local: net::SocketAddr,
+            remote: net::SocketAddr
+        ) -> bool {
+            let src = Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED);
+            let rec = src.orig_dst_if_not_local().map(make_orig_dst_endpoint);
+
+            let mut req = http::Request::new(());
+            if let Some(o) = DstOverride::from_header(&"foo.bar:8080".parse().unwrap()) {
+                req.extensions_mut().insert(o);
+            }
+            req.extensions_mut().insert(src);
+            dst_addr(&mut req);
+
+            RecognizeEndpoint::default()
+                .with_dst_override(true)
+                .recognize(&req) == rec
+        }
+
+        fn recognize_forced_proto(
+            orig_dst: net::SocketAddr,
+            local: net::SocketAddr,
+            remote: net::SocketAddr,
+            h1: bool,
+            enabled: bool
+        ) -> bool {
+            let src = Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED);
+            let rec = src.orig_dst_if_not_local().map(|addr| {
+                let mut ep = make_orig_dst_endpoint(addr);
+                if h1 && enabled {
+                    // The test request has no authority, so it can't be
+                    // kept alive.
+                    ep.http_settings = Settings::Http1 {
+                        keep_alive: false,
+                        wants_h1_upgrade: false,
+                        was_absolute_form: false,
+                    };
+                }
+                ep
+            });
+
+            let mut req = http::Request::new(());
+            let proto = if h1 { "HTTP/1.1" } else { "HTTP/3" };
+            if let Some(p) = ForceProto::from_header(&proto.parse().unwrap()) {
+                req.extensions_mut().insert(p);
+            }
+            req.extensions_mut().insert(src);
+            dst_addr(&mut req);
+
+            RecognizeEndpoint::default()
+                .with_force_proto(enabled)
+                .recognize(&req) == rec
+        }
+
+        fn recognize_port_remap(
+            orig_dst: net::SocketAddr,
+            local: net::SocketAddr,
+            remote: net::SocketAddr,
+            port: u16
+        ) -> bool {
+            let src = Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED);
+            let rec = src.orig_dst_if_not_local().map(|mut addr| {
+                addr.set_port(port);
+                make_orig_dst_endpoint(addr)
+            });
+
+            let mut req = http::Request::new(());
+            req.extensions_mut().insert(src);
+            dst_addr(&mut req);
+
+            let mut remap = IndexMap::new();
+            remap.insert(orig_dst.port(), port);
+            RecognizeEndpoint::default()
+                .with_port_remap(remap)
+                .recognize(&req) == rec
+        }
+
+        fn recognize_h2_settings(
+            orig_dst: net::SocketAddr,
+            local: net::SocketAddr,
+            remote: net::SocketAddr,
+            stream_window: u32
+        ) -> bool {
+            let h2_settings = H2Settings {
+                initial_stream_window_size: Some(stream_window),
+                initial_connection_window_size: None,
+            };
+            let src = Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED);
+            let rec = src.orig_dst_if_not_local().map(|addr| Endpoint {
+                h2_settings: Some(h2_settings),
+                ..make_orig_dst_endpoint(addr)
+            });
+
+            let mut req = http::Request::new(());
+            req.extensions_mut().insert(src);
+            dst_addr(&mut req);
+
+            let mut ports = IndexMap::new();
+            ports.insert(orig_dst.port(), h2_settings);
+            RecognizeEndpoint::default()
+                .with_h2_settings(ports)
+                .recognize(&req) == rec
+        }
     }
 }
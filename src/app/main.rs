@@ -22,8 +22,8 @@ use never::Never;
 use proxy::{
     self, accept, buffer,
     http::{
-        client, insert, metrics as http_metrics, normalize_uri, profiles, router, settings,
-        strip_header,
+        client, extension_from_header, header_limit, insert, metrics as http_metrics,
+        normalize_uri, profiles, router, settings, strip_header,
     },
     pending, reconnect,
 };
@@ -235,6 +235,11 @@ where
             });
 
         let (tap_layer, tap_grpc, tap_daemon) = tap::new();
+        let tap_grpc = match config.tap_direction {
+            Some(direction) => tap_grpc.with_direction(direction),
+            None => tap_grpc,
+        };
+        let tap_grpc = tap_grpc.with_backpressure(config.tap_backpressure_enabled);
 
         let (ctl_http_metrics, ctl_http_report) = {
             let (m, r) = http_metrics::new::<ControlLabels, Class>(config.metrics_retain_idle);
@@ -256,12 +261,15 @@ where
 
         let (transport_metrics, transport_report) = transport::metrics::new();
 
+        let default_fwd_metrics = super::inbound::DefaultForwardMetrics::default();
+
         let report = endpoint_http_report
             .and_then(route_http_report)
             .and_then(retry_http_report)
             .and_then(transport_report)
             //.and_then(tls_config_report)
             .and_then(ctl_http_report)
+            .and_then(default_fwd_metrics.clone())
             .and_then(telemetry::process::Report::new(start_time));
 
         let mut identity_daemon = None;
@@ -365,6 +373,23 @@ where
             config.destination_context.clone(),
         );
 
+        let tap_json_log = config.tap_json_log;
+        let tap_span_log = config.tap_span_log;
+
+        // The tap daemon is only needed if taps may be served. On shutdown,
+        // tapped streams that are abandoned are reported as such, rather than
+        // as canceled.
+        let tap_daemon =
+            if control_listener.is_some() || tap_json_log.is_some() || tap_span_log.is_some() {
+                Some(
+                    drain_rx
+                        .clone()
+                        .watch(tap_daemon, |daemon| daemon.shutdown()),
+                )
+            } else {
+                None
+            };
+
         // Spawn a separate thread to handle the admin stuff.
         {
             let (tx, admin_shutdown_signal) = futures::sync::oneshot::channel::<()>();
@@ -379,11 +404,22 @@ where
                     rt.spawn(control::serve_http(
                         "admin",
                         admin_listener,
-                        Admin::new(report, readiness),
+                        Admin::new(report, readiness, tap_grpc.sessions()),
                     ));
 
+                    if let Some(daemon) = tap_daemon {
+                        rt.spawn(daemon.map_err(|_| ()));
+                    }
+
+                    if let Some(file) = tap_json_log {
+                        rt.spawn(tap::json_sink(tap_grpc.clone(), file));
+                    }
+
+                    if let Some(file) = tap_span_log {
+                        rt.spawn(tap::span_sink(tap_grpc.clone(), file));
+                    }
+
                     if let Some(listener) = control_listener {
-                        rt.spawn(tap_daemon.map_err(|_| ()));
                         rt.spawn(serve_tap(listener, TapServer::new(tap_grpc)));
                     }
 
@@ -442,6 +478,7 @@ where
             let profile_suffixes = config.destination_profile_suffixes.clone();
             let canonicalize_timeout = config.dns_canonicalize_timeout;
             let dispatch_timeout = config.outbound_dispatch_timeout;
+            let error_header_enabled = config.proxy_error_header_enabled;
 
             // Establishes connections to remote peers (for both TCP
             // forwarding and HTTP proxying).
@@ -620,7 +657,8 @@ where
             // shared `addr_router`. The `Source` is stored in the request's
             // extensions so that it can be used by the `addr_router`.
             let server_stack = svc::builder()
-                .layer(super::errors::layer())
+                .layer(tap_layer.rejections(outbound::RejectedSource::new))
+                .layer(super::errors::layer(error_header_enabled))
                 .layer(insert::target::layer())
                 .layer(insert::layer(move || {
                     DispatchDeadline::after(dispatch_timeout)
@@ -650,16 +688,39 @@ where
             use super::inbound::{
                 orig_proto_downgrade,
                 rewrite_loopback_addr,
+                DstOverride,
+                Endpoint,
+                ForceProto,
                 RecognizeEndpoint,
+                RejectedSource,
                 // set_client_id_on_req, set_remote_ip_on_req,
             };
+            use tower::limit::ConcurrencyLimitLayer;
 
             let capacity = config.inbound_router_capacity;
             let max_idle_age = config.inbound_router_max_idle_age;
             let max_in_flight = config.inbound_max_requests_in_flight;
+            let max_in_flight_per_endpoint = config
+                .inbound_max_requests_in_flight_per_endpoint
+                .unwrap_or(max_in_flight);
+            let max_header_bytes = config
+                .inbound_max_request_header_bytes
+                .unwrap_or(::std::usize::MAX);
             let profile_suffixes = config.destination_profile_suffixes;
-            let default_fwd_addr = config.inbound_forward.map(|a| a.into());
+            let default_fwd_addrs = config
+                .inbound_forward
+                .into_iter()
+                .chain(config.inbound_forward_alternates)
+                .collect::<Vec<_>>();
+            let skip_ports = config.inbound_ports_skip;
+            let remap_ports = config.inbound_ports_remap;
+            let authority_addrs = config.inbound_authority_addrs;
+            let ports_h2_settings = config.inbound_ports_h2_settings;
+            let local_app_identity = config.inbound_local_identity;
+            let dst_override_enabled = config.inbound_dst_override_enabled;
+            let force_proto_enabled = config.inbound_force_proto_enabled;
             let dispatch_timeout = config.inbound_dispatch_timeout;
+            let error_header_enabled = config.proxy_error_header_enabled;
 
             // Establishes connections to the local application (for both
             // TCP forwarding and HTTP proxying).
@@ -667,6 +728,11 @@ where
                 .layer(rewrite_loopback_addr::layer())
                 .layer(transport_metrics.connect("inbound"))
                 .timeout(config.inbound_connect_timeout)
+                // Retries are bounded by the connect timeout.
+                .layer(transport::retry::layer(
+                    config.inbound_connect_retries,
+                    config.inbound_connect_retry_backoff.clone(),
+                ))
                 .layer(keepalive::connect::layer(config.inbound_connect_keepalive))
                 .layer(tls::client::layer(local_identity))
                 .service(connect::svc());
@@ -675,24 +741,42 @@ where
             let client_stack = svc::builder()
                 .layer(normalize_uri::layer())
                 .layer(reconnect::layer().with_backoff(config.inbound_connect_backoff.clone()))
-                .layer(client::layer("in", config.h2_settings))
+                .layer(
+                    client::layer(&config.inbound_client_name, config.h2_settings)
+                        .with_h1_settings(config.inbound_h1_settings),
+                )
                 .service(connect.clone());
 
             // A stack configured by `router::Config`, responsible for building
             // a router made of route stacks configured by `inbound::Endpoint`.
             //
             // If there is no `SO_ORIGINAL_DST` for an inbound socket,
-            // `default_fwd_addr` may be used.
+            // one of `default_fwd_addrs` may be used.
             let endpoint_router = svc::builder()
                 .layer(router::layer(
                     router::Config::new("in endpoint", capacity, max_idle_age),
-                    RecognizeEndpoint::new(default_fwd_addr),
+                    RecognizeEndpoint::default()
+                        .with_default_addrs(default_fwd_addrs)
+                        .with_skip_ports(skip_ports)
+                        .with_port_remap(remap_ports)
+                        .with_authority_addrs(authority_addrs)
+                        .with_h2_settings(ports_h2_settings)
+                        .with_local_identity(local_app_identity)
+                        .with_dst_override(dst_override_enabled)
+                        .with_force_proto(force_proto_enabled)
+                        .with_default_forward_metrics(default_fwd_metrics),
                 ))
                 .buffer_pending(max_in_flight, DispatchDeadline::extract)
+                // The endpoint's detected settings are exposed on each
+                // request, so that lower layers need not derive them again.
+                .layer(insert::target::derive(|ep: &Endpoint| ep.http_settings))
+                // Each endpoint's client has its own limit, so that no single
+                // endpoint can consume all of the proxy's capacity.
+                .layer(ConcurrencyLimitLayer::new(max_in_flight_per_endpoint).per_make())
                 .layer(http_metrics::layer::<_, classify::Response>(
                     endpoint_http_metrics,
                 ))
-                .layer(tap_layer)
+                .layer(tap_layer.clone())
                 .service(client_stack)
                 .make();
 
@@ -781,11 +865,27 @@ where
             // `orig-proto` headers. This happens in the source stack so that
             // the router need not detect whether a request _will be_ downgraded.
             let source_stack = svc::builder()
-                .layer(super::errors::layer())
+                // The dst-override and force-proto headers are stripped
+                // before requests are tapped or routed; `RecognizeEndpoint`
+                // uses the values that are parsed from them into the
+                // request's extensions.
+                .layer(extension_from_header::layer(
+                    super::DST_OVERRIDE_HEADER,
+                    DstOverride::from_header,
+                ))
+                .layer(extension_from_header::layer(
+                    super::L5D_FORCE_PROTO,
+                    ForceProto::from_header,
+                ))
+                .layer(strip_header::request::layer(super::DST_OVERRIDE_HEADER))
+                .layer(strip_header::request::layer(super::L5D_FORCE_PROTO))
+                .layer(tap_layer.rejections(RejectedSource::new))
+                // Inbound connect timeouts are reported as gateway timeouts.
+                .layer(super::errors::layer(error_header_enabled).with_connect_timeouts())
                 .layer(insert::layer(move || {
                     DispatchDeadline::after(dispatch_timeout)
                 }))
-                .layer(strip_header::request::layer(super::DST_OVERRIDE_HEADER))
+                .layer(header_limit::layer(max_header_bytes))
                 .layer(strip_header::response::layer(super::L5D_SERVER_ID))
                 .layer(strip_header::request::layer(super::L5D_CLIENT_ID))
                 .layer(strip_header::request::layer(super::L5D_REMOTE_IP))
@@ -25,6 +25,11 @@ pub struct Endpoint {
     pub http_settings: settings::Settings,
 }
 
+/// Describes the requests of an outbound connection that are rejected before
+/// an `Endpoint` is resolved, so that they may be tapped.
+#[derive(Clone, Debug)]
+pub struct RejectedSource(proxy::server::Source);
+
 // === impl Endpoint ===
 
 impl Endpoint {
@@ -155,6 +160,67 @@ impl tap::Inspect for Endpoint {
             .map(|r| r.labels().clone())
     }
 
+    /// Taps observe requests before they are upgraded, so the endpoint
+    /// describes the original protocol.
+    fn protocol<B>(&self, _: &http::Request<B>) -> Option<settings::Settings> {
+        Some(self.http_settings)
+    }
+
+    fn is_orig_proto<B>(&self, _: &http::Request<B>) -> bool {
+        self.can_use_orig_proto()
+    }
+
+    fn profile_name<B>(&self, req: &http::Request<B>) -> Option<String> {
+        req.extensions()
+            .get::<super::dst::Route>()
+            .and_then(|r| r.profile_name())
+            .map(|n| n.to_string())
+    }
+
+    fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
+        true
+    }
+}
+
+// === impl RejectedSource ===
+
+impl RejectedSource {
+    pub fn new(source: &proxy::server::Source) -> Self {
+        RejectedSource(source.clone())
+    }
+}
+
+impl tap::Inspect for RejectedSource {
+    fn src_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+        Some(self.0.remote)
+    }
+
+    fn src_tls<'a, B>(
+        &self,
+        _: &'a http::Request<B>,
+    ) -> Conditional<&'a identity::Name, tls::ReasonForNoIdentity> {
+        Conditional::None(tls::ReasonForNoPeerName::Loopback.into())
+    }
+
+    fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+        self.0.orig_dst
+    }
+
+    fn dst_labels<B>(&self, _: &http::Request<B>) -> Option<&IndexMap<String, String>> {
+        None
+    }
+
+    fn dst_tls<B>(
+        &self,
+        _: &http::Request<B>,
+    ) -> Conditional<&identity::Name, tls::ReasonForNoIdentity> {
+        Conditional::None(tls::ReasonForNoPeerName::NotProvidedByServiceDiscovery.into())
+    }
+
+    fn route_labels<B>(&self, _: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
+        None
+    }
+
     fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
         true
     }
@@ -331,8 +331,8 @@ impl fmt::Display for Server {
     }
 }
 
-impl<D: fmt::Display> Client<&'static str, D> {
-    pub fn proxy(name: &'static str, dst: D) -> Self {
+impl<C: fmt::Display, D: fmt::Display> Client<C, D> {
+    pub fn proxy(name: C, dst: D) -> Self {
         Section::Proxy.client(name, dst)
     }
 }
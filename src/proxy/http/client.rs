@@ -3,6 +3,7 @@ use http;
 use hyper;
 use std::fmt;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use super::glue::{HttpBody, HyperConnect};
 use super::upgrade::{Http11Upgrade, HttpConnect};
@@ -10,7 +11,7 @@ use super::{
     h1, h2,
     settings::{HasSettings, Settings},
 };
-use app::config::H2Settings;
+use app::config::{H1Settings, H2Settings};
 use proxy::Error;
 use svc::{self, ServiceExt};
 use transport::{connect, tls};
@@ -20,7 +21,8 @@ use transport::{connect, tls};
 /// The `proxy_name` is used for diagnostics (logging, mostly).
 #[derive(Debug)]
 pub struct Layer<T, B> {
-    proxy_name: &'static str,
+    proxy_name: Arc<str>,
+    h1_settings: H1Settings,
     h2_settings: H2Settings,
     _p: PhantomData<fn(T) -> B>,
 }
@@ -30,7 +32,8 @@ type HyperClient<C, T, B> = hyper::Client<HyperConnect<C, T>, B>;
 /// A `MakeService` that can speak either HTTP/1 or HTTP/2.
 pub struct Client<C, T, B> {
     connect: C,
-    proxy_name: &'static str,
+    proxy_name: Arc<str>,
+    h1_settings: H1Settings,
     h2_settings: H2Settings,
     _p: PhantomData<fn(T) -> B>,
 }
@@ -68,24 +71,36 @@ pub enum ClientServiceFuture {
 
 // === impl Layer ===
 
-pub fn layer<T, B>(proxy_name: &'static str, h2_settings: H2Settings) -> Layer<T, B>
+pub fn layer<T, B>(proxy_name: &str, h2_settings: H2Settings) -> Layer<T, B>
 where
     B: hyper::body::Payload + Send + 'static,
 {
     Layer {
-        proxy_name,
+        proxy_name: proxy_name.into(),
+        h1_settings: H1Settings::default(),
         h2_settings,
         _p: PhantomData,
     }
 }
 
+impl<T, B> Layer<T, B> {
+    /// Configures how idle HTTP/1 connections are pooled.
+    pub fn with_h1_settings(self, h1_settings: H1Settings) -> Self {
+        Self {
+            h1_settings,
+            ..self
+        }
+    }
+}
+
 impl<T, B> Clone for Layer<T, B>
 where
     B: hyper::body::Payload + Send + 'static,
 {
     fn clone(&self) -> Self {
         Self {
-            proxy_name: self.proxy_name,
+            proxy_name: self.proxy_name.clone(),
+            h1_settings: self.h1_settings,
             h2_settings: self.h2_settings,
             _p: PhantomData,
         }
@@ -102,7 +117,8 @@ where
     fn layer(&self, connect: C) -> Self::Service {
         Client {
             connect,
-            proxy_name: self.proxy_name,
+            proxy_name: self.proxy_name.clone(),
+            h1_settings: self.h1_settings,
             h2_settings: self.h2_settings,
             _p: PhantomData,
         }
@@ -133,7 +149,7 @@ where
         debug!("building client={:?}", config);
 
         let connect = self.connect.clone();
-        let executor = ::logging::Client::proxy(self.proxy_name, config.peer_addr())
+        let executor = ::logging::Client::proxy(self.proxy_name.clone(), config.peer_addr())
             .with_settings(config.http_settings().clone())
             .executor();
 
@@ -143,18 +159,25 @@ where
                 wants_h1_upgrade: _,
                 was_absolute_form,
             } => {
-                let h1 = hyper::Client::builder()
+                let mut builder = hyper::Client::builder();
+                builder
                     .executor(executor)
                     .keep_alive(keep_alive)
                     // hyper should never try to automatically set the Host
                     // header, instead always just passing whatever we received.
-                    .set_host(false)
-                    .build(HyperConnect::new(connect, config, was_absolute_form));
+                    .set_host(false);
+                if let Some(max) = self.h1_settings.max_idle_per_endpoint {
+                    builder.max_idle_per_host(max);
+                }
+                if let Some(timeout) = self.h1_settings.idle_timeout {
+                    builder.keep_alive_timeout(timeout);
+                }
+                let h1 = builder.build(HyperConnect::new(connect, config, was_absolute_form));
                 ClientNewServiceFuture::Http1(Some(h1))
             }
             Settings::Http2 => {
-                let h2 =
-                    h2::Connect::new(connect, executor, self.h2_settings.clone()).oneshot(config);
+                let h2_settings = config.h2_settings().unwrap_or(self.h2_settings);
+                let h2 = h2::Connect::new(connect, executor, h2_settings).oneshot(config);
                 ClientNewServiceFuture::Http2(h2)
             }
             Settings::NotHttp => {
@@ -171,7 +194,8 @@ where
     fn clone(&self) -> Self {
         Client {
             connect: self.connect.clone(),
-            proxy_name: self.proxy_name,
+            proxy_name: self.proxy_name.clone(),
+            h1_settings: self.h1_settings,
             h2_settings: self.h2_settings,
             _p: PhantomData,
         }
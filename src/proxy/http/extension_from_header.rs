@@ -0,0 +1,142 @@
+use futures::{Future, Poll};
+use http;
+use http::header::{AsHeaderName, HeaderValue};
+
+use svc;
+
+/// Wraps HTTP `Service` `Stack<T>`s so that a request header is parsed into
+/// each request's extensions.
+///
+/// This lets a header be stripped before e.g. tap observes the request, while
+/// lower layers may still use its value.
+#[derive(Clone, Debug)]
+pub struct Layer<H, P> {
+    header: H,
+    parse: P,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<H, P, M> {
+    header: H,
+    parse: P,
+    inner: M,
+}
+
+pub struct MakeFuture<H, P, F> {
+    header: H,
+    parse: P,
+    inner: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<H, P, S> {
+    header: H,
+    parse: P,
+    inner: S,
+}
+
+// === impl Layer ===
+
+/// Inserts the value that `parse` returns for each request's `header` into the
+/// request's extensions. Requests without the header, or for which `parse`
+/// returns `None`, are passed through unchanged.
+pub fn layer<H, P, V>(header: H, parse: P) -> Layer<H, P>
+where
+    H: AsHeaderName + Clone,
+    P: Fn(&HeaderValue) -> Option<V> + Clone,
+    V: Send + Sync + 'static,
+{
+    Layer { header, parse }
+}
+
+impl<H, P, M> svc::Layer<M> for Layer<H, P>
+where
+    H: AsHeaderName + Clone,
+    P: Clone,
+{
+    type Service = Stack<H, P, M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            header: self.header.clone(),
+            parse: self.parse.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<H, P, T, M> svc::Service<T> for Stack<H, P, M>
+where
+    H: AsHeaderName + Clone,
+    P: Clone,
+    M: svc::Service<T>,
+{
+    type Response = Service<H, P, M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<H, P, M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, t: T) -> Self::Future {
+        MakeFuture {
+            header: self.header.clone(),
+            parse: self.parse.clone(),
+            inner: self.inner.call(t),
+        }
+    }
+}
+
+// === impl MakeFuture ===
+
+impl<H, P, F> Future for MakeFuture<H, P, F>
+where
+    H: Clone,
+    P: Clone,
+    F: Future,
+{
+    type Item = Service<H, P, F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Service {
+            header: self.header.clone(),
+            parse: self.parse.clone(),
+            inner,
+        }
+        .into())
+    }
+}
+
+// === impl Service ===
+
+impl<H, P, V, S, B> svc::Service<http::Request<B>> for Service<H, P, S>
+where
+    H: AsHeaderName + Clone,
+    P: Fn(&HeaderValue) -> Option<V>,
+    V: Send + Sync + 'static,
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        let value = req
+            .headers()
+            .get(self.header.clone())
+            .and_then(|v| (self.parse)(v));
+        if let Some(value) = value {
+            req.extensions_mut().insert(value);
+        }
+        self.inner.call(req)
+    }
+}
@@ -4,7 +4,7 @@ use hyper::client::connect as hyper_connect;
 use hyper::{self, body::Payload};
 
 use proxy;
-use proxy::http::{upgrade::Http11Upgrade, HasH2Reason};
+use proxy::http::{upgrade::Http11Upgrade, ErrorKind, HasH2Reason};
 use svc;
 use transport::tls::HasStatus as HasTlsStatus;
 use Conditional;
@@ -214,4 +214,8 @@ impl HasH2Reason for hyper::Error {
     fn h2_reason(&self) -> Option<h2::Reason> {
         (self as &(dyn std::error::Error + 'static)).h2_reason()
     }
+
+    fn error_kind(&self) -> ErrorKind {
+        (self as &(dyn std::error::Error + 'static)).error_kind()
+    }
 }
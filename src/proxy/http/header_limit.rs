@@ -0,0 +1,202 @@
+use futures::{future, Future, Poll};
+use http;
+use std::{error, fmt};
+
+use proxy::Error;
+use svc;
+
+/// Rejects requests whose headers are larger than `max_bytes`, without
+/// dispatching them to the inner service.
+///
+/// A header's size is the length of its name and value, so that requests with
+/// many small headers are bounded as well as those with a few large ones.
+pub fn layer(max_bytes: usize) -> Layer {
+    Layer { max_bytes }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    max_bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    max_bytes: usize,
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    max_bytes: usize,
+    inner: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    max_bytes: usize,
+    inner: S,
+}
+
+/// Indicates that a request was rejected because its headers were too large.
+#[derive(Debug)]
+pub struct HeadersTooLarge {
+    size: usize,
+    max_bytes: usize,
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<M> for Layer {
+    type Service = Stack<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            max_bytes: self.max_bytes,
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Service<T> for Stack<M>
+where
+    M: svc::Service<T>,
+{
+    type Response = Service<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        MakeFuture {
+            max_bytes: self.max_bytes,
+            inner: self.inner.call(target),
+        }
+    }
+}
+
+// === impl MakeFuture ===
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = Service<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Service {
+            max_bytes: self.max_bytes,
+            inner,
+        }
+        .into())
+    }
+}
+
+// === impl Service ===
+
+impl<S, B> svc::Service<http::Request<B>> for Service<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::FutureResult<S::Response, Error>,
+        future::MapErr<S::Future, fn(S::Error) -> Error>,
+    >;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let size = headers_size(req.headers());
+        if size > self.max_bytes {
+            debug!(
+                "request headers are too large; size={}; max={}",
+                size, self.max_bytes
+            );
+            let e = HeadersTooLarge {
+                size,
+                max_bytes: self.max_bytes,
+            };
+            return future::Either::A(future::err(e.into()));
+        }
+
+        future::Either::B(self.inner.call(req).map_err(Into::into))
+    }
+}
+
+fn headers_size(headers: &http::HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum()
+}
+
+// === impl HeadersTooLarge ===
+
+impl fmt::Display for HeadersTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "request headers are {} bytes, exceeding the limit of {} bytes",
+            self.size, self.max_bytes
+        )
+    }
+}
+
+impl error::Error for HeadersTooLarge {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svc::Service as _;
+
+    /// Responds to every request.
+    struct Accept;
+
+    impl svc::Service<http::Request<()>> for Accept {
+        type Response = http::Response<()>;
+        type Error = Error;
+        type Future = future::FutureResult<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, _: http::Request<()>) -> Self::Future {
+            future::ok(http::Response::new(()))
+        }
+    }
+
+    fn request(value: &str) -> http::Request<()> {
+        http::Request::builder()
+            .header("x-test", value)
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn allows_headers_up_to_the_limit() {
+        // "x-test" and "abcd" are ten bytes in all.
+        let mut svc = Service {
+            max_bytes: 10,
+            inner: Accept,
+        };
+        assert!(svc.call(request("abcd")).wait().is_ok());
+    }
+
+    #[test]
+    fn rejects_headers_over_the_limit() {
+        let mut svc = Service {
+            max_bytes: 10,
+            inner: Accept,
+        };
+        let e = svc.call(request("abcde")).wait().expect_err("must fail");
+        assert!(e.is::<HeadersTooLarge>());
+    }
+}
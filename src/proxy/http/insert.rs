@@ -169,57 +169,113 @@ where
 pub mod target {
     use super::*;
 
-    /// Wraps an HTTP `Service` so that the Stack's `T -typed target` is cloned into
-    /// each request's extensions.
+    /// Derives a value from a Stack's target, to be inserted into each
+    /// request's extensions.
+    pub trait Derive<T>: Clone {
+        type Value: Clone + Send + Sync + 'static;
+
+        fn derive(&self, target: &T) -> Self::Value;
+    }
+
+    /// Derives the target itself.
+    #[derive(Copy, Clone, Debug)]
+    pub struct CloneTarget;
+
+    /// Wraps an HTTP `Service` so that a value derived from the Stack's
+    /// `T -typed target` is cloned into each request's extensions.
     #[derive(Clone, Debug)]
-    pub struct Make<M>(M);
+    pub struct Make<M, D = CloneTarget> {
+        inner: M,
+        derive: D,
+    }
 
-    pub struct MakeFuture<F, T> {
+    pub struct MakeFuture<F, V> {
         inner: F,
-        target: T,
+        value: V,
     }
 
     // === impl Layer ===
 
     pub fn layer<M>() -> impl svc::Layer<M, Service = Make<M>> + Copy {
-        svc::layer::mk(Make)
+        svc::layer::mk(|inner: M| Make {
+            inner,
+            derive: CloneTarget,
+        })
     }
 
-    // === impl Stack ===
+    /// Inserts the value produced by `derive` for each target, e.g.
+    /// `derive(|t: &Endpoint| t.labels.clone())`.
+    pub fn derive<D, M>(derive: D) -> impl svc::Layer<M, Service = Make<M, D>> + Clone
+    where
+        D: Clone,
+    {
+        svc::layer::mk(move |inner: M| Make {
+            inner,
+            derive: derive.clone(),
+        })
+    }
+
+    // === impl Derive ===
+
+    impl<T, V, F> Derive<T> for F
+    where
+        F: Fn(&T) -> V + Clone,
+        V: Clone + Send + Sync + 'static,
+    {
+        type Value = V;
 
-    impl<T, M> svc::Service<T> for Make<M>
+        fn derive(&self, target: &T) -> V {
+            (self)(target)
+        }
+    }
+
+    impl<T> Derive<T> for CloneTarget
     where
         T: Clone + Send + Sync + 'static,
+    {
+        type Value = T;
+
+        fn derive(&self, target: &T) -> T {
+            target.clone()
+        }
+    }
+
+    // === impl Stack ===
+
+    impl<T, M, D> svc::Service<T> for Make<M, D>
+    where
         M: svc::Service<T>,
+        D: Derive<T>,
     {
-        type Response = super::Service<M::Response, super::ValLazy<T>, T>;
+        type Response = super::Service<M::Response, super::ValLazy<D::Value>, D::Value>;
         type Error = M::Error;
-        type Future = MakeFuture<M::Future, T>;
+        type Future = MakeFuture<M::Future, D::Value>;
 
         fn poll_ready(&mut self) -> Poll<(), Self::Error> {
-            self.0.poll_ready()
+            self.inner.poll_ready()
         }
 
         fn call(&mut self, t: T) -> Self::Future {
-            let target = t.clone();
-            let inner = self.0.call(t);
-            MakeFuture { inner, target }
+            let value = self.derive.derive(&t);
+            let inner = self.inner.call(t);
+            MakeFuture { inner, value }
         }
     }
 
     // === impl MakeFuture ===
 
-    impl<F, T> Future for MakeFuture<F, T>
+    impl<F, V> Future for MakeFuture<F, V>
     where
         F: Future,
-        T: Clone,
+        V: Clone,
     {
-        type Item = super::Service<F::Item, ValLazy<T>, T>;
+        type Item = super::Service<F::Item, ValLazy<V>, V>;
         type Error = F::Error;
 
         fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
             let inner = try_ready!(self.inner.poll());
-            let svc = super::Service::new(inner, super::ValLazy(self.target.clone()));
+            let lazy = super::ValLazy(self.value.clone());
+            let svc = super::Service::new(inner, lazy);
             Ok(svc.into())
         }
     }
@@ -2,11 +2,13 @@ pub mod add_header;
 pub mod balance;
 pub mod canonicalize;
 pub mod client;
+pub mod extension_from_header;
 pub mod fallback;
 pub(super) mod glue;
 pub mod h1;
 pub mod h2;
 pub mod header_from_target;
+pub mod header_limit;
 pub mod insert;
 pub mod metrics;
 pub mod normalize_uri;
@@ -28,6 +30,44 @@ use http::uri::Authority;
 
 pub trait HasH2Reason {
     fn h2_reason(&self) -> Option<::h2::Reason>;
+
+    /// Classifies errors that are not necessarily described by an HTTP/2
+    /// reason, e.g. so that tap can report why a stream failed.
+    fn error_kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+/// Describes the cause of a failed stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A timeout elapsed.
+    Timeout,
+
+    /// A connection to the endpoint was refused.
+    ConnectionRefused,
+
+    /// Any other error.
+    Other,
+}
+
+/// A header that describes why the proxy failed a request, in the response
+/// that the proxy generated for it.
+pub const L5D_PROXY_ERROR: &str = "l5d-proxy-error";
+
+/// A marker set in `http::Response::extensions` when *this* process
+/// generated the response, rather than receiving it from an upstream server.
+#[derive(Clone, Copy, Debug)]
+pub struct ProxyResponse(());
+
+impl ProxyResponse {
+    pub fn mark<B>(rsp: &mut http::Response<B>) {
+        rsp.extensions_mut().insert(ProxyResponse(()));
+    }
+
+    pub fn is_marked<B>(rsp: &http::Response<B>) -> bool {
+        rsp.extensions().get::<ProxyResponse>().is_some()
+    }
 }
 
 impl<'a> HasH2Reason for &'a (dyn std::error::Error + 'static) {
@@ -44,12 +84,38 @@ impl<'a> HasH2Reason for &'a (dyn std::error::Error + 'static) {
 
         None
     }
+
+    fn error_kind(&self) -> ErrorKind {
+        let mut cause = Some(*self);
+
+        while let Some(err) = cause {
+            if err.is::<::timeout::error::Timedout>() {
+                return ErrorKind::Timeout;
+            }
+
+            if let Some(err) = err.downcast_ref::<::std::io::Error>() {
+                match err.kind() {
+                    ::std::io::ErrorKind::TimedOut => return ErrorKind::Timeout,
+                    ::std::io::ErrorKind::ConnectionRefused => return ErrorKind::ConnectionRefused,
+                    _ => {}
+                }
+            }
+
+            cause = err.source();
+        }
+
+        ErrorKind::Other
+    }
 }
 
 impl HasH2Reason for ::proxy::Error {
     fn h2_reason(&self) -> Option<::h2::Reason> {
         (&**self as &(dyn std::error::Error + 'static)).h2_reason()
     }
+
+    fn error_kind(&self) -> ErrorKind {
+        (&**self as &(dyn std::error::Error + 'static)).error_kind()
+    }
 }
 
 impl HasH2Reason for ::h2::Error {
@@ -58,6 +124,18 @@ impl HasH2Reason for ::h2::Error {
     }
 }
 
+// === impl ErrorKind ===
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ErrorKind::Timeout => write!(f, "timeout"),
+            ErrorKind::ConnectionRefused => write!(f, "connection_refused"),
+            ErrorKind::Other => write!(f, "other"),
+        }
+    }
+}
+
 /// Returns an Authority from the value of `header`.
 pub fn authority_from_header<B, K>(req: &http::Request<B>, header: K) -> Option<Authority>
 where
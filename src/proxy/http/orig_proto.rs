@@ -20,6 +20,11 @@ pub struct Downgrade<S> {
     inner: S,
 }
 
+/// Marks requests that were downgraded to their original protocol, so that
+/// they may be distinguished from requests that were received as-is.
+#[derive(Clone, Debug)]
+pub struct Downgraded(());
+
 // ==== impl Upgrade =====
 
 impl<S> Upgrade<S> {
@@ -141,6 +146,7 @@ where
                 if !was_absolute_form(val) {
                     h1::set_origin_form(req.uri_mut());
                 }
+                Downgraded::mark(&mut req);
                 upgrade_response = true;
             }
         }
@@ -172,6 +178,18 @@ where
     }
 }
 
+// ===== impl Downgraded =====
+
+impl Downgraded {
+    pub fn mark<B>(req: &mut http::Request<B>) {
+        req.extensions_mut().insert(Downgraded(()));
+    }
+
+    pub fn is_marked<B>(req: &http::Request<B>) -> bool {
+        req.extensions().get::<Downgraded>().is_some()
+    }
+}
+
 fn was_absolute_form(val: &[u8]) -> bool {
     val.len() >= "HTTP/1.1; absolute-form".len() && &val[10..23] == b"absolute-form"
 }
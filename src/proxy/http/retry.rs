@@ -45,8 +45,16 @@ pub struct MakeFuture<F, R, S> {
 
 pub type Service<R, Svc, St> = tower_retry::Retry<Policy<R, St>, Svc>;
 
+/// Counts the number of times a request has been retried.
+///
+/// Retried requests carry this as an extension; original requests do not.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Retries(pub usize);
+
+/// Holds the number of retries issued so far, so that each cloned request
+/// can be annotated with its `Retries`.
 #[derive(Clone)]
-pub struct Policy<R, S>(R, S);
+pub struct Policy<R, S>(R, S, usize);
 
 // === impl Layer ===
 
@@ -118,7 +126,7 @@ where
         let policy = if let Some(retries) = target.can_retry() {
             trace!("stack is retryable");
             let stats = self.registry.scoped(target.clone().into());
-            Some(Policy(retries, stats))
+            Some(Policy(retries, stats, 0))
         } else {
             None
         };
@@ -162,7 +170,11 @@ where
             Ok(res) => match self.0.retry(req, res) {
                 Ok(()) => {
                     trace!("retrying request");
-                    Some(future::ok(self.clone()))
+                    Some(future::ok(Policy(
+                        self.0.clone(),
+                        self.1.clone(),
+                        self.2 + 1,
+                    )))
                 }
                 Err(NoRetry::Budget) => {
                     self.1.incr_retry_skipped_budget();
@@ -178,8 +190,10 @@ where
     }
 
     fn clone_request(&self, req: &Request<A>) -> Option<Request<A>> {
-        if let Some(clone) = self.0.clone_request(req) {
+        if let Some(mut clone) = self.0.clone_request(req) {
             trace!("cloning request");
+            // The clone is only dispatched if the request is retried.
+            clone.extensions_mut().insert(Retries(self.2 + 1));
             Some(clone)
         } else {
             trace!("request could not be cloned");
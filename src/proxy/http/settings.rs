@@ -1,4 +1,6 @@
+use app::config::H2Settings;
 use http::{self, header::HOST};
+use std::fmt;
 
 /// HTTP Client Settings portion of the `Recognize` key for a request.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -25,6 +27,13 @@ pub enum Settings {
 
 pub trait HasSettings {
     fn http_settings(&self) -> &Settings;
+
+    /// Overrides the client's HTTP/2 settings for this target.
+    ///
+    /// By default, all HTTP/2 clients are configured identically.
+    fn h2_settings(&self) -> Option<H2Settings> {
+        None
+    }
 }
 
 // ===== impl Settings =====
@@ -73,3 +82,49 @@ impl Settings {
         }
     }
 }
+
+/// Renders settings compactly, e.g. `h2` or `h1+upgrade+absolute`.
+///
+/// Keep-alive is omitted, since it doesn't change how a client is configured.
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Settings::Http1 {
+                wants_h1_upgrade,
+                was_absolute_form,
+                ..
+            } => {
+                f.write_str("h1")?;
+                if *wants_h1_upgrade {
+                    f.write_str("+upgrade")?;
+                }
+                if *was_absolute_form {
+                    f.write_str("+absolute")?;
+                }
+                Ok(())
+            }
+            Settings::Http2 => f.write_str("h2"),
+            Settings::NotHttp => f.write_str("opaque"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Settings;
+
+    #[test]
+    fn display() {
+        let h1 = |wants_h1_upgrade, was_absolute_form| Settings::Http1 {
+            keep_alive: true,
+            wants_h1_upgrade,
+            was_absolute_form,
+        };
+        assert_eq!(h1(false, false).to_string(), "h1");
+        assert_eq!(h1(true, false).to_string(), "h1+upgrade");
+        assert_eq!(h1(false, true).to_string(), "h1+absolute");
+        assert_eq!(h1(true, true).to_string(), "h1+upgrade+absolute");
+        assert_eq!(Settings::Http2.to_string(), "h2");
+        assert_eq!(Settings::NotHttp.to_string(), "opaque");
+    }
+}
@@ -114,12 +114,14 @@ where
                 let mut res = Response::default();
                 *res.status_mut() = StatusCode::GATEWAY_TIMEOUT;
                 res.extensions_mut().insert(ProxyTimedOut(()));
+                super::ProxyResponse::mark(&mut res);
                 return Ok(res);
             } else if let Some(err) = err.downcast_ref::<error::Timer>() {
                 // These are unexpected, and mean the runtime is in a bad place.
                 error!("unexpected runtime timer error: {}", err);
                 let mut res = Response::default();
                 *res.status_mut() = StatusCode::BAD_GATEWAY;
+                super::ProxyResponse::mark(&mut res);
                 return Ok(res);
             }
 
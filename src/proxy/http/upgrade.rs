@@ -219,6 +219,7 @@ where
         if h1::is_bad_request(&req) {
             let mut res = http::Response::default();
             *res.status_mut() = http::StatusCode::BAD_REQUEST;
+            super::ProxyResponse::mark(&mut res);
             return Either::B(future::ok(res));
         }
 
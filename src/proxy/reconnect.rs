@@ -56,7 +56,7 @@ pub enum Backoff {
 }
 
 impl Backoff {
-    fn for_failures<R: rand::Rng>(&self, failures: u32, mut rng: R) -> Option<Duration> {
+    pub(crate) fn for_failures<R: rand::Rng>(&self, failures: u32, mut rng: R) -> Option<Duration> {
         match self {
             Backoff::None => None,
             Backoff::Exponential { max, min, jitter } => {
@@ -2,7 +2,7 @@ use futures::{future::Either, Future};
 use http;
 use hyper;
 use std::marker::PhantomData;
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::{error, fmt};
 
 use futures::{future, Poll};
@@ -108,14 +108,35 @@ impl Source {
         }
     }
 
+    /// Addresses are considered the same if they have the same port and
+    /// either the same IP (after converting IPv4-mapped IPv6 addresses to
+    /// IPv4) or both have loopback IPs, since a loopback address of either
+    /// family may reach the proxy's listener.
     fn same_addr(a0: SocketAddr, a1: SocketAddr) -> bool {
-        use std::net::IpAddr::{V4, V6};
-        (a0.port() == a1.port())
-            && match (a0.ip(), a1.ip()) {
-                (V6(a0), V4(a1)) => a0.to_ipv4() == Some(a1),
-                (V4(a0), V6(a1)) => Some(a0) == a1.to_ipv4(),
-                (a0, a1) => (a0 == a1),
+        if a0.port() != a1.port() {
+            return false;
+        }
+        let (ip0, ip1) = (Self::unmapped_ip(a0.ip()), Self::unmapped_ip(a1.ip()));
+        ip0 == ip1 || (ip0.is_loopback() && ip1.is_loopback())
+    }
+
+    /// Converts IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`) to IPv4.
+    ///
+    /// `Ipv6Addr::to_ipv4` is not used because it also converts deprecated
+    /// IPv4-compatible addresses, so that, e.g., `::1` would be treated as
+    /// `0.0.0.1`.
+    pub(crate) fn unmapped_ip(ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V6(v6) => {
+                let o = v6.octets();
+                if o[..10].iter().all(|b| *b == 0) && o[10] == 0xff && o[11] == 0xff {
+                    Ipv4Addr::new(o[12], o[13], o[14], o[15]).into()
+                } else {
+                    IpAddr::V6(v6)
+                }
             }
+            ip => ip,
+        }
     }
 
     #[cfg(test)]
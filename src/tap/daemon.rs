@@ -3,8 +3,9 @@ use futures::{Async, Future, Poll, Stream};
 use never::Never;
 
 use super::iface::Tap;
+use super::Shutdown;
 
-pub fn new<T>() -> (Daemon<T>, Register<T>, Subscribe<T>) {
+pub(super) fn new<T>(shutdown: Shutdown) -> (Daemon<T>, Register<T>, Subscribe<T>) {
     let (svc_tx, svc_rx) = mpsc::channel(super::REGISTER_CHANNEL_CAPACITY);
     let (tap_tx, tap_rx) = mpsc::channel(super::TAP_CAPACITY);
 
@@ -14,6 +15,8 @@ pub fn new<T>() -> (Daemon<T>, Register<T>, Subscribe<T>) {
 
         tap_rx,
         taps: Vec::default(),
+
+        shutdown,
     };
 
     (daemon, Register(svc_tx), Subscribe(tap_tx))
@@ -32,6 +35,8 @@ pub struct Daemon<T> {
 
     tap_rx: mpsc::Receiver<(T, oneshot::Sender<()>)>,
     taps: Vec<T>,
+
+    shutdown: Shutdown,
 }
 
 #[derive(Debug)]
@@ -52,11 +57,27 @@ enum FutState<T> {
     Pending(oneshot::Receiver<()>),
 }
 
+impl<T> Daemon<T> {
+    /// Marks tapped streams that are abandoned from now on as abandoned due
+    /// to shutdown, and completes the daemon so that no more taps are
+    /// dispatched to services.
+    pub fn shutdown(&mut self) {
+        debug!("tap daemon shutting down");
+        self.shutdown.shutdown();
+        self.svcs.clear();
+        self.taps.clear();
+    }
+}
+
 impl<T: Tap> Future for Daemon<T> {
     type Item = ();
     type Error = Never;
 
     fn poll(&mut self) -> Poll<(), Never> {
+        if self.shutdown.is_shutdown() {
+            return Ok(Async::Ready(()));
+        }
+
         // Drop taps that are no longer active (i.e. the response stream has
         // been dropped).
         let tap_count = self.taps.len();
@@ -3,14 +3,23 @@ use indexmap::IndexMap;
 use ipnet::{Contains, Ipv4Net, Ipv6Net};
 use std::boxed::Box;
 use std::net;
+use std::str::FromStr;
 use std::{error, fmt};
 
 use api::net::ip_address;
 use api::tap::observe_request;
 use convert::TryFrom;
+use identity;
+use transport::tls::ReasonForNoIdentity;
+use Conditional;
 
 use tap::Inspect;
 
+/// A predicate over requests and the `Inspect`ed targets that serve them.
+///
+/// While it is built from a tap's `ObserveRequest`, a `Match` may be
+/// evaluated by anything that can inspect its requests, independently of the
+/// tap server.
 #[derive(Clone, Debug)]
 pub enum Match {
     Any(Vec<Match>),
@@ -18,9 +27,23 @@ pub enum Match {
     Not(Box<Match>),
     Source(TcpMatch),
     Destination(TcpMatch),
+    SourceLabel(LabelMatch),
     DestinationLabel(LabelMatch),
     RouteLabel(LabelMatch),
     Http(HttpMatch),
+    Tls(TlsMatch),
+    Direction(DirectionMatch),
+}
+
+/// Summarizes the directions of the requests that a `Match` may satisfy.
+///
+/// A service's requests all flow in the same direction, so a service may
+/// consult a tap's prefilter once, rather than evaluating its match for every
+/// request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Prefilter {
+    inbound: bool,
+    outbound: bool,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -30,8 +53,20 @@ pub enum InvalidMatch {
     InvalidNetwork,
     InvalidHttpMethod,
     InvalidScheme,
+    InvalidGrpcStatus,
+    InvalidTls,
+    InvalidHost,
+    InvalidDirection,
+    InvalidGrpcMethod,
+    InvalidPath,
+    InvalidContentType,
+    TooDeep,
 }
 
+/// Bounds the nesting of `All`, `Any`, and `Not` matches, so that a hostile
+/// `ObserveRequest` cannot exhaust the stack when its match is evaluated.
+const MAX_DEPTH: usize = 32;
+
 #[derive(Clone, Debug)]
 pub struct LabelMatch {
     key: String,
@@ -57,20 +92,133 @@ pub enum HttpMatch {
     Method(http::Method),
     Path(observe_request::r#match::http::string_match::Match),
     Authority(observe_request::r#match::http::string_match::Match),
+    Host(HostMatch),
+    GrpcMethod(GrpcMethodMatch),
+    ContentType(ContentTypeMatch),
+}
+
+/// Matches the host of a request's authority, ignoring its port.
+///
+/// Hosts are compared case-insensitively. A `*.`-prefixed host, e.g.
+/// `*.example.com`, matches any of its subdomains, but not the domain itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HostMatch {
+    Exact(String),
+    /// Holds the suffix of a wildcard, including its leading `.`.
+    Suffix(String),
 }
 
+/// Matches gRPC requests by the service and method that their path names,
+/// e.g. `/package.Service/Method`.
+///
+/// It is parsed from a `package.Service/Method` string, where either part may
+/// be a `*` wildcard. Requests whose paths do not name a gRPC method never
+/// match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrpcMethodMatch {
+    service: Option<String>,
+    method: Option<String>,
+}
+
+/// Matches requests whose `content-type` has any of a comma-separated list of
+/// media types, e.g. `application/json,application/grpc`.
+///
+/// Media types are compared case-insensitively and without their parameters.
+/// A `type/subtype` also matches its structured-syntax variants, so that
+/// `application/json` matches `application/vnd.api+json` and
+/// `application/grpc` matches `application/grpc+proto`. A bare suffix, e.g.
+/// `+proto`, matches any media type with that suffix. Requests without a
+/// `content-type` never match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentTypeMatch(Vec<MediaTypeMatch>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum MediaTypeMatch {
+    Type {
+        type_: String,
+        subtype: String,
+    },
+    /// Holds a structured-syntax suffix, without its leading `+`.
+    Suffix(String),
+}
+
+/// Matches requests with any of a comma-separated list of HTTP methods, e.g.
+/// `POST,PUT`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MethodsMatch(Vec<http::Method>);
+
+/// Matches requests with any of a comma-separated list of paths, e.g.
+/// `/healthz,/metrics/*`. A path ending in `*` matches any path with its
+/// prefix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathsMatch(Vec<observe_request::r#match::http::string_match::Match>);
+
+/// Matches whether the connection with the proxy's peer is secured by mTLS.
+///
+/// Inbound requests are matched by the TLS status of their source, and
+/// outbound requests by the TLS status of their destination.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TlsMatch {
+    Established,
+    Disabled,
+}
+
+/// Matches whether a request was proxied inbound or outbound.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DirectionMatch {
+    Inbound,
+    Outbound,
+}
+
+/// Matches the `grpc-status` of a response once its stream has ended.
+///
+/// Responses without a `grpc-status` never match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrpcStatusMatch(Vec<u32>);
+
 // ===== impl Match ======
 
 impl Match {
-    fn from_seq(seq: observe_request::r#match::Seq) -> Result<Vec<Self>, InvalidMatch> {
+    fn from_seq(
+        seq: observe_request::r#match::Seq,
+        depth: usize,
+    ) -> Result<Vec<Self>, InvalidMatch> {
         let mut new = Vec::with_capacity(seq.matches.len());
         for m in seq.matches.into_iter().filter_map(|m| m.r#match) {
-            new.push(Self::try_from(m)?);
+            new.push(Self::from_proto(m, depth)?);
         }
 
         Ok(new)
     }
 
+    /// Builds a match nested within `depth - 1` combinators.
+    fn from_proto(m: observe_request::r#match::Match, depth: usize) -> Result<Self, InvalidMatch> {
+        use api::tap::observe_request::r#match;
+
+        if depth > MAX_DEPTH {
+            return Err(InvalidMatch::TooDeep);
+        }
+
+        match m {
+            r#match::Match::All(seq) => Self::from_seq(seq, depth + 1).map(Match::All),
+            r#match::Match::Any(seq) => Self::from_seq(seq, depth + 1).map(Match::Any),
+            r#match::Match::Not(m) => m
+                .r#match
+                .ok_or(InvalidMatch::Empty)
+                .and_then(|m| Self::from_proto(m, depth + 1))
+                .map(|m| Match::Not(Box::new(m))),
+            r#match::Match::Source(src) => TcpMatch::try_from(src).map(Match::Source),
+            r#match::Match::Destination(dst) => TcpMatch::try_from(dst).map(Match::Destination),
+            r#match::Match::DestinationLabel(l) => {
+                LabelMatch::try_from(l).map(Match::DestinationLabel)
+            }
+            r#match::Match::RouteLabel(l) => LabelMatch::try_from(l).map(Match::RouteLabel),
+            r#match::Match::Http(http) => HttpMatch::try_from(http).map(Match::Http),
+        }
+    }
+
+    /// Returns `true` if `req`, as described by `inspect`, satisfies this
+    /// predicate.
     pub fn matches<B, I: Inspect>(&self, req: &http::Request<B>, inspect: &I) -> bool {
         match self {
             Match::Any(ref ms) => ms.iter().any(|m| m.matches(req, inspect)),
@@ -84,20 +232,68 @@ impl Match {
                 .dst_addr(req)
                 .map(|d| dst.matches(d))
                 .unwrap_or(false),
+            Match::SourceLabel(ref lbl) => {
+                lbl.matches_tls(inspect.src_tls(req), super::CLIENT_ID_LABEL)
+            }
             Match::DestinationLabel(ref lbl) => inspect
                 .dst_labels(req)
-                .map(|l| lbl.matches(l))
+                .map(|l| {
+                    lbl.matches(l) || lbl.matches_tls(inspect.dst_tls(req), super::SERVER_ID_LABEL)
+                })
                 .unwrap_or(false),
             Match::RouteLabel(ref lbl) => inspect
                 .route_labels(req)
                 .map(|l| lbl.matches(l.as_ref()))
                 .unwrap_or(false),
             Match::Http(ref http) => http.matches(req, inspect),
+            Match::Tls(ref tls) => tls.matches(is_peer_tls_established(req, inspect)),
+            Match::Direction(ref dir) => dir.matches(inspect.is_outbound(req)),
         }
     }
 }
 
 impl Match {
+    pub fn prefilter(&self) -> Prefilter {
+        Prefilter {
+            inbound: self.matches_direction(false) != Some(false),
+            outbound: self.matches_direction(true) != Some(false),
+        }
+    }
+
+    /// Evaluates this predicate knowing only whether a request is outbound.
+    ///
+    /// Returns `None` if the result depends on other properties of the
+    /// request.
+    fn matches_direction(&self, is_outbound: bool) -> Option<bool> {
+        match self {
+            Match::Any(ref ms) => {
+                let mut any = Some(false);
+                for m in ms {
+                    match m.matches_direction(is_outbound) {
+                        Some(true) => return Some(true),
+                        Some(false) => {}
+                        None => any = None,
+                    }
+                }
+                any
+            }
+            Match::All(ref ms) => {
+                let mut all = Some(true);
+                for m in ms {
+                    match m.matches_direction(is_outbound) {
+                        Some(false) => return Some(false),
+                        Some(true) => {}
+                        None => all = None,
+                    }
+                }
+                all
+            }
+            Match::Not(ref not) => not.matches_direction(is_outbound).map(|m| !m),
+            Match::Direction(ref dir) => Some(dir.matches(is_outbound)),
+            _ => None,
+        }
+    }
+
     pub fn try_new(m: Option<observe_request::Match>) -> Result<Self, InvalidMatch> {
         m.and_then(|m| m.r#match)
             .map(Self::try_from)
@@ -108,26 +304,19 @@ impl Match {
 impl TryFrom<observe_request::r#match::Match> for Match {
     type Err = InvalidMatch;
 
-    #[allow(unconditional_recursion)]
     fn try_from(m: observe_request::r#match::Match) -> Result<Self, Self::Err> {
-        use api::tap::observe_request::r#match;
+        Self::from_proto(m, 1)
+    }
+}
 
-        match m {
-            r#match::Match::All(seq) => Self::from_seq(seq).map(Match::All),
-            r#match::Match::Any(seq) => Self::from_seq(seq).map(Match::Any),
-            r#match::Match::Not(m) => m
-                .r#match
-                .ok_or(InvalidMatch::Empty)
-                .and_then(Self::try_from)
-                .map(|m| Match::Not(Box::new(m))),
-            r#match::Match::Source(src) => TcpMatch::try_from(src).map(Match::Source),
-            r#match::Match::Destination(dst) => TcpMatch::try_from(dst).map(Match::Destination),
-            r#match::Match::DestinationLabel(l) => {
-                LabelMatch::try_from(l).map(Match::DestinationLabel)
-            }
-            r#match::Match::RouteLabel(l) => LabelMatch::try_from(l).map(Match::RouteLabel),
-            r#match::Match::Http(http) => HttpMatch::try_from(http).map(Match::Http),
-        }
+/// Determines whether the connection with the proxy's peer is secured by mTLS:
+/// the source's for inbound requests, and the destination's for outbound
+/// requests.
+fn is_peer_tls_established<B, I: Inspect>(req: &http::Request<B>, inspect: &I) -> bool {
+    if inspect.is_outbound(req) {
+        inspect.dst_tls(req).is_some()
+    } else {
+        inspect.src_tls(req).is_some()
     }
 }
 
@@ -137,6 +326,46 @@ impl LabelMatch {
     fn matches(&self, labels: &IndexMap<String, String>) -> bool {
         labels.get(&self.key) == Some(&self.value)
     }
+
+    /// Matches the labels that tap events use to describe a peer's TLS
+    /// status, where the peer's identity is labeled by `id_label`.
+    fn matches_tls(
+        &self,
+        tls: Conditional<&identity::Name, ReasonForNoIdentity>,
+        id_label: &str,
+    ) -> bool {
+        if self.key == super::TLS_LABEL {
+            return tls.as_ref().map(|_| ()).to_string() == self.value;
+        }
+
+        if self.key == id_label {
+            if let Conditional::Some(id) = tls {
+                let id: &str = id.as_ref();
+                return id == self.value;
+            }
+        }
+
+        false
+    }
+}
+
+/// Parses a `key=value` label.
+impl FromStr for LabelMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        let mut parts = s.splitn(2, '=');
+        let key = parts.next().unwrap_or_default().trim();
+        let value = parts.next().unwrap_or_default().trim();
+        if key.is_empty() || value.is_empty() {
+            return Err(InvalidMatch::Empty);
+        }
+
+        Ok(LabelMatch {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        })
+    }
 }
 
 impl TryFrom<observe_request::r#match::Label> for LabelMatch {
@@ -154,6 +383,317 @@ impl TryFrom<observe_request::r#match::Label> for LabelMatch {
     }
 }
 
+// ===== impl HostMatch ======
+
+impl HostMatch {
+    fn matches_authority(&self, authority: &str) -> bool {
+        let authority = match authority.parse::<http::uri::Authority>() {
+            Ok(a) => a,
+            Err(_) => return false,
+        };
+        let host = authority.host().as_bytes();
+
+        match self {
+            HostMatch::Exact(ref h) => host.eq_ignore_ascii_case(h.as_bytes()),
+            HostMatch::Suffix(ref sfx) => {
+                let sfx = sfx.as_bytes();
+                host.len() > sfx.len() && host[host.len() - sfx.len()..].eq_ignore_ascii_case(sfx)
+            }
+        }
+    }
+}
+
+impl FromStr for HostMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(InvalidMatch::Empty);
+        }
+
+        let (host, wildcard) = if s.starts_with("*.") {
+            (&s[1..], true)
+        } else {
+            (s, false)
+        };
+        if host.contains('*') || host.contains(':') || host.len() == 1 {
+            return Err(InvalidMatch::InvalidHost);
+        }
+
+        let host = host.to_ascii_lowercase();
+        if wildcard {
+            Ok(HostMatch::Suffix(host))
+        } else {
+            Ok(HostMatch::Exact(host))
+        }
+    }
+}
+
+// ===== impl ContentTypeMatch ======
+
+impl ContentTypeMatch {
+    fn matches(&self, content_type: &str) -> bool {
+        let media_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        let (type_, subtype) = match split_media_type(&media_type) {
+            Some(t) => t,
+            None => return false,
+        };
+        // e.g. `vnd.api+json` is split into `vnd.api` and `json`.
+        let (base, suffix) = match subtype.rfind('+') {
+            Some(i) => (&subtype[..i], Some(&subtype[i + 1..])),
+            None => (subtype, None),
+        };
+
+        self.0.iter().any(|m| match m {
+            MediaTypeMatch::Type {
+                type_: ref t,
+                subtype: ref st,
+            } => t == type_ && (st == subtype || st == base || Some(st.as_str()) == suffix),
+            MediaTypeMatch::Suffix(ref sfx) => Some(sfx.as_str()) == suffix,
+        })
+    }
+}
+
+impl FromStr for ContentTypeMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        fn is_token(s: &str) -> bool {
+            !s.is_empty()
+                && s.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&b))
+        }
+
+        s.split(',')
+            .map(|m| {
+                let m = m.trim().to_ascii_lowercase();
+                if m.starts_with('+') {
+                    let sfx = &m[1..];
+                    if !is_token(sfx) || sfx.contains('+') {
+                        return Err(InvalidMatch::InvalidContentType);
+                    }
+                    return Ok(MediaTypeMatch::Suffix(sfx.to_owned()));
+                }
+
+                match split_media_type(&m) {
+                    Some((t, st)) if is_token(t) && is_token(st) => Ok(MediaTypeMatch::Type {
+                        type_: t.to_owned(),
+                        subtype: st.to_owned(),
+                    }),
+                    _ => Err(InvalidMatch::InvalidContentType),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(ContentTypeMatch)
+    }
+}
+
+fn split_media_type(media_type: &str) -> Option<(&str, &str)> {
+    let mut parts = media_type.splitn(2, '/');
+    match (parts.next(), parts.next()) {
+        (Some(t), Some(st)) if !t.is_empty() && !st.is_empty() => Some((t, st)),
+        _ => None,
+    }
+}
+
+// ===== impl GrpcMethodMatch ======
+
+impl GrpcMethodMatch {
+    fn matches_path(&self, path: &str) -> bool {
+        let mut parts = path.splitn(3, '/');
+        let (service, method) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(""), Some(service), Some(method))
+                if !service.is_empty() && !method.is_empty() && !method.contains('/') =>
+            {
+                (service, method)
+            }
+            _ => return false,
+        };
+
+        self.service.as_ref().map(|s| s == service).unwrap_or(true)
+            && self.method.as_ref().map(|m| m == method).unwrap_or(true)
+    }
+}
+
+impl FromStr for GrpcMethodMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        fn part(p: &str) -> Result<Option<String>, InvalidMatch> {
+            match p {
+                "" => Err(InvalidMatch::InvalidGrpcMethod),
+                "*" => Ok(None),
+                p if p.contains('*') => Err(InvalidMatch::InvalidGrpcMethod),
+                p => Ok(Some(p.to_owned())),
+            }
+        }
+
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(InvalidMatch::Empty);
+        }
+
+        let mut parts = s.trim_start_matches('/').splitn(2, '/');
+        match (parts.next(), parts.next()) {
+            (Some(service), Some(method)) if !method.contains('/') => Ok(GrpcMethodMatch {
+                service: part(service)?,
+                method: part(method)?,
+            }),
+            _ => Err(InvalidMatch::InvalidGrpcMethod),
+        }
+    }
+}
+
+// ===== impl MethodsMatch ======
+
+impl From<MethodsMatch> for Match {
+    fn from(MethodsMatch(methods): MethodsMatch) -> Self {
+        let any = methods
+            .into_iter()
+            .map(|m| Match::Http(HttpMatch::Method(m)))
+            .collect();
+        Match::Any(any)
+    }
+}
+
+impl FromStr for MethodsMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        s.split(',')
+            .map(|m| {
+                http::Method::from_bytes(m.trim().as_bytes())
+                    .map_err(|_| InvalidMatch::InvalidHttpMethod)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(MethodsMatch)
+    }
+}
+
+// ===== impl PathsMatch ======
+
+impl From<PathsMatch> for Match {
+    fn from(PathsMatch(paths): PathsMatch) -> Self {
+        let any = paths
+            .into_iter()
+            .map(|p| Match::Http(HttpMatch::Path(p)))
+            .collect();
+        Match::Any(any)
+    }
+}
+
+impl FromStr for PathsMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        use api::tap::observe_request::r#match::http::string_match::Match as StringMatch;
+
+        s.split(',')
+            .map(|p| {
+                let p = p.trim();
+                if !p.starts_with('/') {
+                    return Err(InvalidMatch::InvalidPath);
+                }
+                if p.ends_with('*') {
+                    let prefix = p.trim_end_matches('*');
+                    return Ok(StringMatch::Prefix(prefix.to_owned()));
+                }
+                Ok(StringMatch::Exact(p.to_owned()))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(PathsMatch)
+    }
+}
+
+// ===== impl TlsMatch ======
+
+impl TlsMatch {
+    fn matches(&self, established: bool) -> bool {
+        match self {
+            TlsMatch::Established => established,
+            TlsMatch::Disabled => !established,
+        }
+    }
+}
+
+impl FromStr for TlsMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        match s.trim() {
+            "established" => Ok(TlsMatch::Established),
+            "disabled" => Ok(TlsMatch::Disabled),
+            _ => Err(InvalidMatch::InvalidTls),
+        }
+    }
+}
+
+// ===== impl Prefilter =====
+
+impl Prefilter {
+    /// Returns `false` if no request in the given direction can match.
+    pub fn may_match(&self, is_outbound: bool) -> bool {
+        if is_outbound {
+            self.outbound
+        } else {
+            self.inbound
+        }
+    }
+}
+
+// ===== impl DirectionMatch ======
+
+impl DirectionMatch {
+    fn matches(&self, is_outbound: bool) -> bool {
+        match self {
+            DirectionMatch::Inbound => !is_outbound,
+            DirectionMatch::Outbound => is_outbound,
+        }
+    }
+}
+
+impl FromStr for DirectionMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        match s.trim() {
+            "inbound" => Ok(DirectionMatch::Inbound),
+            "outbound" => Ok(DirectionMatch::Outbound),
+            _ => Err(InvalidMatch::InvalidDirection),
+        }
+    }
+}
+
+// ===== impl GrpcStatusMatch ======
+
+impl GrpcStatusMatch {
+    pub fn matches(&self, status: Option<u32>) -> bool {
+        status.map(|s| self.0.contains(&s)).unwrap_or(false)
+    }
+}
+
+/// Parses a comma-separated list of status codes, e.g. `2,13,14`.
+impl FromStr for GrpcStatusMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        s.split(',')
+            .map(|c| {
+                c.trim()
+                    .parse::<u32>()
+                    .map_err(|_| InvalidMatch::InvalidGrpcStatus)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(GrpcStatusMatch)
+    }
+}
+
 // ===== impl TcpMatch ======
 
 impl TcpMatch {
@@ -210,6 +750,29 @@ impl NetMatch {
     }
 }
 
+/// Parses a CIDR, e.g. `10.0.0.0/8` or `fd00::/8`.
+impl FromStr for NetMatch {
+    type Err = InvalidMatch;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMatch> {
+        let mut parts = s.trim().splitn(2, '/');
+        let ip = parts.next().unwrap_or_default();
+        let mask = parts.next().ok_or(InvalidMatch::InvalidNetwork)?;
+        let mask = mask
+            .parse::<u8>()
+            .map_err(|_| InvalidMatch::InvalidNetwork)?;
+
+        match ip.parse().map_err(|_| InvalidMatch::InvalidNetwork)? {
+            net::IpAddr::V4(ip) => Ipv4Net::new(ip, mask)
+                .map(NetMatch::Net4)
+                .map_err(|_| InvalidMatch::InvalidNetwork),
+            net::IpAddr::V6(ip) => Ipv6Net::new(ip, mask)
+                .map(NetMatch::Net6)
+                .map_err(|_| InvalidMatch::InvalidNetwork),
+        }
+    }
+}
+
 impl TryFrom<observe_request::r#match::tcp::Netmask> for NetMatch {
     type Err = InvalidMatch;
 
@@ -244,9 +807,13 @@ impl TryFrom<observe_request::r#match::tcp::Netmask> for NetMatch {
 impl HttpMatch {
     fn matches<B, I: Inspect>(&self, req: &http::Request<B>, inspect: &I) -> bool {
         match self {
-            HttpMatch::Scheme(ref m) => {
-                m == req.uri().scheme_part().unwrap_or(&http::uri::Scheme::HTTP)
-            }
+            HttpMatch::Scheme(ref m) => match req.uri().scheme_part() {
+                Some(scheme) => m == scheme,
+                // Origin-form requests, e.g. most HTTP/1 requests, have no
+                // scheme, so it's inferred from the peer's TLS status.
+                None if is_peer_tls_established(req, inspect) => *m == http::uri::Scheme::HTTPS,
+                None => *m == http::uri::Scheme::HTTP,
+            },
 
             HttpMatch::Method(ref m) => m == req.method(),
 
@@ -256,6 +823,20 @@ impl HttpMatch {
                 .unwrap_or(false),
 
             HttpMatch::Path(ref m) => Self::matches_string(m, req.uri().path()),
+
+            HttpMatch::Host(ref m) => inspect
+                .authority(req)
+                .map(|a| m.matches_authority(&a))
+                .unwrap_or(false),
+
+            HttpMatch::GrpcMethod(ref m) => m.matches_path(req.uri().path()),
+
+            HttpMatch::ContentType(ref m) => req
+                .headers()
+                .get(http::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(|ct| m.matches(ct))
+                .unwrap_or(false),
         }
     }
 
@@ -317,6 +898,7 @@ mod tests {
     use quickcheck::*;
     use rand::Rng;
     use std::collections::HashMap;
+    use std::sync::Arc;
 
     use super::*;
     use api::http_types;
@@ -356,6 +938,386 @@ mod tests {
         }
     }
 
+    #[test]
+    fn net_from_str() {
+        let v4 = "10.0.0.0/8".parse::<NetMatch>().expect("ipv4 cidr");
+        assert!(v4.matches(&net::IpAddr::from([10, 1, 2, 3])));
+        assert!(!v4.matches(&net::IpAddr::from([11, 0, 0, 1])));
+        assert!(!v4.matches(&net::IpAddr::from([0xa00, 0, 0, 0, 0, 0, 0, 1])));
+
+        let v6 = "fd00::/8".parse::<NetMatch>().expect("ipv6 cidr");
+        assert!(v6.matches(&net::IpAddr::from([0xfd12, 0, 0, 0, 0, 0, 0, 1])));
+        assert!(!v6.matches(&net::IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 1])));
+        assert!(!v6.matches(&net::IpAddr::from([10, 0, 0, 1])));
+
+        for invalid in &["10.0.0.0", "10.0.0.0/33", "fd00::/129", "10.0.0/8", "/8"] {
+            assert_eq!(
+                invalid.parse::<NetMatch>().err(),
+                Some(InvalidMatch::InvalidNetwork),
+                "{} must be invalid",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn methods_from_str() {
+        assert_eq!(
+            "POST, PUT".parse(),
+            Ok(MethodsMatch(vec![http::Method::POST, http::Method::PUT]))
+        );
+        assert_eq!(
+            "GET,".parse::<MethodsMatch>(),
+            Err(InvalidMatch::InvalidHttpMethod)
+        );
+    }
+
+    #[test]
+    fn paths_from_str() {
+        use api::tap::observe_request::r#match::http::string_match::Match as StringMatch;
+
+        assert_eq!(
+            "/healthz, /metrics/*".parse(),
+            Ok(PathsMatch(vec![
+                StringMatch::Exact("/healthz".to_owned()),
+                StringMatch::Prefix("/metrics/".to_owned()),
+            ]))
+        );
+        assert_eq!(
+            "/healthz,".parse::<PathsMatch>(),
+            Err(InvalidMatch::InvalidPath)
+        );
+    }
+
+    #[test]
+    fn content_type_matches() {
+        let json = "application/json"
+            .parse::<ContentTypeMatch>()
+            .expect("must parse");
+        assert!(json.matches("application/json"));
+        assert!(json.matches("Application/JSON; charset=utf-8"));
+        assert!(json.matches("application/vnd.api+json"));
+        assert!(!json.matches("application/jsonp"));
+        assert!(!json.matches("text/json"));
+        assert!(!json.matches("application/grpc+proto"));
+
+        let grpc = "application/grpc"
+            .parse::<ContentTypeMatch>()
+            .expect("must parse");
+        assert!(grpc.matches("application/grpc"));
+        assert!(grpc.matches("application/grpc+proto"));
+        assert!(grpc.matches("application/grpc+json"));
+        assert!(!grpc.matches("application/grpc-web"));
+        assert!(!grpc.matches("application/json"));
+
+        let proto = "+proto, text/plain"
+            .parse::<ContentTypeMatch>()
+            .expect("must parse");
+        assert!(proto.matches("application/grpc+proto"));
+        assert!(proto.matches("application/vnd.example.v1+proto"));
+        assert!(proto.matches("text/plain"));
+        assert!(!proto.matches("application/x-protobuf"));
+        assert!(!proto.matches(""));
+    }
+
+    #[test]
+    fn content_type_from_str() {
+        assert_eq!(
+            "application/json".parse(),
+            Ok(ContentTypeMatch(vec![MediaTypeMatch::Type {
+                type_: "application".to_owned(),
+                subtype: "json".to_owned(),
+            }]))
+        );
+        assert_eq!(
+            "+JSON".parse(),
+            Ok(ContentTypeMatch(vec![MediaTypeMatch::Suffix(
+                "json".to_owned()
+            )]))
+        );
+        for invalid in &["", "json", "application/", "/json", "+", "a/b;c", "a/b,"] {
+            assert_eq!(
+                invalid.parse::<ContentTypeMatch>(),
+                Err(InvalidMatch::InvalidContentType),
+                "{:?} must not parse",
+                invalid,
+            );
+        }
+    }
+
+    #[test]
+    fn host_wildcard() {
+        let m = "*.example.com".parse::<HostMatch>().expect("must parse");
+        assert!(m.matches_authority("web.example.com"));
+        assert!(m.matches_authority("web.EXAMPLE.com:8080"));
+        assert!(m.matches_authority("a.b.example.com"));
+        assert!(!m.matches_authority("example.com"));
+        assert!(!m.matches_authority("badexample.com"));
+        assert!(!m.matches_authority(""));
+    }
+
+    #[test]
+    fn host_exact() {
+        let m = "Example.com".parse::<HostMatch>().expect("must parse");
+        assert!(m.matches_authority("example.com"));
+        assert!(m.matches_authority("example.com:80"));
+        assert!(!m.matches_authority("web.example.com"));
+    }
+
+    #[test]
+    fn host_from_str() {
+        assert_eq!("".parse::<HostMatch>(), Err(InvalidMatch::Empty));
+        assert_eq!("*".parse::<HostMatch>(), Err(InvalidMatch::InvalidHost));
+        assert_eq!("*.".parse::<HostMatch>(), Err(InvalidMatch::InvalidHost));
+        assert_eq!(
+            "a.*.com".parse::<HostMatch>(),
+            Err(InvalidMatch::InvalidHost)
+        );
+        assert_eq!(
+            "a.com:80".parse::<HostMatch>(),
+            Err(InvalidMatch::InvalidHost)
+        );
+    }
+
+    #[test]
+    fn grpc_method_matches() {
+        let m = "pkg.Greeter/SayHello"
+            .parse::<GrpcMethodMatch>()
+            .expect("must parse");
+        assert!(m.matches_path("/pkg.Greeter/SayHello"));
+        assert!(!m.matches_path("/pkg.Greeter/SayGoodbye"));
+        assert!(!m.matches_path("/pkg.Other/SayHello"));
+        assert!(!m.matches_path("/pkg.Greeter/SayHello/more"));
+        assert!(!m.matches_path("/pkg.Greeter"));
+        assert!(!m.matches_path("/"));
+
+        let m = "pkg.Greeter/*"
+            .parse::<GrpcMethodMatch>()
+            .expect("must parse");
+        assert!(m.matches_path("/pkg.Greeter/SayHello"));
+        assert!(m.matches_path("/pkg.Greeter/SayGoodbye"));
+        assert!(!m.matches_path("/pkg.Other/SayHello"));
+
+        let m = "*/SayHello".parse::<GrpcMethodMatch>().expect("must parse");
+        assert!(m.matches_path("/pkg.Greeter/SayHello"));
+        assert!(m.matches_path("/pkg.Other/SayHello"));
+        assert!(!m.matches_path("/pkg.Greeter/"));
+    }
+
+    #[test]
+    fn grpc_method_from_str() {
+        assert_eq!(
+            "/pkg.Greeter/SayHello".parse(),
+            Ok(GrpcMethodMatch {
+                service: Some("pkg.Greeter".to_owned()),
+                method: Some("SayHello".to_owned()),
+            })
+        );
+        assert_eq!("".parse::<GrpcMethodMatch>(), Err(InvalidMatch::Empty));
+        for invalid in &[
+            "pkg.Greeter",
+            "pkg.Greeter/",
+            "/SayHello",
+            "a/b/c",
+            "pkg.*/SayHello",
+        ] {
+            assert_eq!(
+                invalid.parse::<GrpcMethodMatch>(),
+                Err(InvalidMatch::InvalidGrpcMethod),
+                "{} must not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn tls_from_str() {
+        assert_eq!("established".parse(), Ok(TlsMatch::Established));
+        assert_eq!("disabled".parse(), Ok(TlsMatch::Disabled));
+        assert_eq!("true".parse::<TlsMatch>(), Err(InvalidMatch::InvalidTls));
+    }
+
+    #[test]
+    fn direction_from_str() {
+        assert_eq!("inbound".parse(), Ok(DirectionMatch::Inbound));
+        assert_eq!("outbound".parse(), Ok(DirectionMatch::Outbound));
+        assert_eq!(
+            "both".parse::<DirectionMatch>(),
+            Err(InvalidMatch::InvalidDirection)
+        );
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        use self::observe_request::r#match;
+
+        fn nest(depth: usize) -> Option<observe_request::Match> {
+            let leaf = r#match::Match::Destination(r#match::Tcp {
+                r#match: Some(r#match::tcp::Match::Ports(r#match::tcp::PortRange {
+                    min: 80,
+                    max: 80,
+                })),
+            });
+            let m = (1..depth).fold(leaf, |m, _| {
+                r#match::Match::All(r#match::Seq {
+                    matches: vec![observe_request::Match { r#match: Some(m) }],
+                })
+            });
+            Some(observe_request::Match { r#match: Some(m) })
+        }
+
+        assert!(Match::try_new(nest(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Match::try_new(nest(MAX_DEPTH + 1)).err(),
+            Some(InvalidMatch::TooDeep)
+        );
+    }
+
+    #[test]
+    fn prefilters_by_direction() {
+        let inbound = Match::Direction(DirectionMatch::Inbound);
+        let outbound = Match::Direction(DirectionMatch::Outbound);
+        let get = Match::Http(HttpMatch::Method(http::Method::GET));
+
+        let p = Match::All(vec![inbound.clone(), get.clone()]).prefilter();
+        assert!(p.may_match(false));
+        assert!(!p.may_match(true));
+
+        let p = Match::Any(vec![outbound.clone(), get.clone()]).prefilter();
+        assert!(p.may_match(false) && p.may_match(true));
+
+        let p = Match::Not(Box::new(outbound)).prefilter();
+        assert!(p.may_match(false));
+        assert!(!p.may_match(true));
+
+        let p = Match::All(vec![inbound, Match::Not(Box::new(get))]).prefilter();
+        assert!(!p.may_match(true));
+    }
+
+    /// Inspects inbound requests, whose peers' identities, if any, are stored
+    /// in their extensions.
+    struct Inbound;
+
+    impl Inspect for Inbound {
+        fn src_addr<B>(&self, _: &http::Request<B>) -> Option<net::SocketAddr> {
+            None
+        }
+
+        fn src_tls<'a, B>(
+            &self,
+            req: &'a http::Request<B>,
+        ) -> Conditional<&'a identity::Name, ReasonForNoIdentity> {
+            match req.extensions().get::<identity::Name>() {
+                Some(id) => Conditional::Some(id),
+                None => Conditional::None(ReasonForNoIdentity::Disabled),
+            }
+        }
+
+        fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<net::SocketAddr> {
+            None
+        }
+
+        fn dst_labels<B>(&self, _: &http::Request<B>) -> Option<&IndexMap<String, String>> {
+            None
+        }
+
+        fn dst_tls<B>(
+            &self,
+            _: &http::Request<B>,
+        ) -> Conditional<&identity::Name, ReasonForNoIdentity> {
+            Conditional::None(ReasonForNoIdentity::Disabled)
+        }
+
+        fn route_labels<B>(&self, _: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
+            None
+        }
+
+        fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn matches_all_requests_except_health_checks() {
+        use api::tap::observe_request::r#match::http::string_match;
+
+        let healthz = Match::Http(HttpMatch::Path(string_match::Match::Exact(
+            "/healthz".to_owned(),
+        )));
+        let m = Match::All(vec![
+            Match::Direction(DirectionMatch::Inbound),
+            Match::Not(Box::new(healthz.clone())),
+        ]);
+
+        let req = http::Request::get("/api").body(()).unwrap();
+        assert!(m.matches(&req, &Inbound));
+        let req = http::Request::get("/healthz").body(()).unwrap();
+        assert!(!m.matches(&req, &Inbound));
+
+        // Negations nest within both `All` and `Any`.
+        let m = Match::Not(Box::new(Match::Any(vec![
+            Match::Not(Box::new(healthz)),
+            Match::Direction(DirectionMatch::Outbound),
+        ])));
+        assert!(m.matches(&req, &Inbound));
+    }
+
+    #[test]
+    fn matches_schemes() {
+        let http_match = Match::Http(HttpMatch::Scheme(http::uri::Scheme::HTTP));
+        let https_match = Match::Http(HttpMatch::Scheme(http::uri::Scheme::HTTPS));
+
+        let req = http::Request::get("http://web.example.com/")
+            .body(())
+            .unwrap();
+        assert!(http_match.matches(&req, &Inbound));
+        assert!(!https_match.matches(&req, &Inbound));
+
+        let req = http::Request::get("https://web.example.com/")
+            .body(())
+            .unwrap();
+        assert!(!http_match.matches(&req, &Inbound));
+        assert!(https_match.matches(&req, &Inbound));
+    }
+
+    #[test]
+    fn matches_schemes_by_tls_without_explicit_scheme() {
+        let http_match = Match::Http(HttpMatch::Scheme(http::uri::Scheme::HTTP));
+        let https_match = Match::Http(HttpMatch::Scheme(http::uri::Scheme::HTTPS));
+
+        let req = http::Request::get("/").body(()).unwrap();
+        assert!(http_match.matches(&req, &Inbound));
+        assert!(!https_match.matches(&req, &Inbound));
+
+        let id = identity::Name::from_hostname(b"web.ns.serviceaccount.identity.linkerd.local")
+            .expect("name must be valid");
+        let mut req = http::Request::get("/").body(()).unwrap();
+        req.extensions_mut().insert(id);
+        assert!(!http_match.matches(&req, &Inbound));
+        assert!(https_match.matches(&req, &Inbound));
+    }
+
+    #[test]
+    fn matches_requests_by_direction_and_host() {
+        let m = Match::All(vec![
+            Match::Direction("inbound".parse().expect("direction must parse")),
+            Match::Http(HttpMatch::Host(
+                "*.example.com".parse().expect("host must parse"),
+            )),
+        ]);
+
+        let req = http::Request::get("http://web.example.com/")
+            .body(())
+            .unwrap();
+        assert!(m.matches(&req, &Inbound));
+
+        let req = http::Request::get("http://example.com/").body(()).unwrap();
+        assert!(!m.matches(&req, &Inbound));
+
+        let outbound = Match::Direction("outbound".parse().expect("direction must parse"));
+        assert!(!outbound.matches(&req, &Inbound));
+    }
+
     quickcheck! {
         fn tcp_from_proto(tcp: observe_request::r#match::Tcp) -> bool {
             use self::observe_request::r#match::tcp;
@@ -416,6 +1378,34 @@ mod tests {
             l.matches(&IndexMap::from_iter(labels.into_iter())) == matches
         }
 
+        fn label_from_str(key: String, value: String) -> TestResult {
+            if key.contains('=') {
+                return TestResult::discard();
+            }
+
+            let expected = if key.trim().is_empty() || value.trim().is_empty() {
+                Err(InvalidMatch::Empty)
+            } else {
+                Ok((key.trim().to_owned(), value.trim().to_owned()))
+            };
+
+            let parsed = format!("{}={}", key, value)
+                .parse::<LabelMatch>()
+                .map(|l| (l.key, l.value));
+            TestResult::from_bool(parsed == expected)
+        }
+
+        fn grpc_status_matches(codes: Vec<u32>, status: Option<u32>) -> bool {
+            let s = codes.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(",");
+            match s.parse::<GrpcStatusMatch>() {
+                Err(e) => codes.is_empty() && e == InvalidMatch::InvalidGrpcStatus,
+                Ok(m) => {
+                    let expected = status.map(|s| codes.contains(&s)).unwrap_or(false);
+                    m.matches(status) == expected
+                }
+            }
+        }
+
         fn http_from_proto(http: observe_request::r#match::Http) -> bool {
             use self::observe_request::r#match::http;
 
@@ -470,6 +1460,14 @@ impl fmt::Display for InvalidMatch {
                 InvalidMatch::InvalidNetwork => "invalid network address",
                 InvalidMatch::InvalidHttpMethod => "invalid http method",
                 InvalidMatch::InvalidScheme => "invalid request scheme",
+                InvalidMatch::InvalidGrpcStatus => "invalid grpc status",
+                InvalidMatch::InvalidTls => "invalid tls status",
+                InvalidMatch::InvalidHost => "invalid host",
+                InvalidMatch::InvalidDirection => "invalid proxy direction",
+                InvalidMatch::InvalidGrpcMethod => "invalid grpc method",
+                InvalidMatch::InvalidPath => "invalid path",
+                InvalidMatch::InvalidContentType => "invalid content type",
+                InvalidMatch::TooDeep => "match is nested too deeply",
             }
         )
     }
@@ -1,4 +1,94 @@
 mod match_;
+mod preview;
 mod server;
 
-pub use self::server::{Server, Tap};
+pub use self::match_::{
+    DirectionMatch, HostMatch, HttpMatch, InvalidMatch, Match, MethodsMatch, TlsMatch,
+};
+pub use self::server::{Server, Sessions, Tap};
+
+// Labels that describe a peer's TLS status in tap events.
+const TLS_LABEL: &str = "tls";
+const CLIENT_ID_LABEL: &str = "client_id";
+const SERVER_ID_LABEL: &str = "server_id";
+
+// Labels a failed stream's final event with the kind of error that caused it,
+// e.g. `timeout`.
+const ERROR_LABEL: &str = "error";
+
+// The `error` of a stream that was abandoned because the proxy shut down.
+const PROXY_SHUTDOWN_ERROR: &str = "proxy_shutdown";
+
+// Labels a gRPC stream's final event with its decoded `grpc-message`.
+const GRPC_MESSAGE_LABEL: &str = "grpc_message";
+
+// Labels a stream's final event with the percent-encoded preview of its
+// request and response bodies.
+const REQUEST_BODY_PREVIEW_LABEL: &str = "request_body_preview";
+const RESPONSE_BODY_PREVIEW_LABEL: &str = "response_body_preview";
+
+// Labels a stream's final event with the number of data frames in its response
+// body and the size of the largest, when frames are summarized.
+const RESPONSE_FRAMES_LABEL: &str = "response_frames";
+const RESPONSE_MAX_FRAME_BYTES_LABEL: &str = "response_max_frame_bytes";
+
+// Labels each of a stream's events with the value of the request's
+// correlation header, e.g. its `x-request-id`.
+const CORRELATION_ID_LABEL: &str = "correlation_id";
+
+// Labels each request with the protocol with which it is proxied, e.g. `h2`,
+// and whether it is carried between proxies by an `l5d-orig-proto` upgrade.
+const PROTOCOL_LABEL: &str = "protocol";
+const ORIG_PROTO_LABEL: &str = "orig_proto";
+
+// Labels a request's `RequestInit` event with the size of its body given by
+// its `content-length` header, if valid, so that it may be compared with the
+// number of bytes that are actually read. Consolidated streams label their
+// final event instead.
+const REQUEST_CONTENT_LENGTH_LABEL: &str = "request_content_length";
+
+// Labels a request routed by a service profile with the profile's name.
+const PROFILE_LABEL: &str = "profile";
+
+// Labels a retried request with the number of times it has been retried.
+const RETRIES_LABEL: &str = "retries";
+
+// Labels a response that was generated by the proxy, rather than received
+// from an upstream server.
+const PROXY_RESPONSE_LABEL: &str = "proxy_response";
+
+// Labels a response that was generated by the proxy with the reason it gives
+// in its `l5d-proxy-error` header, e.g. why the request was rejected. The
+// header is only set when LINKERD2_PROXY_ERROR_HEADER_ENABLED is.
+const PROXY_ERROR_LABEL: &str = "proxy_error";
+
+// Labels each stream event with the wall-clock time at which it occurred, in
+// microseconds since the Unix epoch, so that it may be correlated with other
+// logs.
+pub(super) const TIMESTAMP_LABEL: &str = "timestamp_us";
+
+// Labels the first event of a described session with the parameters that the
+// server applied to it. The timeout is given in milliseconds.
+const SESSION_ID_LABEL: &str = "session_id";
+const SESSION_LIMIT_LABEL: &str = "session_limit";
+const SESSION_MATCH_LABEL: &str = "session_match";
+const SESSION_DIRECTION_LABEL: &str = "session_direction";
+const SESSION_TIMEOUT_LABEL: &str = "session_timeout_ms";
+
+// Labels a lossy tap's final event with the number of events it dropped.
+const DROPPED_EVENTS_LABEL: &str = "dropped_events";
+
+// Labels a rate-limited tap's final event with the number of matching requests
+// that were not tapped because the rate was exceeded.
+const RATE_LIMITED_LABEL: &str = "rate_limited";
+
+// Labels the event emitted when a request body is abandoned before it ends
+// with the ID of its stream.
+const REQUEST_CANCELED_LABEL: &str = "request_canceled";
+
+// Labels that describe a consolidated stream's request and response on its
+// `ResponseEnd` event.
+const METHOD_LABEL: &str = "method";
+const AUTHORITY_LABEL: &str = "authority";
+const PATH_LABEL: &str = "path";
+const HTTP_STATUS_LABEL: &str = "http_status";
@@ -0,0 +1,178 @@
+use bytes::Buf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The most bytes of a body that a tap may preview.
+pub const MAX_LEN: usize = 4096;
+
+/// The most bytes that a tap session's previews may hold at once, unless the
+/// client configures its own budget.
+pub const DEFAULT_BUDGET: usize = 1024 * 1024;
+
+/// Describes which bytes of each tapped body are previewed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BodyPreview {
+    offset: usize,
+    len: usize,
+}
+
+/// Limits the bytes held by all of a tap session's previews at once, so that
+/// a tap of many concurrent streams cannot exhaust the proxy's memory.
+#[derive(Clone, Debug)]
+pub struct Budget(Arc<AtomicUsize>);
+
+/// Accumulates the previewed bytes of a body as its data frames are tapped.
+#[derive(Debug)]
+pub struct Preview {
+    config: BodyPreview,
+    /// The number of bytes of the body that have been tapped so far.
+    seen: usize,
+    bytes: Vec<u8>,
+    /// The preview's buffer is reserved from the budget until it is dropped.
+    budget: Budget,
+}
+
+// === impl BodyPreview ===
+
+impl BodyPreview {
+    /// Previews up to `len` bytes of each body, after skipping `offset` bytes.
+    pub fn new(offset: usize, len: usize) -> Result<Self, String> {
+        if len == 0 {
+            return Err("preview length must be positive".into());
+        }
+        if len > MAX_LEN {
+            return Err(format!("preview length must not exceed {}", MAX_LEN));
+        }
+        Ok(Self { offset, len })
+    }
+}
+
+// === impl Budget ===
+
+impl Budget {
+    pub fn new(max: usize) -> Self {
+        Budget(Arc::new(AtomicUsize::new(max)))
+    }
+
+    /// Reserves `len` bytes, if they are all available.
+    fn reserve(&self, len: usize) -> bool {
+        let mut available = self.0.load(Ordering::Acquire);
+        loop {
+            if available < len {
+                return false;
+            }
+            let prior = self
+                .0
+                .compare_and_swap(available, available - len, Ordering::AcqRel);
+            if prior == available {
+                return true;
+            }
+            available = prior;
+        }
+    }
+
+    fn release(&self, len: usize) {
+        self.0.fetch_add(len, Ordering::AcqRel);
+    }
+}
+
+// === impl Preview ===
+
+impl Preview {
+    /// Reserves a preview's buffer from `budget`.
+    ///
+    /// If the budget is exhausted, the body is not previewed.
+    pub fn new(config: BodyPreview, budget: &Budget) -> Option<Self> {
+        if !budget.reserve(config.len) {
+            trace!("tap; preview budget exhausted");
+            return None;
+        }
+
+        Some(Self {
+            config,
+            seen: 0,
+            bytes: Vec::with_capacity(config.len),
+            budget: budget.clone(),
+        })
+    }
+
+    /// Records the previewed portion of a data frame, which may straddle the
+    /// preview's offset.
+    ///
+    /// Only the frame's first contiguous chunk is read, which holds the whole
+    /// frame for the contiguous buffers that HTTP bodies produce.
+    pub fn record<B: Buf>(&mut self, data: &B) {
+        let chunk = data.bytes();
+        let wanted = self.config.len - self.bytes.len();
+        let skip = self.config.offset.saturating_sub(self.seen);
+        if wanted > 0 && skip < chunk.len() {
+            let available = &chunk[skip..];
+            let n = wanted.min(available.len());
+            self.bytes.extend_from_slice(&available[..n]);
+        }
+        self.seen = self.seen.saturating_add(data.remaining());
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        self.budget.release(self.config.len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn record_frames(preview: BodyPreview, frames: &[&'static str]) -> Vec<u8> {
+        let mut p = Preview::new(preview, &Budget::new(MAX_LEN)).expect("budget must suffice");
+        for frame in frames {
+            p.record(&Cursor::new(frame.as_bytes()));
+        }
+        p.bytes().to_vec()
+    }
+
+    #[test]
+    fn previews_from_the_start() {
+        let preview = BodyPreview::new(0, 4).unwrap();
+        assert_eq!(record_frames(preview, &["ab", "cdef"]), b"abcd");
+    }
+
+    #[test]
+    fn previews_across_the_offset() {
+        let preview = BodyPreview::new(3, 4).unwrap();
+        assert_eq!(record_frames(preview, &["ab", "cdef", "ghi"]), b"defg");
+        assert_eq!(record_frames(preview, &["abcdefghi"]), b"defg");
+        assert_eq!(record_frames(preview, &["abc", "d", "efghi"]), b"defg");
+    }
+
+    #[test]
+    fn previews_short_bodies() {
+        let preview = BodyPreview::new(2, 4).unwrap();
+        assert_eq!(record_frames(preview, &["a"]), b"");
+        assert_eq!(record_frames(preview, &["a", "bcd"]), b"cd");
+    }
+
+    #[test]
+    fn budget_limits_concurrent_previews() {
+        let preview = BodyPreview::new(0, 4).unwrap();
+        let budget = Budget::new(6);
+
+        let first = Preview::new(preview, &budget).expect("budget must suffice");
+        assert!(Preview::new(preview, &budget).is_none());
+
+        drop(first);
+        assert!(Preview::new(preview, &budget).is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_lengths() {
+        assert!(BodyPreview::new(0, 0).is_err());
+        assert!(BodyPreview::new(0, MAX_LEN + 1).is_err());
+    }
+}
@@ -1,57 +1,222 @@
 use bytes::Buf;
 use futures::sync::mpsc;
+use futures::task::AtomicTask;
 use futures::{future, Async, Future, Poll, Stream};
 use hyper::body::Payload;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Weak};
-use std::time::Instant;
-use tokio_timer::clock;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_timer::{clock, Delay};
+use tower_grpc::metadata::KeyRef;
 use tower_grpc::{self as grpc, Response};
 
 use api::{http_types, pb_duration, tap as api};
 
-use super::match_::Match;
-use proxy::http::HasH2Reason;
-use tap::{iface, Inspect};
+use super::match_::{
+    ContentTypeMatch, DirectionMatch, GrpcMethodMatch, GrpcStatusMatch, HostMatch, HttpMatch,
+    InvalidMatch, LabelMatch, Match, MethodsMatch, NetMatch, PathsMatch, Prefilter, TcpMatch,
+    TlsMatch,
+};
+use super::preview::{self, BodyPreview, Preview};
+use proxy::http::{retry, HasH2Reason, ProxyResponse, L5D_PROXY_ERROR};
+use tap::json::Object;
+use tap::{self, iface, Inspect};
 use Conditional;
 
 #[derive(Clone, Debug)]
 pub struct Server<T> {
     subscribe: T,
     base_id: Arc<AtomicUsize>,
+    /// Active tap sessions, by base ID, so that they may be paused.
+    sessions: Arc<Mutex<HashMap<u32, Weak<Shared>>>>,
+    /// Labels events with a fixed direction, if set, rather than the
+    /// direction that each request is inspected to have.
+    direction: Option<DirectionMatch>,
+    /// Whether clients may request that events be delivered with
+    /// backpressure.
+    backpressure: bool,
 }
 
+/// A handle on a server's active tap sessions, so that they may be described,
+/// e.g. by the admin server.
+#[derive(Clone, Debug, Default)]
+pub struct Sessions(Arc<Mutex<HashMap<u32, Weak<Shared>>>>);
+
 #[derive(Debug)]
 pub struct ResponseFuture<F> {
     subscribe: F,
     events_rx: Option<mpsc::Receiver<api::TapEvent>>,
     shared: Option<Arc<Shared>>,
+    inflight: Option<Arc<Inflight>>,
+    opened: Option<api::TapEvent>,
+    report_drops: bool,
 }
 
 #[derive(Debug)]
 pub struct ResponseStream {
     events_rx: mpsc::Receiver<api::TapEvent>,
     shared: Option<Arc<Shared>>,
+    inflight: Arc<Inflight>,
+    /// Fires when the client's deadline passes, so that the stream stops
+    /// tapping new requests even if no events are emitted.
+    deadline: Option<Delay>,
+    /// Fires when the session may have been idle for its idle timeout.
+    idle: Option<Delay>,
+    /// Describes the session, if requested, before any other event.
+    opened: Option<api::TapEvent>,
+    /// Whether the stream ends with a `dropped_event` if the session was
+    /// lossy or rate-limited.
+    report_drops: bool,
 }
 
 #[derive(Debug)]
 struct Shared {
     base_id: u32,
+    /// Authorizes calls that control the session, e.g. to pause it.
+    token: SessionToken,
     count: AtomicUsize,
     limit: usize,
+    /// No more requests are tapped once the client's deadline has passed.
+    deadline: Option<Instant>,
     match_: Match,
+    inflight: InflightHandle,
+    /// While set, no requests are tapped, but the session remains registered.
+    paused: AtomicBool,
+    /// Bounds the streams that may be tapped at once, since each holds a
+    /// handle on the response stream's buffer.
+    max_streams: Option<usize>,
+    /// Bounds the rate at which matching requests are tapped.
+    rate_limit: Option<RateLimit>,
+    /// No more requests are tapped once none have been tapped for a while.
+    idle: Option<Idle>,
+    /// Additional response streams that receive the session's events.
+    tees: Mutex<Vec<TeeTx>>,
+}
+
+/// A random secret that is returned to the client that opened a session, so
+/// that other clients cannot control the session by guessing its base ID.
+struct SessionToken(String);
+
+/// Tracks when a session last emitted events, so that sessions that never
+/// match are not registered indefinitely.
+#[derive(Debug)]
+struct Idle {
+    timeout: Duration,
+    last_event_at: Mutex<Instant>,
+}
+
+/// A token bucket that admits up to `per_second` requests each second,
+/// allowing bursts of up to `per_second` requests.
+#[derive(Debug)]
+struct RateLimit {
+    per_second: usize,
+    bucket: Mutex<Bucket>,
 }
 
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Notifies the response stream as tapped streams complete, so that it may
+/// close once all of its tapped streams have completed.
+#[derive(Debug, Default)]
+struct Inflight {
+    task: AtomicTask,
+    /// Counts events that could not be buffered and were therefore lost.
+    dropped: AtomicUsize,
+    /// Counts the tapped streams that may still emit events.
+    streams: AtomicUsize,
+    /// Counts matching requests that were not tapped due to the rate limit.
+    rate_limited: AtomicUsize,
+}
+
+/// Held by each tapped stream (and by `Shared`) while it may emit events.
+#[derive(Debug)]
+struct InflightHandle(Arc<Inflight>);
+
+/// Shared by a tapped stream's request and response, so that the stream is
+/// counted as active until both have completed.
+#[derive(Debug)]
+struct ActiveStream(InflightHandle);
+
 #[derive(Clone, Debug)]
 struct TapTx {
     id: api::tap_event::http::StreamId,
     tx: mpsc::Sender<api::TapEvent>,
+    delivery: Delivery,
+    stream: Arc<ActiveStream>,
+    /// The session's tees when the stream was tapped.
+    tees: Vec<TeeTx>,
+}
+
+/// Sends a session's events to another call's response stream.
+///
+/// Tees never apply backpressure, so that a slow client cannot stall the
+/// session; events that a tee cannot buffer are dropped and counted on the
+/// tee's own stream.
+#[derive(Clone, Debug)]
+struct TeeTx {
+    tx: mpsc::Sender<api::TapEvent>,
+    inflight: InflightHandle,
 }
 
 #[derive(Clone, Debug)]
 pub struct Tap {
     events_tx: mpsc::Sender<api::TapEvent>,
     shared: Weak<Shared>,
+    prefilter: Prefilter,
+    delivery: Delivery,
+    events: Events,
+    grpc_status: Option<Arc<GrpcStatusMatch>>,
+    body_preview: Option<BodyPreview>,
+    /// Shared by all of the session's tapped streams.
+    preview_budget: preview::Budget,
+    correlation_header: Option<http::header::HeaderName>,
+    /// Whether the frames of each response body are summarized.
+    frames: bool,
+    /// Whether abandoned request bodies are reported.
+    request_cancels: bool,
+    direction: Option<DirectionMatch>,
+    /// Holds the session of a tap that was created without a response
+    /// stream, which otherwise holds it.
+    session: Option<Arc<Shared>>,
+}
+
+/// Determines how events are delivered to a tap's response stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Delivery {
+    /// Events are dropped when the response stream's buffer is full.
+    Lossy,
+
+    /// Tapped responses and bodies are not processed until the response
+    /// stream has capacity to receive their events. Requests that are not
+    /// tapped are never delayed.
+    Backpressure,
+}
+
+/// Determines which events are emitted for each tapped stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Events {
+    /// Events are emitted as each stream's request is initiated and as its
+    /// response is initiated and ends.
+    Phases,
+
+    /// Only the `ResponseEnd` event is emitted, labeled with the request's
+    /// method, authority, and path and the response's status.
+    Consolidated,
+}
+
+/// Holds a stream's events until its `grpc-status` is known, so that streams
+/// that do not match are not reported at all.
+#[derive(Debug)]
+struct Deferred {
+    grpc_status: Arc<GrpcStatusMatch>,
+    events: Vec<api::TapEvent>,
 }
 
 #[derive(Debug)]
@@ -59,12 +224,24 @@ pub struct TapResponse {
     base_event: api::TapEvent,
     request_init_at: Instant,
     tap: TapTx,
+    deferred: Option<Deferred>,
+    events: Events,
+    body_preview: Option<BodyPreview>,
+    preview_budget: preview::Budget,
+    request_preview: Option<Arc<Mutex<Preview>>>,
+    frames: bool,
 }
 
 #[derive(Debug)]
 pub struct TapRequestPayload {
     base_event: api::TapEvent,
     tap: TapTx,
+    // Streams that may not match a `grpc-status` are not reported until their
+    // response ends, so their request bodies are not reported at all.
+    deferred: bool,
+    // Shared with the response so that it may be reported when it ends.
+    preview: Option<Arc<Mutex<Preview>>>,
+    report_cancel: bool,
 }
 
 #[derive(Debug)]
@@ -76,18 +253,388 @@ pub struct TapResponsePayload {
     tap: TapTx,
     // Response-headers may include grpc-status when there is no response body.
     grpc_status: Option<u32>,
+    grpc_message: Option<String>,
+    deferred: Option<Deferred>,
+    preview: Option<Preview>,
+    request_preview: Option<Arc<Mutex<Preview>>>,
+    frames: Option<Frames>,
+}
+
+/// Summarizes the data frames of a response body, e.g. to identify backends
+/// that stream data in many small frames.
+#[derive(Debug, Default)]
+struct Frames {
+    count: usize,
+    max_bytes: usize,
 }
 
+/// Tap options that are not part of the `ObserveRequest` API, and are
+/// therefore read from the request's metadata.
+#[derive(Debug, Default)]
+struct Options {
+    delivery: Delivery,
+    events: Events,
+    grpc_status: Option<GrpcStatusMatch>,
+    source_label: Option<LabelMatch>,
+    source_net: Option<NetMatch>,
+    destination_net: Option<NetMatch>,
+    tls: Option<TlsMatch>,
+    methods: Option<MethodsMatch>,
+    excluded_paths: Option<PathsMatch>,
+    host: Option<HostMatch>,
+    grpc_method: Option<GrpcMethodMatch>,
+    content_type: Option<ContentTypeMatch>,
+    direction: Option<DirectionMatch>,
+    body_preview: Option<BodyPreview>,
+    preview_budget: usize,
+    max_streams: Option<usize>,
+    max_events_per_second: Option<usize>,
+    timeout: Option<GrpcTimeout>,
+    correlation_header: Option<http::header::HeaderName>,
+    frames: bool,
+    idle_timeout: Option<GrpcTimeout>,
+    describe_session: bool,
+    report_drops: bool,
+    request_cancels: bool,
+}
+
+/// A `grpc-timeout`, as described by the gRPC spec, e.g. `30S`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct GrpcTimeout(Duration);
+
+/// Describes why an `ObserveRequest`'s limit is invalid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum InvalidLimit {
+    Zero,
+}
+
+// Tap options that are not part of the `ObserveRequest` API are read from the
+// call's `l5d-tap-*` metadata. Each key is documented below and listed in
+// `METADATA_KEYS`; calls with any other `l5d-tap-*` key are rejected, so that
+// a misspelled option is not silently ignored.
+
+/// The prefix of the metadata keys of all tap options.
+const METADATA_KEY_PREFIX: &str = "l5d-tap-";
+
+/// Selects how events are delivered to the response stream, e.g.
+/// `backpressure`, if the proxy allows it. Defaults to `lossy`.
+const DELIVERY_METADATA_KEY: &str = "l5d-tap-delivery";
+
+/// Set by gRPC clients to limit the duration of the call.
+const GRPC_TIMEOUT_METADATA_KEY: &str = "grpc-timeout";
+
+/// Selects which events are emitted for each tapped stream, e.g.
+/// `consolidated`. Defaults to `phases`.
+const EVENTS_METADATA_KEY: &str = "l5d-tap-events";
+
+/// Restricts the tap to streams that end with one of a comma-separated list
+/// of `grpc-status` codes, e.g. `2,13,14`.
+const GRPC_STATUS_METADATA_KEY: &str = "l5d-tap-grpc-status";
+
+/// Restricts the tap to requests whose source has a `key=value` label, e.g.
+/// `tls=true`.
+const SOURCE_LABEL_METADATA_KEY: &str = "l5d-tap-source-label";
+
+/// Restricts the tap to requests from addresses in a CIDR, e.g. `10.0.0.0/8`.
+const SOURCE_NET_METADATA_KEY: &str = "l5d-tap-source-net";
+
+/// Restricts the tap to requests to addresses in a CIDR, e.g. `fd00::/8`.
+const DESTINATION_NET_METADATA_KEY: &str = "l5d-tap-destination-net";
+
+/// Restricts the tap to requests with (`established`) or without
+/// (`disabled`) mTLS to the proxy's peer.
+const TLS_METADATA_KEY: &str = "l5d-tap-tls";
+
+/// Restricts the tap to requests with any of a comma-separated list of HTTP
+/// methods, e.g. `POST,PUT`.
+const METHODS_METADATA_KEY: &str = "l5d-tap-methods";
+
+/// Skips requests for any of a comma-separated list of paths, even if they
+/// otherwise match, e.g. `/healthz,/metrics/*`. A path ending in `*` excludes
+/// any path with its prefix. By default, no paths are excluded.
+const EXCLUDE_PATHS_METADATA_KEY: &str = "l5d-tap-exclude-paths";
+
+/// Restricts the tap to requests for a host, which may be a wildcard like
+/// `*.example.com`.
+const HOST_METADATA_KEY: &str = "l5d-tap-host";
+
+/// Restricts the tap to gRPC requests for a `package.Service/Method`, where
+/// either part may be a `*` wildcard, e.g. `pkg.Greeter/*`.
+const GRPC_METHOD_METADATA_KEY: &str = "l5d-tap-grpc-method";
+
+/// Restricts the tap to requests whose `content-type` has any of a
+/// comma-separated list of media types, e.g. `application/json,+proto`. A
+/// media type also matches its `+json`- or `+proto`-suffixed variants.
+const CONTENT_TYPE_METADATA_KEY: &str = "l5d-tap-content-type";
+
+/// Restricts the tap to `inbound` or `outbound` requests. By default, both
+/// directions are tapped.
+const DIRECTION_METADATA_KEY: &str = "l5d-tap-direction";
+
+/// Reports up to this many bytes of each tapped request and response body.
+const BODY_PREVIEW_LEN_METADATA_KEY: &str = "l5d-tap-body-preview-len";
+
+/// Skips this many bytes of each body before it is previewed, e.g. to skip a
+/// message's framing. Requires a preview length.
+const BODY_PREVIEW_OFFSET_METADATA_KEY: &str = "l5d-tap-body-preview-offset";
+
+/// Limits the bytes held by all of a session's body previews at once. Once
+/// the budget is exhausted, bodies are not previewed until earlier previews
+/// are reported. Requires a preview length.
+const BODY_PREVIEW_BUDGET_METADATA_KEY: &str = "l5d-tap-body-preview-budget";
+
+/// When `true`, each stream's final event is labeled with the number of data
+/// frames in its response body and the size of the largest. By default, only
+/// the total number of bytes is reported.
+const RESPONSE_FRAMES_METADATA_KEY: &str = "l5d-tap-response-frames";
+
+/// Limits the number of streams that may be tapped at once. While this many
+/// tapped streams are active, e.g. because the client is slow to read their
+/// events, further requests are not tapped. By default, there is no limit.
+const MAX_STREAMS_METADATA_KEY: &str = "l5d-tap-max-streams";
+
+/// Limits the rate at which requests are tapped, e.g. so that a burst of
+/// matching traffic does not flood an interactive client. Matching requests
+/// that exceed the rate are skipped and counted in the final event. By
+/// default, there is no limit.
+const MAX_EVENTS_PER_SECOND_METADATA_KEY: &str = "l5d-tap-max-events-per-second";
+
+/// Names a request header, e.g. `x-request-id`, whose value labels each of a
+/// stream's events, so that they may be correlated with external systems.
+const CORRELATION_HEADER_METADATA_KEY: &str = "l5d-tap-correlation-header";
+
+/// Ends the session once no requests have been tapped for this long, in the
+/// `grpc-timeout` format, e.g. `5M`. Streams that were already tapped still
+/// complete. By default, sessions are never idle.
+const IDLE_TIMEOUT_METADATA_KEY: &str = "l5d-tap-idle-timeout";
+
+/// Sends the events of the tap session with this base ID to the call's
+/// response stream, in addition to the session's own, e.g. so that events may
+/// be archived while they are viewed. Only streams that are tapped after the
+/// call are teed, and the call's response stream ends with the session.
+/// Requires the session's token.
+const TEE_METADATA_KEY: &str = "l5d-tap-tee";
+
+/// When `true`, the response stream's first event describes the parameters
+/// that the server applied to the session, e.g. its match and limit. The
+/// event has no `event`, so this should only be set by clients that expect it.
+const DESCRIBE_SESSION_METADATA_KEY: &str = "l5d-tap-describe-session";
+
+/// When `true`, a lossy or rate-limited session's response stream ends with
+/// an event that describes how many events were dropped and how many requests
+/// were rate-limited in its labels. The event has no `event`, so this should
+/// only be set by clients that expect it.
+const REPORT_DROPS_METADATA_KEY: &str = "l5d-tap-report-drops";
+
+/// When `true`, a request body that is abandoned before it ends is reported
+/// by an event whose labels name the canceled stream. The event has no
+/// `event`, so this should only be set by clients that expect it.
+const REQUEST_CANCELS_METADATA_KEY: &str = "l5d-tap-request-cancels";
+
+/// Pauses the tap session whose events have this base ID. The call's own
+/// response stream ends immediately. Requires the session's token.
+const PAUSE_METADATA_KEY: &str = "l5d-tap-pause";
+
+/// Resumes the paused tap session whose events have this base ID. Requires
+/// the session's token.
+const RESUME_METADATA_KEY: &str = "l5d-tap-resume";
+
+/// Returned in the response metadata of each call that opens a tap session.
+/// Calls that control or tee the session must set this to the same token.
+const SESSION_TOKEN_METADATA_KEY: &str = "l5d-tap-session-token";
+
+/// All of the tap options that may be set in a call's metadata.
+const METADATA_KEYS: &[&str] = &[
+    DELIVERY_METADATA_KEY,
+    EVENTS_METADATA_KEY,
+    GRPC_STATUS_METADATA_KEY,
+    SOURCE_LABEL_METADATA_KEY,
+    SOURCE_NET_METADATA_KEY,
+    DESTINATION_NET_METADATA_KEY,
+    TLS_METADATA_KEY,
+    METHODS_METADATA_KEY,
+    EXCLUDE_PATHS_METADATA_KEY,
+    HOST_METADATA_KEY,
+    GRPC_METHOD_METADATA_KEY,
+    CONTENT_TYPE_METADATA_KEY,
+    DIRECTION_METADATA_KEY,
+    BODY_PREVIEW_LEN_METADATA_KEY,
+    BODY_PREVIEW_OFFSET_METADATA_KEY,
+    BODY_PREVIEW_BUDGET_METADATA_KEY,
+    RESPONSE_FRAMES_METADATA_KEY,
+    MAX_STREAMS_METADATA_KEY,
+    MAX_EVENTS_PER_SECOND_METADATA_KEY,
+    CORRELATION_HEADER_METADATA_KEY,
+    IDLE_TIMEOUT_METADATA_KEY,
+    TEE_METADATA_KEY,
+    DESCRIBE_SESSION_METADATA_KEY,
+    REPORT_DROPS_METADATA_KEY,
+    REQUEST_CANCELS_METADATA_KEY,
+    PAUSE_METADATA_KEY,
+    RESUME_METADATA_KEY,
+    SESSION_TOKEN_METADATA_KEY,
+];
+
+/// Limits the length of the encoded `grpc-message` sent to tap clients.
+const MAX_GRPC_MESSAGE_LEN: usize = 1024;
+
 // === impl Server ===
 
 impl<T: iface::Subscribe<Tap>> Server<T> {
     pub(in tap) fn new(subscribe: T) -> Self {
         let base_id = Arc::new(0.into());
-        Self { base_id, subscribe }
+        Self {
+            base_id,
+            subscribe,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            direction: None,
+            backpressure: false,
+        }
+    }
+
+    /// Labels all tap events as `direction`, e.g. for gateways, whose
+    /// requests are not all proxied in the direction that they are inspected
+    /// to have.
+    ///
+    /// Requests are still matched by their inspected direction.
+    pub fn with_direction(self, direction: DirectionMatch) -> Self {
+        Self {
+            direction: Some(direction),
+            ..self
+        }
+    }
+
+    /// Allows clients to request that events be delivered with backpressure,
+    /// so that the streams they tap wait for their events to be read.
+    ///
+    /// Otherwise, such requests are rejected, so that a slow client cannot
+    /// delay proxied traffic.
+    pub fn with_backpressure(self, backpressure: bool) -> Self {
+        Self {
+            backpressure,
+            ..self
+        }
+    }
+
+    pub fn sessions(&self) -> Sessions {
+        Sessions(self.sessions.clone())
+    }
+
+    /// Taps up to `limit` requests that match `match_`, without a gRPC
+    /// client, e.g. to write their events to a local sink.
+    ///
+    /// Fails if there are too many active taps.
+    pub fn tap(
+        &mut self,
+        match_: Match,
+        limit: usize,
+    ) -> impl Future<Item = mpsc::Receiver<api::TapEvent>, Error = iface::NoCapacity> {
+        let base_id = self.base_id.fetch_add(1, Ordering::Relaxed) as u32;
+        debug!("tap; id={}; match={:?}", base_id, match_);
+        let (mut tap, events_rx) = Tap::new(base_id, match_, limit);
+        tap.direction = self.direction;
+        self.subscribe.subscribe(tap).map(move |()| events_rx)
+    }
+
+    /// Reads the tap options from `req`'s metadata, rejecting those that this
+    /// server does not allow.
+    fn options<M>(&self, req: &grpc::Request<M>) -> Result<Options, String> {
+        let opts = Options::from_metadata(req)?;
+        if opts.delivery == Delivery::Backpressure && !self.backpressure {
+            return Err(format!(
+                "{} must not be backpressure unless enabled by the proxy",
+                DELIVERY_METADATA_KEY
+            ));
+        }
+        Ok(opts)
+    }
+
+    /// Finds the active tap session identified by `base_id`.
+    fn session(&self, base_id: u32) -> Result<Arc<Shared>, grpc::Status> {
+        lock(&self.sessions)
+            .get(&base_id)
+            .and_then(Weak::upgrade)
+            .ok_or_else(|| {
+                grpc::Status::new(
+                    grpc::Code::NotFound,
+                    grpc_message(&format!("no active tap with id {}", base_id)),
+                )
+            })
+    }
+
+    /// Finds the active tap session identified by `base_id`, if `req` has the
+    /// session's token.
+    fn authorized_session<M>(
+        &self,
+        base_id: u32,
+        req: &grpc::Request<M>,
+    ) -> Result<Arc<Shared>, grpc::Status> {
+        let shared = self.session(base_id)?;
+        let token = req
+            .metadata()
+            .get(SESSION_TOKEN_METADATA_KEY)
+            .and_then(|t| t.to_str().ok());
+        match token {
+            Some(token) if shared.token.verify(token) => Ok(shared),
+            _ => {
+                let msg = format!(
+                    "{} does not match tap {}",
+                    SESSION_TOKEN_METADATA_KEY, base_id
+                );
+                Err(grpc::Status::new(
+                    grpc::Code::PermissionDenied,
+                    grpc_message(&msg),
+                ))
+            }
+        }
+    }
+
+    /// Pauses or resumes the tap session identified by `base_id`.
+    fn set_paused<M>(
+        &self,
+        base_id: u32,
+        paused: bool,
+        req: &grpc::Request<M>,
+    ) -> Result<Response<ResponseStream>, grpc::Status> {
+        let shared = self.authorized_session(base_id, req)?;
+        debug!("tap; id={}; paused={}", base_id, paused);
+        shared.paused.store(paused, Ordering::Relaxed);
+        Ok(Response::new(ResponseStream::empty()))
     }
 
-    fn invalid_arg(message: String) -> grpc::Status {
-        grpc::Status::new(grpc::Code::InvalidArgument, message)
+    /// Tees the events of the tap session identified by `base_id` to a new
+    /// response stream.
+    fn tee<M>(
+        &self,
+        base_id: u32,
+        req: &grpc::Request<M>,
+    ) -> Result<Response<ResponseStream>, grpc::Status> {
+        let shared = self.authorized_session(base_id, req)?;
+        let report_drops = parse_metadata(req, REPORT_DROPS_METADATA_KEY)
+            .map_err(|e| invalid_arg(&e))?
+            .unwrap_or(false);
+        debug!("tap; id={}; tee", base_id);
+
+        let (tx, events_rx) = mpsc::channel(super::super::PER_RESPONSE_EVENT_BUFFER_CAPACITY);
+        let inflight = Arc::new(Inflight::default());
+        let tee = TeeTx {
+            tx,
+            inflight: InflightHandle(inflight.clone()),
+        };
+        lock(&shared.tees).push(tee);
+
+        // The stream does not hold the session, so it ends once the session's
+        // own stream has released it and all teed streams have completed.
+        let rsp = ResponseStream {
+            events_rx,
+            shared: None,
+            inflight,
+            deadline: None,
+            idle: None,
+            opened: None,
+            report_drops,
+        };
+        Ok(Response::new(rsp))
     }
 }
 
@@ -102,12 +649,61 @@ where
     >;
 
     fn observe(&mut self, req: grpc::Request<api::ObserveRequest>) -> Self::ObserveFuture {
+        if let Err(e) = check_metadata_keys(&req) {
+            warn!("invalid tap request: {}", e);
+            return future::Either::A(future::err(invalid_arg(&e)));
+        }
+
+        // Calls that pause or resume another session do not tap requests.
+        let control = parse_metadata(&req, PAUSE_METADATA_KEY)
+            .map(|id| id.map(|id| (id, true)))
+            .and_then(|pause| match pause {
+                None => {
+                    parse_metadata(&req, RESUME_METADATA_KEY).map(|id| id.map(|id| (id, false)))
+                }
+                pause => Ok(pause),
+            });
+        match control {
+            Ok(None) => {}
+            Ok(Some((base_id, paused))) => {
+                let rsp = self.set_paused(base_id, paused, &req);
+                return future::Either::A(future::result(rsp));
+            }
+            Err(e) => {
+                warn!("invalid tap request: {}", e);
+                return future::Either::A(future::err(invalid_arg(&e)));
+            }
+        }
+
+        // Calls that tee another session's events do not tap requests.
+        match parse_metadata(&req, TEE_METADATA_KEY) {
+            Ok(None) => {}
+            Ok(Some(base_id)) => {
+                return future::Either::A(future::result(self.tee(base_id, &req)));
+            }
+            Err(e) => {
+                warn!("invalid tap request: {}", e);
+                return future::Either::A(future::err(invalid_arg(&e)));
+            }
+        }
+
+        let mut opts = match self.options(&req) {
+            Ok(opts) => opts,
+            Err(e) => {
+                warn!("invalid tap request: {}", e);
+                return future::Either::A(future::err(invalid_arg(&e)));
+            }
+        };
+        trace!("tap: {:?}", opts);
+
         let req = req.into_inner();
 
-        let limit = req.limit as usize;
-        if limit == 0 {
-            let err = Self::invalid_arg("limit must be positive".into());
-            return future::Either::A(future::err(err));
+        let limit = match parse_limit(req.limit) {
+            Ok(limit) => limit,
+            Err(e) => {
+                warn!("invalid tap request: {}", e);
+                return future::Either::A(future::err(invalid_limit(e)));
+            }
         };
         trace!("tap: limit={}", limit);
 
@@ -120,45 +716,45 @@ where
             Ok(m) => m,
             Err(e) => {
                 warn!("invalid tap request: {} ", e);
-                let err = Self::invalid_arg(e.to_string());
-                return future::Either::A(future::err(err));
+                return future::Either::A(future::err(invalid_match(&e)));
             }
         };
+        let match_ = opts.extend_match(match_);
 
         // Wrapping is okay. This is realy just to disambiguate events within a
         // single tap session (i.e. that may consist of several tap requests).
         let base_id = self.base_id.fetch_add(1, Ordering::Relaxed) as u32;
         debug!("tap; id={}; match={:?}", base_id, match_);
 
-        // The events channel is used to emit tap events to the response stream.
-        //
-        // At most `limit` copies of `events_tx` are dispatched to `taps_rx`
-        // requests. Each tapped request's sender is dropped when the response
-        // completes, so the event stream closes gracefully when all tapped
-        // requests are completed without additional coordination.
-        let (events_tx, events_rx) =
-            mpsc::channel(super::super::PER_RESPONSE_EVENT_BUFFER_CAPACITY);
-
-        let shared = Arc::new(Shared {
-            base_id,
-            count: AtomicUsize::new(0),
-            limit,
-            match_,
-        });
-
-        let tap = Tap {
-            shared: Arc::downgrade(&shared),
-            events_tx,
+        let opened = if opts.describe_session {
+            let timeout = opts.timeout.map(|GrpcTimeout(t)| t);
+            Some(opened_event(
+                base_id,
+                limit,
+                &match_,
+                match_.prefilter(),
+                timeout,
+            ))
+        } else {
+            None
         };
+        let report_drops = opts.report_drops;
+        let (tap, shared, events_rx) = Tap::open(base_id, limit, match_, opts, self.direction);
+        {
+            let mut sessions = lock(&self.sessions);
+            sessions.retain(|_, s| s.upgrade().is_some());
+            sessions.insert(base_id, Arc::downgrade(&shared));
+        }
+        let inflight = shared.inflight.0.clone();
         let subscribe = self.subscribe.subscribe(tap);
 
-        // Reads up to `limit` requests from from `taps_rx` and satisfies them
-        // with a cpoy of `events_tx`.
-
         future::Either::B(ResponseFuture {
             subscribe,
             shared: Some(shared),
             events_rx: Some(events_rx),
+            inflight: Some(inflight),
+            opened,
+            report_drops,
         })
     }
 }
@@ -179,150 +775,861 @@ impl<F: Future<Item = ()>> Future for ResponseFuture<F> {
             }
         }
 
-        let rsp = ResponseStream {
-            shared: self.shared.take(),
-            events_rx: self.events_rx.take().expect("events_rx must be set"),
-        };
+        let shared = self.shared.take();
+        let token = shared.as_ref().map(|s| s.token.0.clone());
+        let rsp = ResponseStream::new(
+            shared,
+            self.events_rx.take().expect("events_rx must be set"),
+            self.inflight.take().expect("inflight must be set"),
+            self.opened.take(),
+            self.report_drops,
+        );
 
-        Ok(Response::new(rsp).into())
+        let mut rsp = Response::new(rsp);
+        if let Some(token) = token {
+            let token = token.parse().expect("hex token must be valid metadata");
+            rsp.metadata_mut().insert(SESSION_TOKEN_METADATA_KEY, token);
+        }
+        Ok(rsp.into())
     }
 }
 
 // === impl ResponseStream ===
 
+impl ResponseStream {
+    fn new(
+        shared: Option<Arc<Shared>>,
+        events_rx: mpsc::Receiver<api::TapEvent>,
+        inflight: Arc<Inflight>,
+        opened: Option<api::TapEvent>,
+        report_drops: bool,
+    ) -> Self {
+        let deadline = shared.as_ref().and_then(|s| s.deadline).map(Delay::new);
+        let idle = shared
+            .as_ref()
+            .and_then(|s| s.idle.as_ref())
+            .map(|i| Delay::new(i.expires_at()));
+        ResponseStream {
+            shared,
+            events_rx,
+            inflight,
+            deadline,
+            idle,
+            opened,
+            report_drops,
+        }
+    }
+
+    /// A stream that ends without emitting any events.
+    fn empty() -> Self {
+        let (_, events_rx) = mpsc::channel(0);
+        ResponseStream {
+            events_rx,
+            shared: None,
+            inflight: Arc::new(Inflight::default()),
+            deadline: None,
+            idle: None,
+            opened: None,
+            report_drops: false,
+        }
+    }
+
+    /// Polls the idle timer, rescheduling it if events were emitted since it
+    /// was set. Returns true once the session has been idle for its timeout.
+    fn poll_idle(&mut self) -> bool {
+        let expires_at = match self.shared.as_ref().and_then(|s| s.idle.as_ref()) {
+            Some(idle) => idle.expires_at(),
+            None => return false,
+        };
+        let delay = match self.idle {
+            Some(ref mut delay) => delay,
+            None => return false,
+        };
+        if delay.deadline() < expires_at {
+            delay.reset(expires_at);
+        }
+        // If the timer fails, the session is treated as idle.
+        delay.poll().map(|a| a.is_ready()).unwrap_or(true)
+    }
+}
+
 impl Stream for ResponseStream {
     type Item = api::TapEvent;
     type Error = grpc::Status;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Some(ev) = self.opened.take() {
+            return Ok(Async::Ready(Some(ev)));
+        }
+
+        // If the timer fails, the deadline is treated as having passed.
+        let expired = self
+            .deadline
+            .as_mut()
+            .map(|d| d.poll().map(|a| a.is_ready()).unwrap_or(true))
+            .unwrap_or(false);
+        if expired {
+            trace!("tap deadline expired");
+            self.deadline = None;
+            self.shared = None;
+        }
+        if self.poll_idle() {
+            trace!("tap idle timeout expired");
+            self.idle = None;
+            self.shared = None;
+        }
+
         // Drop the Shared handle once at our limit so that services do not do
         // any more matching against this tap.
         //
         // Furthermore, this drops the event sender so that `events_rx` closes
         // gracefully when all open taps are complete.
         self.shared = self.shared.take().and_then(|shared| {
-            if shared.is_under_limit() {
+            if shared.can_tap_more() {
                 Some(shared)
             } else {
                 None
             }
         });
 
+        // Idle services may hold senders indefinitely, so once no more
+        // requests may be tapped, the receiver is closed as soon as all
+        // tapped streams have completed. Events that are already buffered are
+        // still read before the stream ends.
+        if self.shared.is_none() {
+            self.inflight.task.register();
+            if Arc::strong_count(&self.inflight) == 1 {
+                trace!("all tapped streams complete; closing");
+                self.events_rx.close();
+            }
+        }
+
         // Read events from taps. The receiver can't actually error, but we need
         // to satisfy the type signature, so we coerce errors into EOS.
-        self.events_rx.poll().or_else(|_| Ok(None.into()))
+        match self.events_rx.poll() {
+            Ok(Async::Ready(Some(ev))) => Ok(Async::Ready(Some(ev))),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(None)) | Err(_) => {
+                // Before the stream ends, tell the client whether the session
+                // was lossy, if it asked. No more events can be dropped once
+                // the receiver has completed, so the count is only reported
+                // once.
+                let dropped = self.inflight.dropped.swap(0, Ordering::Relaxed);
+                let rate_limited = self.inflight.rate_limited.swap(0, Ordering::Relaxed);
+                if dropped == 0 && rate_limited == 0 {
+                    return Ok(Async::Ready(None));
+                }
+                if !self.report_drops {
+                    debug!(
+                        "tap; dropped {} events; rate-limited {} requests; not reported",
+                        dropped, rate_limited
+                    );
+                    return Ok(Async::Ready(None));
+                }
+
+                debug!(
+                    "tap; dropped {} events; rate-limited {} requests",
+                    dropped, rate_limited
+                );
+                Ok(Async::Ready(Some(dropped_event(dropped, rate_limited))))
+            }
+        }
+    }
+}
+
+// === impl Sessions ===
+
+impl Sessions {
+    /// Describes each active session, ordered by base ID, as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut sessions = lock(&self.0)
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|s| s.base_id);
+
+        let mut obj = Object::new();
+        obj.array("sessions", sessions.iter().map(|s| s.describe()).collect());
+        obj.finish()
+    }
+}
+
+// === impl SessionToken ===
+
+impl SessionToken {
+    fn random() -> Self {
+        SessionToken(format!(
+            "{:016x}{:016x}",
+            rand::random::<u64>(),
+            rand::random::<u64>()
+        ))
+    }
+
+    /// Compares `token` in constant time, so that the session's token can't
+    /// be discovered by timing failed attempts.
+    fn verify(&self, token: &str) -> bool {
+        let (a, b) = (self.0.as_bytes(), token.as_bytes());
+        a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+}
+
+/// The token is a secret, so it is never logged.
+impl fmt::Debug for SessionToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SessionToken(..)")
     }
 }
 
 // === impl Shared ===
 
 impl Shared {
+    fn describe(&self) -> Object {
+        let inflight = &self.inflight.0;
+        let mut obj = Object::new();
+        obj.number("id", self.base_id);
+        obj.string("match", &format!("{:?}", self.match_));
+        obj.number("tapped", self.count.load(Ordering::Relaxed));
+        obj.number("limit", self.limit);
+        obj.number("active_streams", inflight.streams.load(Ordering::Relaxed));
+        obj.number("dropped_events", inflight.dropped.load(Ordering::Relaxed));
+        obj.number(
+            "rate_limited_requests",
+            inflight.rate_limited.load(Ordering::Relaxed),
+        );
+        obj.string(
+            "state",
+            if self.paused.load(Ordering::Relaxed) {
+                "paused"
+            } else {
+                "active"
+            },
+        );
+        obj
+    }
+
     fn is_under_limit(&self) -> bool {
         self.count.load(Ordering::Relaxed) < self.limit
     }
-}
 
-// === impl Tap ===
+    fn can_tap_more(&self) -> bool {
+        let now = clock::now();
+        self.is_under_limit()
+            && self.deadline.map(|d| now < d).unwrap_or(true)
+            && self
+                .idle
+                .as_ref()
+                .map(|i| now < i.expires_at())
+                .unwrap_or(true)
+    }
+}
 
-impl iface::Tap for Tap {
-    type TapRequestPayload = TapRequestPayload;
-    type TapResponse = TapResponse;
-    type TapResponsePayload = TapResponsePayload;
+// === impl InflightHandle ===
 
-    fn can_tap_more(&self) -> bool {
-        self.shared
-            .upgrade()
-            .map(|shared| shared.is_under_limit())
-            .unwrap_or(false)
+impl Clone for InflightHandle {
+    fn clone(&self) -> Self {
+        InflightHandle(self.0.clone())
     }
+}
 
-    fn tap<B, I>(
-        &mut self,
-        req: &http::Request<B>,
-        inspect: &I,
-    ) -> Option<(TapRequestPayload, TapResponse)>
-    where
-        B: Payload,
-        I: Inspect,
-    {
-        let id = self.shared.upgrade().and_then(|shared| {
-            if !shared.match_.matches(req, inspect) {
-                return None;
-            }
-            let next_id = shared.count.fetch_add(1, Ordering::Relaxed);
-            if next_id < shared.limit {
-                Some(api::tap_event::http::StreamId {
-                    base: shared.base_id,
-                    stream: next_id as u64,
-                })
-            } else {
-                None
-            }
-        })?;
+impl InflightHandle {
+    fn record_dropped(&self) {
+        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+    }
 
-        let request_init_at = clock::now();
+    fn record_rate_limited(&self) {
+        self.0.rate_limited.fetch_add(1, Ordering::Relaxed);
+    }
 
-        let base_event = base_event(req, inspect);
+    /// Counts a new tapped stream, unless `max` streams are already active.
+    fn try_stream(&self, max: Option<usize>) -> Option<ActiveStream> {
+        let active = self.0.streams.fetch_add(1, Ordering::AcqRel);
+        // The count is decremented when the `ActiveStream` is dropped.
+        let stream = ActiveStream(self.clone());
+        if max.map(|max| active >= max).unwrap_or(false) {
+            debug!("tap; {} streams are active; skipping", active);
+            return None;
+        }
+        Some(stream)
+    }
+}
 
-        let init = api::tap_event::http::RequestInit {
-            id: Some(id.clone()),
-            method: Some(req.method().into()),
-            scheme: req.uri().scheme_part().map(http_types::Scheme::from),
-            authority: inspect.authority(req).unwrap_or_default(),
-            path: req.uri().path().into(),
-        };
-;
-        let event = api::TapEvent {
-            event: Some(api::tap_event::Event::Http(api::tap_event::Http {
-                event: Some(api::tap_event::http::Event::RequestInit(init)),
-            })),
-            ..base_event.clone()
-        };
+impl Drop for InflightHandle {
+    fn drop(&mut self) {
+        self.0.task.notify();
+    }
+}
 
-        // If try_send fails, just return `None`...
-        self.events_tx.try_send(event).ok()?;
+// === impl RateLimit ===
 
-        let tap = TapTx {
-            id,
-            tx: self.events_tx.clone(),
+impl RateLimit {
+    fn new(per_second: usize) -> Self {
+        let bucket = Bucket {
+            tokens: per_second as f64,
+            refilled_at: clock::now(),
         };
+        Self {
+            per_second,
+            bucket: Mutex::new(bucket),
+        }
+    }
 
-        let req = TapRequestPayload {
-            tap: tap.clone(),
-            base_event: base_event.clone(),
-        };
-        let rsp = TapResponse {
-            tap,
-            base_event,
-            request_init_at,
-        };
-        Some((req, rsp))
+    /// Takes a token from the bucket, if one is available at `now`.
+    fn try_acquire(&self, now: Instant) -> bool {
+        let mut bucket = lock(&self.bucket);
+        if now > bucket.refilled_at {
+            let elapsed = now - bucket.refilled_at;
+            let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+            let max = self.per_second as f64;
+            bucket.tokens = (bucket.tokens + secs * max).min(max);
+            bucket.refilled_at = now;
+        }
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
     }
 }
 
-// === impl TapResponse ===
+// === impl Idle ===
 
-impl iface::TapResponse for TapResponse {
-    type TapPayload = TapResponsePayload;
+impl Idle {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_event_at: Mutex::new(clock::now()),
+        }
+    }
 
-    fn tap<B: Payload>(mut self, rsp: &http::Response<B>) -> TapResponsePayload {
-        let response_init_at = clock::now();
-        let init = api::tap_event::http::Event::ResponseInit(api::tap_event::http::ResponseInit {
-            id: Some(self.tap.id.clone()),
-            since_request_init: Some(pb_duration(response_init_at - self.request_init_at)),
-            http_status: rsp.status().as_u16().into(),
-        });
+    fn record_event(&self, now: Instant) {
+        *lock(&self.last_event_at) = now;
+    }
 
-        let event = api::TapEvent {
-            event: Some(api::tap_event::Event::Http(api::tap_event::Http {
-                event: Some(init),
-            })),
-            ..self.base_event.clone()
-        };
-        let _ = self.tap.tx.try_send(event);
+    /// The time at which the session becomes idle, unless further events are
+    /// emitted.
+    fn expires_at(&self) -> Instant {
+        *lock(&self.last_event_at) + self.timeout
+    }
+}
 
-        TapResponsePayload {
-            base_event: self.base_event,
+// === impl ActiveStream ===
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        (self.0).0.streams.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// === impl Options ===
+
+impl Options {
+    fn from_metadata<M>(req: &grpc::Request<M>) -> Result<Self, String> {
+        let preview_len = parse_metadata(req, BODY_PREVIEW_LEN_METADATA_KEY)?;
+        let preview_offset = parse_metadata(req, BODY_PREVIEW_OFFSET_METADATA_KEY)?;
+        let body_preview = match (preview_len, preview_offset) {
+            (None, None) => None,
+            (None, Some(_)) => {
+                return Err(format!(
+                    "{} requires {}",
+                    BODY_PREVIEW_OFFSET_METADATA_KEY, BODY_PREVIEW_LEN_METADATA_KEY
+                ));
+            }
+            (Some(len), offset) => Some(BodyPreview::new(offset.unwrap_or(0), len)?),
+        };
+        let max_streams = parse_metadata(req, MAX_STREAMS_METADATA_KEY)?;
+        if max_streams == Some(0) {
+            return Err(format!("{} must be positive", MAX_STREAMS_METADATA_KEY));
+        }
+        let max_events_per_second = parse_metadata(req, MAX_EVENTS_PER_SECOND_METADATA_KEY)?;
+        if max_events_per_second == Some(0) {
+            return Err(format!(
+                "{} must be positive",
+                MAX_EVENTS_PER_SECOND_METADATA_KEY
+            ));
+        }
+        let idle_timeout = parse_metadata(req, IDLE_TIMEOUT_METADATA_KEY)?;
+        if idle_timeout == Some(GrpcTimeout(Duration::from_secs(0))) {
+            return Err(format!("{} must be positive", IDLE_TIMEOUT_METADATA_KEY));
+        }
+        let preview_budget = parse_metadata(req, BODY_PREVIEW_BUDGET_METADATA_KEY)?;
+        if preview_budget.is_some() && body_preview.is_none() {
+            return Err(format!(
+                "{} requires {}",
+                BODY_PREVIEW_BUDGET_METADATA_KEY, BODY_PREVIEW_LEN_METADATA_KEY
+            ));
+        }
+
+        Ok(Self {
+            delivery: parse_metadata(req, DELIVERY_METADATA_KEY)?.unwrap_or_default(),
+            events: parse_metadata(req, EVENTS_METADATA_KEY)?.unwrap_or_default(),
+            grpc_status: parse_metadata(req, GRPC_STATUS_METADATA_KEY)?,
+            source_label: parse_metadata(req, SOURCE_LABEL_METADATA_KEY)?,
+            source_net: parse_metadata(req, SOURCE_NET_METADATA_KEY)?,
+            destination_net: parse_metadata(req, DESTINATION_NET_METADATA_KEY)?,
+            tls: parse_metadata(req, TLS_METADATA_KEY)?,
+            methods: parse_metadata(req, METHODS_METADATA_KEY)?,
+            excluded_paths: parse_metadata(req, EXCLUDE_PATHS_METADATA_KEY)?,
+            host: parse_metadata(req, HOST_METADATA_KEY)?,
+            grpc_method: parse_metadata(req, GRPC_METHOD_METADATA_KEY)?,
+            content_type: parse_metadata(req, CONTENT_TYPE_METADATA_KEY)?,
+            direction: parse_metadata(req, DIRECTION_METADATA_KEY)?,
+            body_preview,
+            preview_budget: preview_budget.unwrap_or(preview::DEFAULT_BUDGET),
+            max_streams,
+            max_events_per_second,
+            timeout: parse_metadata(req, GRPC_TIMEOUT_METADATA_KEY)?,
+            correlation_header: parse_metadata(req, CORRELATION_HEADER_METADATA_KEY)?,
+            frames: parse_metadata(req, RESPONSE_FRAMES_METADATA_KEY)?.unwrap_or(false),
+            idle_timeout,
+            describe_session: parse_metadata(req, DESCRIBE_SESSION_METADATA_KEY)?.unwrap_or(false),
+            report_drops: parse_metadata(req, REPORT_DROPS_METADATA_KEY)?.unwrap_or(false),
+            request_cancels: parse_metadata(req, REQUEST_CANCELS_METADATA_KEY)?.unwrap_or(false),
+        })
+    }
+
+    /// Requires that requests also satisfy the predicates given as options.
+    fn extend_match(&mut self, match_: Match) -> Match {
+        // The direction is cheapest to check, so it's evaluated first.
+        let mut all = Vec::new();
+        all.extend(self.direction.take().map(Match::Direction));
+        all.push(match_);
+        all.extend(self.source_label.take().map(Match::SourceLabel));
+        all.extend(
+            self.source_net
+                .take()
+                .map(|n| Match::Source(TcpMatch::Net(n))),
+        );
+        all.extend(
+            self.destination_net
+                .take()
+                .map(|n| Match::Destination(TcpMatch::Net(n))),
+        );
+        all.extend(self.tls.take().map(Match::Tls));
+        all.extend(self.methods.take().map(Match::from));
+        all.extend(self.host.take().map(|h| Match::Http(HttpMatch::Host(h))));
+        all.extend(
+            self.grpc_method
+                .take()
+                .map(|m| Match::Http(HttpMatch::GrpcMethod(m))),
+        );
+        all.extend(
+            self.content_type
+                .take()
+                .map(|m| Match::Http(HttpMatch::ContentType(m))),
+        );
+        all.extend(
+            self.excluded_paths
+                .take()
+                .map(|p| Match::Not(Box::new(Match::from(p)))),
+        );
+
+        if all.len() == 1 {
+            all.pop().expect("match must be set")
+        } else {
+            Match::All(all)
+        }
+    }
+}
+
+// === impl Delivery ===
+
+impl Default for Delivery {
+    fn default() -> Self {
+        Delivery::Lossy
+    }
+}
+
+impl FromStr for Delivery {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lossy" => Ok(Delivery::Lossy),
+            "backpressure" => Ok(Delivery::Backpressure),
+            _ => Err("unknown delivery mode"),
+        }
+    }
+}
+
+impl Delivery {
+    /// Polls `tx` for capacity if events must not be dropped.
+    fn poll_capacity(self, tx: &mut mpsc::Sender<api::TapEvent>) -> Async<()> {
+        match self {
+            Delivery::Lossy => Async::Ready(()),
+            // If the response stream has been dropped, there's no reason to
+            // wait for capacity.
+            Delivery::Backpressure => tx.poll_ready().unwrap_or(Async::Ready(())),
+        }
+    }
+}
+
+// === impl GrpcTimeout ===
+
+impl FromStr for GrpcTimeout {
+    type Err = &'static str;
+
+    /// Parses at most 8 digits followed by a unit: `H`ours, `M`inutes,
+    /// `S`econds, `m`illiseconds, `u`microseconds, or `n`anoseconds.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() < 2 || s.len() > 9 {
+            return Err("invalid timeout");
+        }
+        let (value, unit) = s.split_at(s.len() - 1);
+        if !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err("invalid timeout");
+        }
+        let value = value.parse::<u64>().map_err(|_| "invalid timeout")?;
+        let timeout = match unit {
+            "H" => Duration::from_secs(value * 60 * 60),
+            "M" => Duration::from_secs(value * 60),
+            "S" => Duration::from_secs(value),
+            "m" => Duration::from_millis(value),
+            "u" => Duration::from_micros(value),
+            "n" => Duration::from_nanos(value),
+            _ => return Err("invalid timeout unit"),
+        };
+        Ok(GrpcTimeout(timeout))
+    }
+}
+
+// === impl InvalidLimit ===
+
+impl fmt::Display for InvalidLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidLimit::Zero => write!(f, "limit must be positive"),
+        }
+    }
+}
+
+// === impl Events ===
+
+impl Default for Events {
+    fn default() -> Self {
+        Events::Phases
+    }
+}
+
+impl FromStr for Events {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "phases" => Ok(Events::Phases),
+            "consolidated" => Ok(Events::Consolidated),
+            _ => Err("unknown events mode"),
+        }
+    }
+}
+
+// === impl Tap ===
+
+impl Tap {
+    /// Creates a tap for up to `limit` requests that match `match_`, along
+    /// with the receiver of its events, without a gRPC server, e.g. to write
+    /// tapped requests to a local sink.
+    ///
+    /// The tap is active until it has tapped `limit` requests or the receiver
+    /// is dropped. The receiver's stream ends once the tap and all of its
+    /// tapped streams have been dropped.
+    pub fn new(base_id: u32, match_: Match, limit: usize) -> (Self, mpsc::Receiver<api::TapEvent>) {
+        let (mut tap, shared, events_rx) =
+            Self::open(base_id, limit, match_, Options::default(), None);
+        tap.session = Some(shared);
+        (tap, events_rx)
+    }
+
+    /// Creates a session's tap, along with the state that its response stream
+    /// holds.
+    ///
+    /// At most `limit` copies of the tap's event sender are dispatched to
+    /// tapped requests. Each tapped request's sender is dropped when the
+    /// response completes, so the event stream closes gracefully when all
+    /// tapped requests are completed without additional coordination.
+    fn open(
+        base_id: u32,
+        limit: usize,
+        match_: Match,
+        opts: Options,
+        direction: Option<DirectionMatch>,
+    ) -> (Self, Arc<Shared>, mpsc::Receiver<api::TapEvent>) {
+        let (events_tx, events_rx) =
+            mpsc::channel(super::super::PER_RESPONSE_EVENT_BUFFER_CAPACITY);
+
+        let prefilter = match_.prefilter();
+        let shared = Arc::new(Shared {
+            base_id,
+            token: SessionToken::random(),
+            count: AtomicUsize::new(0),
+            limit,
+            deadline: opts.timeout.map(|GrpcTimeout(t)| clock::now() + t),
+            match_,
+            inflight: InflightHandle(Arc::new(Inflight::default())),
+            paused: AtomicBool::new(false),
+            max_streams: opts.max_streams,
+            rate_limit: opts.max_events_per_second.map(RateLimit::new),
+            idle: opts.idle_timeout.map(|GrpcTimeout(t)| Idle::new(t)),
+            tees: Mutex::new(Vec::new()),
+        });
+
+        let tap = Tap {
+            shared: Arc::downgrade(&shared),
+            prefilter,
+            events_tx,
+            delivery: opts.delivery,
+            events: opts.events,
+            grpc_status: opts.grpc_status.map(Arc::new),
+            body_preview: opts.body_preview,
+            preview_budget: preview::Budget::new(opts.preview_budget),
+            correlation_header: opts.correlation_header,
+            frames: opts.frames,
+            request_cancels: opts.request_cancels,
+            direction,
+            session: None,
+        };
+
+        (tap, shared, events_rx)
+    }
+}
+
+impl iface::Tap for Tap {
+    type TapRequestPayload = TapRequestPayload;
+    type TapResponse = TapResponse;
+    type TapResponsePayload = TapResponsePayload;
+
+    fn can_tap_more(&self) -> bool {
+        !self.events_tx.is_closed()
+            && self
+                .shared
+                .upgrade()
+                .map(|shared| shared.can_tap_more())
+                .unwrap_or(false)
+    }
+
+    fn may_match(&self, is_outbound: bool) -> bool {
+        self.prefilter.may_match(is_outbound)
+    }
+
+    fn tap<B, I>(
+        &mut self,
+        req: &http::Request<B>,
+        inspect: &I,
+    ) -> Option<(TapRequestPayload, TapResponse)>
+    where
+        B: Payload,
+        I: Inspect,
+    {
+        let (id, stream, mut tees) = self.shared.upgrade().and_then(|shared| {
+            // Paused sessions remain registered, so that they may be resumed
+            // without matching anew.
+            if shared.paused.load(Ordering::Relaxed) || !shared.match_.matches(req, inspect) {
+                return None;
+            }
+            let stream = shared.inflight.try_stream(shared.max_streams)?;
+            if let Some(ref rate_limit) = shared.rate_limit {
+                if !rate_limit.try_acquire(clock::now()) {
+                    trace!("tap; rate limit exceeded; skipping");
+                    shared.inflight.record_rate_limited();
+                    return None;
+                }
+            }
+            let next_id = shared.count.fetch_add(1, Ordering::Relaxed);
+            if next_id < shared.limit {
+                if let Some(ref idle) = shared.idle {
+                    idle.record_event(clock::now());
+                }
+                let id = api::tap_event::http::StreamId {
+                    base: shared.base_id,
+                    stream: next_id as u64,
+                };
+                let tees = lock(&shared.tees).clone();
+                Some((id, stream, tees))
+            } else {
+                None
+            }
+        })?;
+
+        let request_init_at = clock::now();
+
+        let mut base_event = base_event(req, inspect, self.direction);
+        let authority = authority(req, inspect);
+
+        // All of the stream's events are built from the base event, so they
+        // all carry its correlation ID.
+        let correlation_id = self
+            .correlation_header
+            .as_ref()
+            .and_then(|h| req.headers().get(h))
+            .and_then(|v| v.to_str().ok());
+        if let Some(id) = correlation_id {
+            base_event
+                .route_meta
+                .get_or_insert_with(Default::default)
+                .labels
+                .insert(super::CORRELATION_ID_LABEL.to_owned(), id.to_owned());
+        }
+
+        // If a `grpc-status` must match, events are not emitted until the
+        // response ends.
+        let mut deferred = self.grpc_status.clone().map(Deferred::new);
+
+        let content_length = content_length(req).map(|l| l.to_string());
+
+        // Consolidated streams describe their requests on the response's
+        // final event instead.
+        let mut rsp_event = base_event.clone();
+        match self.events {
+            Events::Consolidated => {
+                let m = rsp_event.route_meta.get_or_insert_with(Default::default);
+                m.labels
+                    .insert(super::METHOD_LABEL.to_owned(), req.method().to_string());
+                m.labels
+                    .insert(super::AUTHORITY_LABEL.to_owned(), authority);
+                m.labels
+                    .insert(super::PATH_LABEL.to_owned(), req.uri().path().to_owned());
+                if let Some(l) = content_length {
+                    m.labels
+                        .insert(super::REQUEST_CONTENT_LENGTH_LABEL.to_owned(), l);
+                }
+            }
+            Events::Phases => {
+                let init = api::tap_event::http::RequestInit {
+                    id: Some(id.clone()),
+                    method: Some(req.method().into()),
+                    scheme: req.uri().scheme_part().map(http_types::Scheme::from),
+                    authority,
+                    path: req.uri().path().into(),
+                };
+                let mut event = api::TapEvent {
+                    event: Some(api::tap_event::Event::Http(api::tap_event::Http {
+                        event: Some(api::tap_event::http::Event::RequestInit(init)),
+                    })),
+                    ..base_event.clone()
+                };
+                label_timestamp(&mut event);
+                if let Some(l) = content_length {
+                    event
+                        .route_meta
+                        .get_or_insert_with(Default::default)
+                        .labels
+                        .insert(super::REQUEST_CONTENT_LENGTH_LABEL.to_owned(), l);
+                }
+
+                match deferred {
+                    Some(ref mut d) => d.events.push(event),
+                    // If try_send fails, just return `None`...
+                    None => {
+                        if self.events_tx.try_send(event.clone()).is_err() {
+                            stream.0.record_dropped();
+                            return None;
+                        }
+                        for tee in &mut tees {
+                            tee.send(event.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        let tap = TapTx {
+            id,
+            tx: self.events_tx.clone(),
+            delivery: self.delivery,
+            stream: Arc::new(stream),
+            tees,
+        };
+
+        let request_preview = self
+            .body_preview
+            .and_then(|p| Preview::new(p, &self.preview_budget))
+            .map(|p| Arc::new(Mutex::new(p)));
+        let req = TapRequestPayload {
+            tap: tap.clone(),
+            base_event: base_event.clone(),
+            deferred: deferred.is_some(),
+            preview: request_preview.clone(),
+            report_cancel: self.request_cancels,
+        };
+        let rsp = TapResponse {
+            tap,
+            base_event: rsp_event,
+            request_init_at,
+            deferred,
+            events: self.events,
+            body_preview: self.body_preview,
+            preview_budget: self.preview_budget.clone(),
+            request_preview,
+            frames: self.frames,
+        };
+        Some((req, rsp))
+    }
+}
+
+// === impl TapResponse ===
+
+impl iface::TapResponse for TapResponse {
+    type TapPayload = TapResponsePayload;
+
+    fn poll_ready(&mut self) -> Async<()> {
+        self.tap.poll_ready()
+    }
+
+    fn tap<B: Payload>(mut self, rsp: &http::Response<B>) -> TapResponsePayload {
+        let response_init_at = clock::now();
+        if ProxyResponse::is_marked(rsp) {
+            let m = self
+                .base_event
+                .route_meta
+                .get_or_insert_with(Default::default);
+            m.labels
+                .insert(super::PROXY_RESPONSE_LABEL.to_owned(), "true".to_owned());
+            let reason = rsp
+                .headers()
+                .get(L5D_PROXY_ERROR)
+                .and_then(|v| v.to_str().ok());
+            if let Some(reason) = reason {
+                m.labels
+                    .insert(super::PROXY_ERROR_LABEL.to_owned(), reason.to_owned());
+            }
+        }
+        match self.events {
+            Events::Consolidated => {
+                let m = self
+                    .base_event
+                    .route_meta
+                    .get_or_insert_with(Default::default);
+                m.labels.insert(
+                    super::HTTP_STATUS_LABEL.to_owned(),
+                    rsp.status().as_u16().to_string(),
+                );
+            }
+            Events::Phases => {
+                let init =
+                    api::tap_event::http::Event::ResponseInit(api::tap_event::http::ResponseInit {
+                        id: Some(self.tap.id.clone()),
+                        since_request_init: Some(pb_duration(
+                            response_init_at - self.request_init_at,
+                        )),
+                        http_status: rsp.status().as_u16().into(),
+                    });
+
+                let mut event = api::TapEvent {
+                    event: Some(api::tap_event::Event::Http(api::tap_event::Http {
+                        event: Some(init),
+                    })),
+                    ..self.base_event.clone()
+                };
+                label_timestamp(&mut event);
+                match self.deferred {
+                    Some(ref mut d) => d.events.push(event),
+                    None => self.tap.send(event),
+                }
+            }
+        }
+
+        TapResponsePayload {
+            base_event: self.base_event,
             request_init_at: self.request_init_at,
             response_init_at,
             response_bytes: 0,
@@ -332,6 +1639,17 @@ impl iface::TapResponse for TapResponse {
                 .get("grpc-status")
                 .and_then(|v| v.to_str().ok())
                 .and_then(|s| s.parse::<u32>().ok()),
+            grpc_message: grpc_message_from(rsp.headers()),
+            deferred: self.deferred,
+            preview: self
+                .body_preview
+                .and_then(|p| Preview::new(p, &self.preview_budget)),
+            request_preview: self.request_preview,
+            frames: if self.frames {
+                Some(Frames::default())
+            } else {
+                None
+            },
         }
     }
 
@@ -348,80 +1666,507 @@ impl iface::TapResponse for TapResponse {
             }),
         });
 
-        let event = api::TapEvent {
+        let mut event = api::TapEvent {
             event: Some(api::tap_event::Event::Http(api::tap_event::Http {
                 event: Some(end),
             })),
             ..self.base_event
         };
-        let _ = self.tap.tx.try_send(event);
+        label_timestamp(&mut event);
+        label_error(&mut event, err);
+        label_previews(&mut event, self.request_preview.as_ref(), None);
+        self.tap.send_end(self.deferred, None, event);
     }
 }
 
 // === impl TapRequestPayload ===
 
 impl iface::TapPayload for TapRequestPayload {
-    fn data<B: Buf>(&mut self, _: &B) {}
+    fn data<B: Buf>(&mut self, data: &B) {
+        if let Some(ref preview) = self.preview {
+            if let Ok(mut p) = preview.lock() {
+                p.record(data);
+            }
+        }
+    }
 
     fn eos(self, _: Option<&http::HeaderMap>) {}
 
     fn fail<E: HasH2Reason>(self, _: &E) {}
+
+    /// The proto has no `RequestEnd` event, so an abandoned request body is
+    /// described by an event's `route_meta` labels, like `dropped_event`, if
+    /// the client set `REQUEST_CANCELS_METADATA_KEY`.
+    fn cancel(mut self) {
+        if self.deferred || !self.report_cancel {
+            return;
+        }
+
+        let mut m = self.base_event.route_meta.take().unwrap_or_default();
+        m.labels.insert(
+            super::REQUEST_CANCELED_LABEL.to_owned(),
+            self.tap.id.stream.to_string(),
+        );
+        let mut event = api::TapEvent {
+            route_meta: Some(m),
+            ..self.base_event
+        };
+        label_timestamp(&mut event);
+        self.tap.send(event);
+    }
 }
 
 // === impl TapResponsePayload ===
 
 impl iface::TapPayload for TapResponsePayload {
+    fn poll_ready(&mut self) -> Async<()> {
+        self.tap.poll_ready()
+    }
+
     fn data<B: Buf>(&mut self, data: &B) {
         self.response_bytes += data.remaining();
+        if let Some(ref mut f) = self.frames {
+            f.record(data.remaining());
+        }
+        if let Some(ref mut p) = self.preview {
+            p.record(data);
+        }
     }
 
-    fn eos(self, trls: Option<&http::HeaderMap>) {
-        let status = match trls {
-            None => self.grpc_status,
-            Some(t) => t
-                .get("grpc-status")
-                .and_then(|v| v.to_str().ok())
-                .and_then(|s| s.parse::<u32>().ok()),
+    fn eos(mut self, trls: Option<&http::HeaderMap>) {
+        let (status, message) = match trls {
+            None => (self.grpc_status, self.grpc_message.take()),
+            Some(t) => (
+                t.get("grpc-status")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|s| s.parse::<u32>().ok()),
+                grpc_message_from(t),
+            ),
         };
 
+        // The `Eos` only describes the status code, so the message is
+        // reported as a label.
+        if let Some(message) = message {
+            let m = self
+                .base_event
+                .route_meta
+                .get_or_insert_with(Default::default);
+            m.labels
+                .insert(super::GRPC_MESSAGE_LABEL.to_owned(), message);
+        }
+
         self.send(status.map(api::eos::End::GrpcStatusCode));
     }
 
-    fn fail<E: HasH2Reason>(self, e: &E) {
+    fn fail<E: HasH2Reason>(mut self, e: &E) {
         let end = e
             .h2_reason()
             .map(|r| api::eos::End::ResetErrorCode(r.into()));
+        label_error(&mut self.base_event, e);
         self.send(end);
     }
+
+    fn cancel(self) {
+        self.eos(None);
+    }
+
+    /// Ends the stream without a status, labeled so that it is not mistaken
+    /// for a response that the proxy forwarded in full.
+    fn shutdown(mut self) {
+        let m = self
+            .base_event
+            .route_meta
+            .get_or_insert_with(Default::default);
+        m.labels.insert(
+            super::ERROR_LABEL.to_owned(),
+            super::PROXY_SHUTDOWN_ERROR.to_owned(),
+        );
+        self.send(None);
+    }
 }
 
 impl TapResponsePayload {
     fn send(mut self, end: Option<api::eos::End>) {
+        let grpc_status = match end {
+            Some(api::eos::End::GrpcStatusCode(code)) => Some(code),
+            _ => None,
+        };
+
         let response_end_at = clock::now();
         let end = api::tap_event::http::ResponseEnd {
-            id: Some(self.tap.id),
+            id: Some(self.tap.id.clone()),
             since_request_init: Some(pb_duration(response_end_at - self.request_init_at)),
             since_response_init: Some(pb_duration(response_end_at - self.response_init_at)),
             response_bytes: self.response_bytes as u64,
             eos: Some(api::Eos { end }),
         };
 
-        let event = api::TapEvent {
+        let mut event = api::TapEvent {
             event: Some(api::tap_event::Event::Http(api::tap_event::Http {
                 event: Some(api::tap_event::http::Event::ResponseEnd(end)),
             })),
             ..self.base_event
         };
-        let _ = self.tap.tx.try_send(event);
+        label_timestamp(&mut event);
+        label_previews(
+            &mut event,
+            self.request_preview.as_ref(),
+            self.preview.as_ref(),
+        );
+        if let Some(ref f) = self.frames {
+            f.label(&mut event);
+        }
+        self.tap.send_end(self.deferred, grpc_status, event);
+    }
+}
+
+// === impl Frames ===
+
+impl Frames {
+    fn record(&mut self, bytes: usize) {
+        self.count += 1;
+        self.max_bytes = self.max_bytes.max(bytes);
+    }
+
+    fn label(&self, event: &mut api::TapEvent) {
+        let m = event.route_meta.get_or_insert_with(Default::default);
+        m.labels.insert(
+            super::RESPONSE_FRAMES_LABEL.to_owned(),
+            self.count.to_string(),
+        );
+        m.labels.insert(
+            super::RESPONSE_MAX_FRAME_BYTES_LABEL.to_owned(),
+            self.max_bytes.to_string(),
+        );
+    }
+}
+
+// === impl TapTx ===
+
+impl TapTx {
+    fn poll_ready(&mut self) -> Async<()> {
+        self.delivery.poll_capacity(&mut self.tx)
+    }
+
+    /// Emits a stream's final event, preceded by any events that were
+    /// deferred until its `grpc-status` was known.
+    fn send_end(
+        &mut self,
+        deferred: Option<Deferred>,
+        grpc_status: Option<u32>,
+        end: api::TapEvent,
+    ) {
+        if let Some(d) = deferred {
+            if !d.grpc_status.matches(grpc_status) {
+                trace!("tap; grpc-status={:?} does not match", grpc_status);
+                return;
+            }
+
+            for event in d.events {
+                self.send(event);
+            }
+        }
+
+        self.send(end);
+    }
+
+    fn send(&mut self, event: api::TapEvent) {
+        for tee in &mut self.tees {
+            tee.send(event.clone());
+        }
+        if self.tx.try_send(event).is_err() {
+            self.stream.0.record_dropped();
+        }
+    }
+}
+
+// === impl TeeTx ===
+
+impl TeeTx {
+    fn send(&mut self, event: api::TapEvent) {
+        if self.tx.try_send(event).is_err() {
+            self.inflight.record_dropped();
+        }
+    }
+}
+
+// === impl Deferred ===
+
+impl Deferred {
+    fn new(grpc_status: Arc<GrpcStatusMatch>) -> Self {
+        Self {
+            grpc_status,
+            events: Vec::with_capacity(2),
+        }
+    }
+}
+
+/// Builds the final event of a lossy or rate-limited tap, which describes how
+/// many events were dropped and how many requests were rate-limited in its
+/// `route_meta` labels. It has no `event`, so it is only sent to clients that
+/// set `REPORT_DROPS_METADATA_KEY`.
+fn dropped_event(dropped: usize, rate_limited: usize) -> api::TapEvent {
+    let mut m = api::tap_event::RouteMeta::default();
+    m.labels
+        .insert(super::DROPPED_EVENTS_LABEL.to_owned(), dropped.to_string());
+    if rate_limited > 0 {
+        m.labels.insert(
+            super::RATE_LIMITED_LABEL.to_owned(),
+            rate_limited.to_string(),
+        );
+    }
+    api::TapEvent {
+        route_meta: Some(m),
+        ..api::TapEvent::default()
+    }
+}
+
+/// Builds the first event of a described session, which describes the
+/// parameters that the server applied in its `route_meta` labels. It has no
+/// `event`.
+fn opened_event(
+    base_id: u32,
+    limit: usize,
+    match_: &Match,
+    prefilter: Prefilter,
+    timeout: Option<Duration>,
+) -> api::TapEvent {
+    let direction = match (prefilter.may_match(false), prefilter.may_match(true)) {
+        (true, true) => "all",
+        (true, false) => "inbound",
+        (false, true) => "outbound",
+        (false, false) => "none",
+    };
+
+    let mut m = api::tap_event::RouteMeta::default();
+    m.labels
+        .insert(super::SESSION_ID_LABEL.to_owned(), base_id.to_string());
+    m.labels
+        .insert(super::SESSION_LIMIT_LABEL.to_owned(), limit.to_string());
+    m.labels.insert(
+        super::SESSION_MATCH_LABEL.to_owned(),
+        format!("{:?}", match_),
+    );
+    m.labels.insert(
+        super::SESSION_DIRECTION_LABEL.to_owned(),
+        direction.to_owned(),
+    );
+    if let Some(t) = timeout {
+        let ms = t.as_secs() * 1_000 + u64::from(t.subsec_millis());
+        m.labels
+            .insert(super::SESSION_TIMEOUT_LABEL.to_owned(), ms.to_string());
+    }
+    api::TapEvent {
+        route_meta: Some(m),
+        ..api::TapEvent::default()
+    }
+}
+
+fn invalid_arg(message: &str) -> grpc::Status {
+    grpc::Status::new(grpc::Code::InvalidArgument, grpc_message(message))
+}
+
+fn invalid_match(e: &InvalidMatch) -> grpc::Status {
+    invalid_arg(&format!("invalid match: {}", e))
+}
+
+fn invalid_limit(e: InvalidLimit) -> grpc::Status {
+    invalid_arg(&e.to_string())
+}
+
+/// Validates the number of requests that an `ObserveRequest` may tap.
+fn parse_limit(limit: u32) -> Result<usize, InvalidLimit> {
+    if limit == 0 {
+        return Err(InvalidLimit::Zero);
+    }
+    Ok(limit as usize)
+}
+
+/// Percent-encodes a message, as described by the gRPC spec, so that it is
+/// always a valid `grpc-message` header value.
+///
+/// Messages that are longer than `MAX_GRPC_MESSAGE_LEN` are truncated.
+fn grpc_message(message: &str) -> String {
+    percent_encode(message.as_bytes(), MAX_GRPC_MESSAGE_LEN)
+}
+
+/// Percent-encodes all bytes except printable ASCII characters other than
+/// `%`, truncating the result to at most `max_len` characters.
+fn percent_encode(bytes: &[u8], max_len: usize) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &b in bytes {
+        let escape = b < b' ' || b > b'~' || b == b'%';
+        let len = if escape { 3 } else { 1 };
+        if encoded.len() + len > max_len {
+            break;
+        }
+        if escape {
+            encoded.push_str(&format!("%{:02X}", b));
+        } else {
+            encoded.push(b as char);
+        }
+    }
+    encoded
+}
+
+/// Labels an event with the current wall-clock time.
+///
+/// Durations within a stream are measured with the monotonic clock, so they
+/// are unaffected by this label.
+fn label_timestamp(event: &mut api::TapEvent) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let micros = now.as_secs() * 1_000_000 + u64::from(now.subsec_micros());
+    let m = event.route_meta.get_or_insert_with(Default::default);
+    m.labels
+        .insert(super::TIMESTAMP_LABEL.to_owned(), micros.to_string());
+}
+
+/// Labels a failed stream's final event with the kind of error that caused
+/// it, since the `Eos` can only describe HTTP/2 resets.
+fn label_error<E: HasH2Reason>(event: &mut api::TapEvent, err: &E) {
+    let m = event.route_meta.get_or_insert_with(Default::default);
+    m.labels
+        .insert(super::ERROR_LABEL.to_owned(), err.error_kind().to_string());
+}
+
+/// Locks state that is shared across streams and sessions.
+///
+/// Taps are invoked via `tap::service::isolate`, which recovers from panics,
+/// so a panic while a lock is held must not poison the lock for every other
+/// stream. All of this state remains consistent across a panic, so it is used
+/// as-is.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Labels a stream's final event with the previewed bytes of its request and
+/// response bodies, percent-encoded so that binary bodies are legible.
+fn label_previews(
+    event: &mut api::TapEvent,
+    request: Option<&Arc<Mutex<Preview>>>,
+    response: Option<&Preview>,
+) {
+    let request = request.and_then(|p| {
+        p.lock()
+            .ok()
+            .map(|p| percent_encode(p.bytes(), ::std::usize::MAX))
+    });
+    let response = response.map(|p| percent_encode(p.bytes(), ::std::usize::MAX));
+    if request.is_none() && response.is_none() {
+        return;
+    }
+
+    let m = event.route_meta.get_or_insert_with(Default::default);
+    if let Some(preview) = request {
+        m.labels
+            .insert(super::REQUEST_BODY_PREVIEW_LABEL.to_owned(), preview);
+    }
+    if let Some(preview) = response {
+        m.labels
+            .insert(super::RESPONSE_BODY_PREVIEW_LABEL.to_owned(), preview);
+    }
+}
+
+/// Reads a percent-encoded `grpc-message` from response headers or trailers.
+fn grpc_message_from(headers: &http::HeaderMap) -> Option<String> {
+    headers
+        .get("grpc-message")
+        .map(|v| decode_grpc_message(v.as_bytes()))
+}
+
+/// Decodes a percent-encoded `grpc-message`, as described by the gRPC spec.
+///
+/// Invalid escapes are left as-is, and invalid UTF-8 is replaced.
+fn decode_grpc_message(message: &[u8]) -> String {
+    fn hex(b: u8) -> Option<u8> {
+        match b {
+            b'0'..=b'9' => Some(b - b'0'),
+            b'a'..=b'f' => Some(b - b'a' + 10),
+            b'A'..=b'F' => Some(b - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    let mut decoded = Vec::with_capacity(message.len());
+    let mut i = 0;
+    while i < message.len() {
+        let escaped = match (message.get(i + 1), message.get(i + 2)) {
+            (Some(&hi), Some(&lo)) if message[i] == b'%' => {
+                hex(hi).and_then(|hi| hex(lo).map(|lo| hi << 4 | lo))
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(b) => {
+                decoded.push(b);
+                i += 3;
+            }
+            None => {
+                decoded.push(message[i]);
+                i += 1;
+            }
+        }
     }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Fails if the request has an `l5d-tap-*` metadata key that is not a known
+/// tap option.
+fn check_metadata_keys<M>(req: &grpc::Request<M>) -> Result<(), String> {
+    for key in req.metadata().keys() {
+        let key = match key {
+            KeyRef::Ascii(k) => k.as_str(),
+            KeyRef::Binary(k) => k.as_str(),
+        };
+        if key.starts_with(METADATA_KEY_PREFIX) && !METADATA_KEYS.contains(&key) {
+            return Err(format!("unknown tap option: {}", key));
+        }
+    }
+    Ok(())
+}
+
+/// Parses an optional ASCII metadata value from a gRPC request.
+fn parse_metadata<M, T>(req: &grpc::Request<M>, key: &'static str) -> Result<Option<T>, String>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match req.metadata().get(key).map(|v| v.to_str()) {
+        None => Ok(None),
+        Some(Err(_)) => Err(format!("invalid {}: not ascii", key)),
+        Some(Ok(s)) => s
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("invalid {}: {}", key, e)),
+    }
+}
+
+/// Reads the size of a request's body from its `content-length` header, if it
+/// is valid, before any of the body has been read.
+fn content_length<B>(req: &http::Request<B>) -> Option<u64> {
+    req.headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.parse().ok())
 }
 
 // All of the events emitted from tap have a common set of metadata.
 // Build this once, without an `event`, so that it can be used to build
 // each HTTP event.
-fn base_event<B, I: Inspect>(req: &http::Request<B>, inspect: &I) -> api::TapEvent {
+fn base_event<B, I: Inspect>(
+    req: &http::Request<B>,
+    inspect: &I,
+    direction: Option<DirectionMatch>,
+) -> api::TapEvent {
+    let is_outbound = match direction {
+        Some(DirectionMatch::Outbound) => true,
+        Some(DirectionMatch::Inbound) => false,
+        None => inspect.is_outbound(req),
+    };
     api::TapEvent {
-        proxy_direction: if inspect.is_outbound(req) {
+        proxy_direction: if is_outbound {
             api::tap_event::ProxyDirection::Outbound.into()
         } else {
             api::tap_event::ProxyDirection::Inbound.into()
@@ -431,33 +2176,628 @@ fn base_event<B, I: Inspect>(req: &http::Request<B>, inspect: &I) -> api::TapEve
             let mut m = api::tap_event::EndpointMeta::default();
             let tls = inspect.src_tls(req);
             let tls_status = tls.as_ref().map(|_| ()).to_string();
-            m.labels.insert("tls".to_owned(), tls_status);
+            m.labels.insert(super::TLS_LABEL.to_owned(), tls_status);
             if let Conditional::Some(id) = tls {
                 m.labels
-                    .insert("client_id".to_owned(), id.as_ref().to_owned());
+                    .insert(super::CLIENT_ID_LABEL.to_owned(), id.as_ref().to_owned());
             }
             Some(m)
         },
         destination: inspect.dst_addr(req).as_ref().map(|a| a.into()),
-        destination_meta: inspect.dst_labels(req).map(|labels| {
-            let mut m = api::tap_event::EndpointMeta::default();
-            m.labels
-                .extend(labels.iter().map(|(k, v)| (k.clone(), v.clone())));
+        destination_meta: {
+            let labels = inspect.dst_labels(req);
             let tls = inspect.dst_tls(req);
-            let tls_status = tls.as_ref().map(|_| ()).to_string();
-            m.labels.insert("tls".to_owned(), tls_status);
-            if let Conditional::Some(id) = tls {
-                m.labels
-                    .insert("server_id".to_owned(), id.as_ref().to_owned());
+            // The peer's identity is reported even if the destination has no
+            // labels, e.g. for inbound requests.
+            let id = tls.value().cloned();
+            if labels.is_none() && id.is_none() {
+                None
+            } else {
+                let mut m = api::tap_event::EndpointMeta::default();
+                if let Some(labels) = labels {
+                    m.labels
+                        .extend(labels.iter().map(|(k, v)| (k.clone(), v.clone())));
+                }
+                let tls_status = tls.as_ref().map(|_| ()).to_string();
+                m.labels.insert(super::TLS_LABEL.to_owned(), tls_status);
+                if let Some(id) = id {
+                    m.labels
+                        .insert(super::SERVER_ID_LABEL.to_owned(), id.as_ref().to_owned());
+                }
+                Some(m)
             }
-            m
-        }),
-        route_meta: inspect.route_labels(req).map(|labels| {
-            let mut m = api::tap_event::RouteMeta::default();
-            m.labels
-                .extend(labels.as_ref().iter().map(|(k, v)| (k.clone(), v.clone())));
-            m
-        }),
+        },
+        route_meta: {
+            let labels = inspect.route_labels(req);
+            let profile = inspect.profile_name(req);
+            let protocol = inspect.protocol(req);
+            let retries = req.extensions().get::<retry::Retries>();
+            if labels.is_none() && profile.is_none() && protocol.is_none() && retries.is_none() {
+                None
+            } else {
+                let mut m = api::tap_event::RouteMeta::default();
+                if let Some(labels) = labels {
+                    m.labels
+                        .extend(labels.as_ref().iter().map(|(k, v)| (k.clone(), v.clone())));
+                }
+                if let Some(profile) = profile {
+                    m.labels.insert(super::PROFILE_LABEL.to_owned(), profile);
+                }
+                if let Some(protocol) = protocol {
+                    m.labels
+                        .insert(super::PROTOCOL_LABEL.to_owned(), protocol.to_string());
+                    if inspect.is_orig_proto(req) {
+                        m.labels
+                            .insert(super::ORIG_PROTO_LABEL.to_owned(), "true".to_owned());
+                    }
+                }
+                // Retried requests are tapped as new streams, so the retry
+                // count lets consumers correlate them with the original.
+                if let Some(retries) = retries {
+                    m.labels
+                        .insert(super::RETRIES_LABEL.to_owned(), retries.0.to_string());
+                }
+                Some(m)
+            }
+        },
         event: None,
     }
 }
+
+/// Describes a request's authority, reading it from the request itself if
+/// `inspect` cannot describe it.
+fn authority<B, I: Inspect>(req: &http::Request<B>, inspect: &I) -> String {
+    inspect
+        .authority(req)
+        .or_else(|| tap::request_authority(req))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::tap::observe_request::r#match;
+    use identity;
+    use indexmap::IndexMap;
+    use std::net;
+    use transport::tls::ReasonForNoIdentity;
+
+    #[test]
+    fn invalid_match_describes_the_error() {
+        let m = api::observe_request::Match {
+            r#match: Some(r#match::Match::Destination(r#match::Tcp {
+                r#match: Some(r#match::tcp::Match::Ports(r#match::tcp::PortRange {
+                    min: 80,
+                    max: 70_000,
+                })),
+            })),
+        };
+        let e = Match::try_new(Some(m)).expect_err("port must be invalid");
+        let status = invalid_match(&e);
+        assert_eq!(status.code(), grpc::Code::InvalidArgument);
+        assert_eq!(status.message(), "invalid match: invalid port number");
+    }
+
+    #[test]
+    fn rejects_unknown_tap_options() {
+        let mut req = grpc::Request::new(());
+        req.metadata_mut()
+            .insert(MAX_STREAMS_METADATA_KEY, "1".parse().unwrap());
+        req.metadata_mut().insert("l5d-other", "1".parse().unwrap());
+        assert!(check_metadata_keys(&req).is_ok());
+
+        req.metadata_mut()
+            .insert("l5d-tap-max-stream", "1".parse().unwrap());
+        let e = check_metadata_keys(&req).expect_err("key must be unknown");
+        assert_eq!(e, "unknown tap option: l5d-tap-max-stream");
+        assert_eq!(invalid_arg(&e).code(), grpc::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn backpressure_is_opt_in() {
+        let mut req = grpc::Request::new(());
+        req.metadata_mut()
+            .insert(DELIVERY_METADATA_KEY, "backpressure".parse().unwrap());
+
+        let (_, server, _) = tap::new();
+        let e = server.options(&req).expect_err("must be rejected");
+        assert_eq!(
+            e,
+            "l5d-tap-delivery must not be backpressure unless enabled by the proxy"
+        );
+
+        let server = server.with_backpressure(true);
+        let opts = server.options(&req).expect("must be allowed");
+        assert_eq!(opts.delivery, Delivery::Backpressure);
+    }
+
+    #[test]
+    fn verifies_session_tokens() {
+        let token = SessionToken::random();
+        assert_eq!(token.0.len(), 32);
+        assert!(token.verify(&token.0.clone()));
+        assert!(!token.verify(""));
+        assert!(!token.verify(&token.0[1..]));
+        assert!(!SessionToken::random().verify(&token.0));
+        assert_eq!(format!("{:?}", token), "SessionToken(..)");
+    }
+
+    #[test]
+    fn only_the_session_token_pauses_a_session() {
+        let (_, server, _) = tap::new();
+        let match_ = Match::Direction("inbound".parse().expect("must parse"));
+        let (_tap, shared, _rx) = Tap::open(7, 1, match_, Options::default(), None);
+        server
+            .sessions
+            .lock()
+            .unwrap()
+            .insert(7, Arc::downgrade(&shared));
+
+        let mut req = grpc::Request::new(());
+        let e = server
+            .set_paused(7, true, &req)
+            .expect_err("must be denied");
+        assert_eq!(e.code(), grpc::Code::PermissionDenied);
+
+        req.metadata_mut()
+            .insert(SESSION_TOKEN_METADATA_KEY, "0123".parse().unwrap());
+        let e = server
+            .set_paused(7, true, &req)
+            .expect_err("must be denied");
+        assert_eq!(e.code(), grpc::Code::PermissionDenied);
+        assert!(!shared.paused.load(Ordering::Relaxed));
+
+        req.metadata_mut()
+            .insert(SESSION_TOKEN_METADATA_KEY, shared.token.0.parse().unwrap());
+        server.set_paused(7, true, &req).expect("must pause");
+        assert!(shared.paused.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn only_the_session_token_tees_a_session() {
+        let (_, server, _) = tap::new();
+        let match_ = Match::Direction("inbound".parse().expect("must parse"));
+        let (_tap, shared, _rx) = Tap::open(7, 1, match_, Options::default(), None);
+        server
+            .sessions
+            .lock()
+            .unwrap()
+            .insert(7, Arc::downgrade(&shared));
+
+        let mut req = grpc::Request::new(());
+        let e = server.tee(7, &req).expect_err("must be denied");
+        assert_eq!(e.code(), grpc::Code::PermissionDenied);
+        assert!(shared.tees.lock().unwrap().is_empty());
+
+        req.metadata_mut()
+            .insert(SESSION_TOKEN_METADATA_KEY, shared.token.0.parse().unwrap());
+        server.tee(7, &req).expect("must tee");
+        assert_eq!(shared.tees.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn events_without_an_event_are_opt_in() {
+        let opts = Options::from_metadata(&grpc::Request::new(())).expect("must be valid");
+        assert!(!opts.describe_session);
+        assert!(!opts.report_drops);
+        assert!(!opts.request_cancels);
+
+        let mut req = grpc::Request::new(());
+        for key in &[
+            DESCRIBE_SESSION_METADATA_KEY,
+            REPORT_DROPS_METADATA_KEY,
+            REQUEST_CANCELS_METADATA_KEY,
+        ] {
+            req.metadata_mut().insert(*key, "true".parse().unwrap());
+        }
+        let opts = Options::from_metadata(&req).expect("must be valid");
+        assert!(opts.describe_session);
+        assert!(opts.report_drops);
+        assert!(opts.request_cancels);
+    }
+
+    #[test]
+    fn validates_limits() {
+        assert_eq!(parse_limit(1), Ok(1));
+        assert_eq!(parse_limit(u32::max_value()), Ok(u32::max_value() as usize));
+
+        let e = parse_limit(0).expect_err("limit must be invalid");
+        assert_eq!(e, InvalidLimit::Zero);
+        let status = invalid_limit(e);
+        assert_eq!(status.code(), grpc::Code::InvalidArgument);
+        assert_eq!(status.message(), "limit must be positive");
+    }
+
+    #[test]
+    fn describes_opened_sessions() {
+        let match_ = Match::Direction("inbound".parse().expect("must parse"));
+        let ev = opened_event(
+            7,
+            100,
+            &match_,
+            match_.prefilter(),
+            Some(Duration::from_secs(30)),
+        );
+        assert!(ev.event.is_none());
+
+        let labels = ev.route_meta.expect("must be labeled").labels;
+        assert_eq!(labels[super::super::SESSION_ID_LABEL], "7");
+        assert_eq!(labels[super::super::SESSION_LIMIT_LABEL], "100");
+        assert_eq!(labels[super::super::SESSION_DIRECTION_LABEL], "inbound");
+        assert_eq!(labels[super::super::SESSION_TIMEOUT_LABEL], "30000");
+        assert!(labels.contains_key(super::super::SESSION_MATCH_LABEL));
+    }
+
+    #[test]
+    fn describes_active_sessions() {
+        let sessions = Sessions::default();
+        assert_eq!(sessions.to_json(), r#"{"sessions":[]}"#);
+
+        let shared = Arc::new(Shared {
+            base_id: 3,
+            count: AtomicUsize::new(2),
+            limit: 10,
+            deadline: None,
+            match_: Match::Direction("inbound".parse().expect("must parse")),
+            inflight: InflightHandle(Arc::new(Inflight::default())),
+            paused: AtomicBool::new(true),
+            max_streams: None,
+            rate_limit: None,
+            idle: None,
+            tees: Mutex::new(Vec::new()),
+        });
+        shared.inflight.record_dropped();
+        sessions
+            .0
+            .lock()
+            .unwrap()
+            .insert(3, Arc::downgrade(&shared));
+
+        let json = sessions.to_json();
+        assert!(json.starts_with(r#"{"sessions":[{"id":3,"match":"Direction(Inbound)","#));
+        assert!(json.ends_with(
+            r#""tapped":2,"limit":10,"active_streams":0,"dropped_events":1,"rate_limited_requests":0,"state":"paused"}]}"#
+        ));
+
+        // Sessions are no longer described once they complete.
+        drop(shared);
+        assert_eq!(sessions.to_json(), r#"{"sessions":[]}"#);
+    }
+
+    #[test]
+    fn empty_response_stream_ends() {
+        let events = ResponseStream::empty().collect().wait().expect("stream");
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn limits_active_streams() {
+        let inflight = InflightHandle(Arc::new(Inflight::default()));
+
+        let first = inflight.try_stream(Some(1)).expect("must be under limit");
+        assert!(inflight.try_stream(Some(1)).is_none());
+        assert!(inflight.try_stream(None).is_some());
+
+        drop(first);
+        assert!(inflight.try_stream(Some(1)).is_some());
+    }
+
+    #[test]
+    fn rate_limits_bursts() {
+        let rate_limit = RateLimit::new(2);
+        let t0 = rate_limit.bucket.lock().unwrap().refilled_at;
+
+        assert!(rate_limit.try_acquire(t0));
+        assert!(rate_limit.try_acquire(t0));
+        assert!(!rate_limit.try_acquire(t0));
+
+        // Tokens are replenished at the configured rate.
+        let t1 = t0 + Duration::from_millis(500);
+        assert!(rate_limit.try_acquire(t1));
+        assert!(!rate_limit.try_acquire(t1));
+
+        // Idle time does not allow bursts beyond the rate.
+        let t2 = t1 + Duration::from_secs(10);
+        assert!(rate_limit.try_acquire(t2));
+        assert!(rate_limit.try_acquire(t2));
+        assert!(!rate_limit.try_acquire(t2));
+    }
+
+    #[test]
+    fn idle_timeout_is_extended_by_events() {
+        let idle = Idle::new(Duration::from_secs(5));
+        let t0 = *idle.last_event_at.lock().unwrap();
+        assert_eq!(idle.expires_at(), t0 + Duration::from_secs(5));
+
+        let t1 = t0 + Duration::from_secs(3);
+        idle.record_event(t1);
+        assert_eq!(idle.expires_at(), t1 + Duration::from_secs(5));
+    }
+
+    #[test]
+    fn taps_without_a_server() {
+        use api::tap_event::http::Event;
+        use tap::iface::{Tap as _, TapPayload as _, TapResponse as _};
+
+        let (mut tap, events) = Tap::new(7, Match::All(vec![]), 1);
+
+        let req = http::Request::new(::hyper::Body::empty());
+        let (req_tap, rsp_tap) = tap.tap(&req, &NoAuthority).expect("request must be tapped");
+        req_tap.eos(None);
+        let rsp_tap = rsp_tap.tap(&http::Response::new(::hyper::Body::empty()));
+        rsp_tap.eos(None);
+        assert!(!tap.can_tap_more(), "tap must be limited");
+        drop(tap);
+
+        let events = events.collect().wait().expect("events");
+        let events = events
+            .iter()
+            .map(|ev| match ev.event {
+                Some(api::tap_event::Event::Http(ref http)) => match http.event {
+                    Some(Event::RequestInit(ref e)) => ("request_init", e.id.clone()),
+                    Some(Event::ResponseInit(ref e)) => ("response_init", e.id.clone()),
+                    Some(Event::ResponseEnd(ref e)) => ("response_end", e.id.clone()),
+                    None => panic!("http event must be set"),
+                },
+                _ => panic!("event must be http"),
+            })
+            .collect::<Vec<_>>();
+        let id = Some(api::tap_event::http::StreamId { base: 7, stream: 0 });
+        assert_eq!(
+            events,
+            vec![
+                ("request_init", id.clone()),
+                ("response_init", id.clone()),
+                ("response_end", id),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_drops_only_when_requested() {
+        let stream = |report_drops: bool| {
+            let (_, events_rx) = mpsc::channel(0);
+            let inflight = Arc::new(Inflight::default());
+            inflight.dropped.store(3, Ordering::Relaxed);
+            ResponseStream::new(None, events_rx, inflight, None, report_drops)
+        };
+
+        let events = stream(false).collect().wait().unwrap();
+        assert!(events.is_empty());
+
+        let mut events = stream(true).collect().wait().unwrap();
+        assert_eq!(events.len(), 1);
+        let labels = events.pop().unwrap().route_meta.expect("route meta").labels;
+        assert_eq!(
+            labels.get(super::super::DROPPED_EVENTS_LABEL),
+            Some(&"3".to_owned())
+        );
+    }
+
+    #[test]
+    fn reports_request_cancels_only_when_requested() {
+        use tap::iface::TapPayload;
+
+        let canceled = |report_cancel: bool| {
+            let (tx, rx) = mpsc::channel(1);
+            let payload = TapRequestPayload {
+                base_event: api::TapEvent::default(),
+                tap: TapTx {
+                    id: api::tap_event::http::StreamId::default(),
+                    tx,
+                    delivery: Delivery::Lossy,
+                    stream: Arc::new(ActiveStream(InflightHandle(Arc::default()))),
+                    tees: Vec::new(),
+                },
+                deferred: false,
+                preview: None,
+                report_cancel,
+            };
+            payload.cancel();
+            rx.collect().wait().unwrap()
+        };
+
+        assert!(canceled(false).is_empty());
+
+        let mut events = canceled(true);
+        assert_eq!(events.len(), 1);
+        let labels = events.pop().unwrap().route_meta.expect("route meta").labels;
+        assert!(labels.contains_key(super::super::REQUEST_CANCELED_LABEL));
+    }
+
+    #[test]
+    fn tees_drop_events_independently() {
+        let (tx, rx) = mpsc::channel(1);
+        let stream = Arc::new(Inflight::default());
+        let (tee_tx, tee_rx) = mpsc::channel(0);
+        let tee = Arc::new(Inflight::default());
+        let mut tap = TapTx {
+            id: api::tap_event::http::StreamId::default(),
+            tx,
+            delivery: Delivery::Lossy,
+            stream: Arc::new(ActiveStream(InflightHandle(stream.clone()))),
+            tees: vec![TeeTx {
+                tx: tee_tx,
+                inflight: InflightHandle(tee.clone()),
+            }],
+        };
+
+        // The tee can only buffer one event, but the session buffers both.
+        tap.send(api::TapEvent::default());
+        tap.send(api::TapEvent::default());
+        drop(tap);
+
+        assert_eq!(rx.collect().wait().unwrap().len(), 2);
+        assert_eq!(stream.dropped.load(Ordering::Relaxed), 0);
+        assert_eq!(tee_rx.collect().wait().unwrap().len(), 1);
+        assert_eq!(tee.dropped.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn reads_content_length() {
+        let req = |len: Option<&str>| {
+            let mut req = http::Request::new(());
+            if let Some(len) = len {
+                req.headers_mut()
+                    .insert(http::header::CONTENT_LENGTH, len.parse().unwrap());
+            }
+            req
+        };
+        assert_eq!(content_length(&req(Some("1024"))), Some(1024));
+        assert_eq!(content_length(&req(Some("-1"))), None);
+        assert_eq!(content_length(&req(Some("lots"))), None);
+        assert_eq!(content_length(&req(None)), None);
+    }
+
+    #[test]
+    fn summarizes_response_frames() {
+        let mut frames = Frames::default();
+        frames.record(3);
+        frames.record(10);
+        frames.record(0);
+
+        let mut event = api::TapEvent::default();
+        frames.label(&mut event);
+        let labels = event.route_meta.expect("route meta").labels;
+        assert_eq!(labels[super::super::RESPONSE_FRAMES_LABEL], "3");
+        assert_eq!(labels[super::super::RESPONSE_MAX_FRAME_BYTES_LABEL], "10");
+    }
+
+    #[test]
+    fn labels_events_with_timestamps() {
+        let before = SystemTime::now();
+        let mut event = api::TapEvent::default();
+        label_timestamp(&mut event);
+
+        let labels = event.route_meta.expect("route meta").labels;
+        let micros = labels[super::super::TIMESTAMP_LABEL]
+            .parse::<u64>()
+            .expect("timestamp must be a number");
+        let before = before.duration_since(UNIX_EPOCH).unwrap();
+        assert!(micros >= before.as_secs() * 1_000_000);
+    }
+
+    #[test]
+    fn parses_grpc_timeouts() {
+        let parse = |s: &str| s.parse::<GrpcTimeout>().map(|GrpcTimeout(t)| t);
+        assert_eq!(parse("2H"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse("3M"), Ok(Duration::from_secs(3 * 60)));
+        assert_eq!(parse("30S"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse("250m"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse("99999999u"), Ok(Duration::from_micros(99_999_999)));
+        assert_eq!(parse("1n"), Ok(Duration::from_nanos(1)));
+        assert!(parse("S").is_err());
+        assert!(parse("100").is_err());
+        assert!(parse("-1S").is_err());
+        assert!(parse("123456789S").is_err());
+        assert!(parse("10s").is_err());
+    }
+
+    #[test]
+    fn grpc_message_is_percent_encoded() {
+        assert_eq!(
+            grpc_message("limit must be positive"),
+            "limit must be positive"
+        );
+        assert_eq!(grpc_message("100%\n\u{e9}"), "100%25%0A%C3%A9");
+    }
+
+    #[test]
+    fn grpc_message_is_percent_decoded() {
+        assert_eq!(decode_grpc_message(b"not found"), "not found");
+        assert_eq!(decode_grpc_message(b"100%25%0A%C3%A9"), "100%\n\u{e9}");
+        assert_eq!(decode_grpc_message(b"50%zz%"), "50%zz%");
+    }
+
+    #[test]
+    fn grpc_message_is_truncated() {
+        let long = "%".repeat(MAX_GRPC_MESSAGE_LEN);
+        let encoded = grpc_message(&long);
+        assert!(encoded.len() <= MAX_GRPC_MESSAGE_LEN);
+        assert!(encoded.ends_with("%25"));
+    }
+
+    /// Describes targets that cannot describe their requests' authorities.
+    struct NoAuthority;
+
+    impl Inspect for NoAuthority {
+        fn src_addr<B>(&self, _: &http::Request<B>) -> Option<net::SocketAddr> {
+            None
+        }
+
+        fn src_tls<'a, B>(
+            &self,
+            _: &'a http::Request<B>,
+        ) -> Conditional<&'a identity::Name, ReasonForNoIdentity> {
+            Conditional::None(ReasonForNoIdentity::Disabled)
+        }
+
+        fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<net::SocketAddr> {
+            None
+        }
+
+        fn dst_labels<B>(&self, _: &http::Request<B>) -> Option<&IndexMap<String, String>> {
+            None
+        }
+
+        fn dst_tls<B>(
+            &self,
+            _: &http::Request<B>,
+        ) -> Conditional<&identity::Name, ReasonForNoIdentity> {
+            Conditional::None(ReasonForNoIdentity::Disabled)
+        }
+
+        fn route_labels<B>(&self, _: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
+            None
+        }
+
+        fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
+            false
+        }
+
+        fn authority<B>(&self, _: &http::Request<B>) -> Option<String> {
+            None
+        }
+    }
+
+    #[test]
+    fn direction_override_wins() {
+        let req = http::Request::new(());
+        let inbound = api::tap_event::ProxyDirection::Inbound as i32;
+        let outbound = api::tap_event::ProxyDirection::Outbound as i32;
+
+        // `NoAuthority` inspects every request as inbound.
+        assert_eq!(
+            base_event(&req, &NoAuthority, None).proxy_direction,
+            inbound
+        );
+        assert_eq!(
+            base_event(&req, &NoAuthority, Some(DirectionMatch::Outbound)).proxy_direction,
+            outbound
+        );
+        assert_eq!(
+            base_event(&req, &NoAuthority, Some(DirectionMatch::Inbound)).proxy_direction,
+            inbound
+        );
+    }
+
+    #[test]
+    fn authority_falls_back_to_the_request() {
+        let req = http::Request::builder()
+            .uri("/")
+            .header(http::header::HOST, "web.example.com")
+            .body(())
+            .unwrap();
+        assert_eq!(authority(&req, &NoAuthority), "web.example.com");
+
+        let req = http::Request::builder()
+            .uri("http://api.example.com/")
+            .header(http::header::HOST, "web.example.com")
+            .body(())
+            .unwrap();
+        assert_eq!(authority(&req, &NoAuthority), "api.example.com");
+
+        let req = http::Request::new(());
+        assert_eq!(authority(&req, &NoAuthority), "");
+    }
+}
@@ -0,0 +1,254 @@
+//! Writes tap events as newline-delimited JSON.
+//!
+//! This is intended for local debugging, where it is more convenient to read
+//! events from a file than to run a tap client against the control listener.
+
+use futures::{Future, Stream};
+use std::io::Write;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use api::http_types::{self, scheme};
+use api::net::{ip_address::Ip, TcpAddress};
+use api::tap as api;
+
+use super::{Match, Server};
+
+/// Writes every tapped event to `writer`, one JSON object per line.
+///
+/// Events are written synchronously, so `writer` must not block for long,
+/// e.g. a local file.
+pub fn sink<W: Write>(mut server: Server, mut writer: W) -> impl Future<Item = (), Error = ()> {
+    server
+        .tap(Match::All(vec![]), ::std::usize::MAX)
+        .map_err(|e| error!("tap json sink failed: {}", e))
+        .and_then(move |events| {
+            events.for_each(move |ev| {
+                writeln!(writer, "{}", encode(&ev))
+                    .map_err(|e| error!("failed to write tap event: {}", e))
+            })
+        })
+}
+
+fn encode(ev: &api::TapEvent) -> String {
+    let mut obj = Object::new();
+
+    let outbound = ev.proxy_direction == api::tap_event::ProxyDirection::Outbound as i32;
+    obj.string(
+        "proxy_direction",
+        if outbound { "outbound" } else { "inbound" },
+    );
+
+    if let Some(addr) = ev.source.as_ref().and_then(sock_addr) {
+        obj.string("source", &addr.to_string());
+    }
+    if let Some(ref meta) = ev.source_meta {
+        obj.object("source_meta", labels(&meta.labels));
+    }
+    if let Some(addr) = ev.destination.as_ref().and_then(sock_addr) {
+        obj.string("destination", &addr.to_string());
+    }
+    if let Some(ref meta) = ev.destination_meta {
+        obj.object("destination_meta", labels(&meta.labels));
+    }
+    if let Some(ref meta) = ev.route_meta {
+        obj.object("route_meta", labels(&meta.labels));
+    }
+
+    let http = match ev.event {
+        Some(api::tap_event::Event::Http(ref http)) => http.event.as_ref(),
+        _ => None,
+    };
+    match http {
+        Some(api::tap_event::http::Event::RequestInit(ref init)) => {
+            let mut o = Object::new();
+            if let Some(ref id) = init.id {
+                o.object("id", stream_id(id));
+            }
+            if let Some(m) = init.method.as_ref().and_then(method) {
+                o.string("method", &m);
+            }
+            if let Some(s) = init.scheme.as_ref().and_then(scheme) {
+                o.string("scheme", &s);
+            }
+            o.string("authority", &init.authority);
+            o.string("path", &init.path);
+            obj.object("request_init", o);
+        }
+        Some(api::tap_event::http::Event::ResponseInit(ref init)) => {
+            let mut o = Object::new();
+            if let Some(ref id) = init.id {
+                o.object("id", stream_id(id));
+            }
+            if let Some(ref d) = init.since_request_init {
+                o.number("since_request_init_us", micros(d));
+            }
+            o.number("http_status", init.http_status);
+            obj.object("response_init", o);
+        }
+        Some(api::tap_event::http::Event::ResponseEnd(ref end)) => {
+            let mut o = Object::new();
+            if let Some(ref id) = end.id {
+                o.object("id", stream_id(id));
+            }
+            if let Some(ref d) = end.since_request_init {
+                o.number("since_request_init_us", micros(d));
+            }
+            if let Some(ref d) = end.since_response_init {
+                o.number("since_response_init_us", micros(d));
+            }
+            o.number("response_bytes", end.response_bytes);
+            match end.eos.as_ref().and_then(|eos| eos.end.as_ref()) {
+                Some(api::eos::End::GrpcStatusCode(code)) => o.number("grpc_status", code),
+                Some(api::eos::End::ResetErrorCode(code)) => o.number("reset_error_code", code),
+                None => {}
+            }
+            obj.object("response_end", o);
+        }
+        None => {}
+    }
+
+    obj.finish()
+}
+
+fn labels<'a, I>(labels: I) -> Object
+where
+    I: IntoIterator<Item = (&'a String, &'a String)>,
+{
+    let mut o = Object::new();
+    for (k, v) in labels {
+        o.string(k, v);
+    }
+    o
+}
+
+fn stream_id(id: &api::tap_event::http::StreamId) -> Object {
+    let mut o = Object::new();
+    o.number("base", id.base);
+    o.number("stream", id.stream);
+    o
+}
+
+pub(super) fn method(m: &http_types::HttpMethod) -> Option<String> {
+    m.r#type
+        .clone()
+        .and_then(|t| t.try_as_http().ok())
+        .map(|m| m.as_str().to_owned())
+}
+
+fn scheme(s: &http_types::Scheme) -> Option<String> {
+    match s.r#type {
+        Some(scheme::Type::Registered(r)) if r == scheme::Registered::Http.into() => {
+            Some("http".to_owned())
+        }
+        Some(scheme::Type::Registered(r)) if r == scheme::Registered::Https.into() => {
+            Some("https".to_owned())
+        }
+        Some(scheme::Type::Unregistered(ref s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn sock_addr(addr: &TcpAddress) -> Option<SocketAddr> {
+    let ip = match addr.ip.as_ref().and_then(|ip| ip.ip.as_ref()) {
+        Some(Ip::Ipv4(octets)) => Ipv4Addr::from(*octets).into(),
+        Some(Ip::Ipv6(v6)) => {
+            Ipv6Addr::from((u128::from(v6.first) << 64) | u128::from(v6.last)).into()
+        }
+        None => return None,
+    };
+    Some(SocketAddr::new(ip, addr.port as u16))
+}
+
+pub(super) fn micros(d: &::prost_types::Duration) -> i64 {
+    d.seconds * 1_000_000 + i64::from(d.nanos / 1_000)
+}
+
+/// Builds a single-line JSON object.
+pub(super) struct Object(String);
+
+impl Object {
+    pub(super) fn new() -> Self {
+        Object(String::from("{"))
+    }
+
+    fn key(&mut self, key: &str) {
+        if self.0.len() > 1 {
+            self.0.push(',');
+        }
+        push_quoted(&mut self.0, key);
+        self.0.push(':');
+    }
+
+    pub(super) fn string(&mut self, key: &str, value: &str) {
+        self.key(key);
+        push_quoted(&mut self.0, value);
+    }
+
+    pub(super) fn number<N: ToString>(&mut self, key: &str, value: N) {
+        self.key(key);
+        self.0.push_str(&value.to_string());
+    }
+
+    pub(super) fn object(&mut self, key: &str, value: Object) {
+        self.key(key);
+        self.0.push_str(&value.finish());
+    }
+
+    pub(super) fn array(&mut self, key: &str, values: Vec<Object>) {
+        self.key(key);
+        self.0.push('[');
+        for (i, value) in values.into_iter().enumerate() {
+            if i > 0 {
+                self.0.push(',');
+            }
+            self.0.push_str(&value.finish());
+        }
+        self.0.push(']');
+    }
+
+    pub(super) fn finish(mut self) -> String {
+        self.0.push('}');
+        self.0
+    }
+}
+
+fn push_quoted(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_strings() {
+        let mut out = String::new();
+        push_quoted(&mut out, "a\"b\\c\n\u{1}");
+        assert_eq!(out, "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+
+    #[test]
+    fn encodes_objects() {
+        let mut o = Object::new();
+        o.string("path", "/");
+        o.number("http_status", 200);
+        o.object("route_meta", Object::new());
+        o.array("attributes", vec![Object::new(), Object::new()]);
+        assert_eq!(
+            o.finish(),
+            r#"{"path":"/","http_status":200,"route_meta":{},"attributes":[{},{}]}"#
+        );
+    }
+}
@@ -1,15 +1,26 @@
 use http;
 use indexmap::IndexMap;
 use std::net;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use identity;
+use proxy::http::Settings;
 use transport::tls::ReasonForNoIdentity;
 use Conditional;
 
 mod daemon;
 mod grpc;
+mod json;
+mod rejections;
 mod service;
+mod span;
+
+pub use self::grpc::{
+    DirectionMatch, HostMatch, HttpMatch, InvalidMatch, Match, MethodsMatch, Sessions, TlsMatch,
+};
+pub use self::json::sink as json_sink;
+pub use self::span::sink as span_sink;
 
 /// Instruments service stacks so that requests may be tapped.
 pub type Layer = service::Layer<daemon::Register<grpc::Tap>>;
@@ -33,12 +44,28 @@ const PER_RESPONSE_EVENT_BUFFER_CAPACITY: usize = 400;
 
 /// Build the tap subsystem.
 pub fn new() -> (Layer, Server, Daemon) {
-    let (daemon, register, subscribe) = daemon::new();
-    let layer = Layer::new(register);
+    let shutdown = Shutdown::default();
+    let (daemon, register, subscribe) = daemon::new(shutdown.clone());
+    let layer = Layer::new(register, shutdown);
     let server = Server::new(subscribe);
     (layer, server, daemon)
 }
 
+/// Set by the `Daemon` when the proxy begins to shut down, so that streams
+/// that are abandoned as it does are not described as canceled by a peer.
+#[derive(Clone, Debug, Default)]
+struct Shutdown(Arc<AtomicBool>);
+
+impl Shutdown {
+    fn shutdown(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
 /// Inspects a request for a `Stack`.
 ///
 /// `Stack` target types
@@ -58,6 +85,23 @@ pub trait Inspect {
 
     fn route_labels<B>(&self, req: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>>;
 
+    /// Describes the protocol with which the request is proxied to its
+    /// destination.
+    fn protocol<B>(&self, _: &http::Request<B>) -> Option<Settings> {
+        None
+    }
+
+    /// Returns `true` if the request is carried between proxies as HTTP/2,
+    /// via an `l5d-orig-proto` upgrade.
+    fn is_orig_proto<B>(&self, _: &http::Request<B>) -> bool {
+        false
+    }
+
+    /// Names the service profile that routed the request, if any.
+    fn profile_name<B>(&self, _: &http::Request<B>) -> Option<String> {
+        None
+    }
+
     fn is_outbound<B>(&self, req: &http::Request<B>) -> bool;
 
     fn is_inbound<B>(&self, req: &http::Request<B>) -> bool {
@@ -65,18 +109,24 @@ pub trait Inspect {
     }
 
     fn authority<B>(&self, req: &http::Request<B>) -> Option<String> {
-        req.uri()
-            .authority_part()
-            .map(|a| a.as_str().to_owned())
-            .or_else(|| {
-                req.headers()
-                    .get(http::header::HOST)
-                    .and_then(|h| h.to_str().ok())
-                    .map(|s| s.to_owned())
-            })
+        request_authority(req)
     }
 }
 
+/// Reads a request's authority from its URI or, failing that, its `Host`
+/// header.
+fn request_authority<B>(req: &http::Request<B>) -> Option<String> {
+    req.uri()
+        .authority_part()
+        .map(|a| a.as_str().to_owned())
+        .or_else(|| {
+            req.headers()
+                .get(http::header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .map(|s| s.to_owned())
+        })
+}
+
 /// The internal interface used between Layer, Server, and Daemon.
 ///
 /// These interfaces are provided to decouple the service implementation from any
@@ -88,7 +138,7 @@ pub trait Inspect {
 /// module.
 mod iface {
     use bytes::Buf;
-    use futures::{Future, Stream};
+    use futures::{Async, Future, Stream};
     use http;
     use hyper::body::Payload;
 
@@ -120,6 +170,12 @@ mod iface {
         /// Returns `true` as l
         fn can_tap_more(&self) -> bool;
 
+        /// Returns `false` if the tap cannot match any request in the given
+        /// direction, so that services may avoid matching requests against it.
+        fn may_match(&self, _is_outbound: bool) -> bool {
+            true
+        }
+
         /// Initiate a tap, if it matches.
         ///
         /// If the tap cannot be initialized, for instance because the tap has
@@ -132,16 +188,42 @@ mod iface {
     }
 
     pub trait TapPayload {
+        /// Polls the tap for capacity to record more of the payload.
+        ///
+        /// Taps that do not apply backpressure are always ready.
+        fn poll_ready(&mut self) -> Async<()> {
+            Async::Ready(())
+        }
+
         fn data<B: Buf>(&mut self, data: &B);
 
         fn eos(self, headers: Option<&http::HeaderMap>);
 
         fn fail<E: HasH2Reason>(self, error: &E);
+
+        /// Record that the payload was dropped before it completed.
+        fn cancel(self);
+
+        /// Record that the payload was dropped before it completed because
+        /// the proxy is shutting down.
+        fn shutdown(self)
+        where
+            Self: Sized,
+        {
+            self.cancel()
+        }
     }
 
     pub trait TapResponse {
         type TapPayload: TapPayload;
 
+        /// Polls the tap for capacity to record a response.
+        ///
+        /// Taps that do not apply backpressure are always ready.
+        fn poll_ready(&mut self) -> Async<()> {
+            Async::Ready(())
+        }
+
         /// Record a response and obtain a handle to tap its body.
         fn tap<B: Payload>(self, rsp: &http::Response<B>) -> Self::TapPayload;
 
@@ -159,5 +241,4 @@ mod iface {
     }
 
     impl ::std::error::Error for NoCapacity {}
-
 }
@@ -0,0 +1,305 @@
+use futures::{Async, Future, Poll, Stream};
+use http;
+use hyper::body::Payload as HyperPayload;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::iface::{Register, Tap, TapPayload, TapResponse};
+use super::service::{isolate, Payload};
+use super::{Inspect, Shutdown};
+use proxy::http::{HasH2Reason, ProxyResponse};
+use svc;
+
+/// A layer that taps requests that the proxy rejects before they reach an
+/// endpoint's stack, e.g. because no endpoint could be recognized for them.
+///
+/// Each target is described by the `Inspect` that `inspect` builds from it.
+/// The description is also stored in the extensions of each tapped request,
+/// so that it may describe the request with the request's lifetime.
+/// Rejected requests are only tapped once the proxy's own response is known,
+/// so they do not apply backpressure.
+#[derive(Clone, Debug)]
+pub struct Layer<R: Register, F> {
+    registry: R,
+    inspect: F,
+    shutdown: Shutdown,
+}
+
+/// Makes wrapped Services to tap rejected requests.
+#[derive(Clone, Debug)]
+pub struct Stack<R: Register, F, M> {
+    registry: R,
+    inspect: F,
+    shutdown: Shutdown,
+    inner: M,
+}
+
+/// Future returned by `Stack`.
+pub struct MakeFuture<F, R, I> {
+    inner: F,
+    next: Option<(R, I)>,
+    shutdown: Shutdown,
+}
+
+/// A middleware that taps the requests that its inner service rejects.
+#[derive(Clone, Debug)]
+pub struct Service<I, R, T, S> {
+    tap_rx: R,
+    taps: Vec<T>,
+    inner: S,
+    inspect: I,
+    shutdown: Shutdown,
+}
+
+pub struct ResponseFuture<F, I, T> {
+    inner: F,
+    /// Set when the request may be tapped if it is rejected.
+    rejected: Option<Rejected<I, T>>,
+    shutdown: Shutdown,
+}
+
+/// Describes a request that may be tapped if it is rejected.
+struct Rejected<I, T> {
+    /// The request's head, without its body, which is never tapped.
+    req: http::Request<::hyper::Body>,
+    inspect: I,
+    taps: Vec<T>,
+    observed: Observed,
+}
+
+/// Set when an endpoint's stack taps a request, so that the request is not
+/// tapped again if it fails.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Observed(Arc<AtomicBool>);
+
+// === Layer ===
+
+impl<R, F> Layer<R, F>
+where
+    R: Register + Clone,
+{
+    pub(super) fn new(registry: R, inspect: F, shutdown: Shutdown) -> Self {
+        Self {
+            registry,
+            inspect,
+            shutdown,
+        }
+    }
+}
+
+impl<R, F, M> svc::Layer<M> for Layer<R, F>
+where
+    R: Register + Clone,
+    F: Clone,
+{
+    type Service = Stack<R, F, M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            inner,
+            registry: self.registry.clone(),
+            inspect: self.inspect.clone(),
+            shutdown: self.shutdown.clone(),
+        }
+    }
+}
+
+// === Stack ===
+
+impl<R, F, I, T, M> svc::Service<T> for Stack<R, F, M>
+where
+    F: Fn(&T) -> I,
+    I: Inspect + Clone + Send + Sync + 'static,
+    R: Register,
+    M: svc::Service<T>,
+{
+    type Response = Service<I, R::Taps, R::Tap, M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future, R::Taps, I>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let inspect = (self.inspect)(&target);
+        let inner = self.inner.call(target);
+        let tap_rx = self.registry.register();
+        MakeFuture {
+            inner,
+            next: Some((tap_rx, inspect)),
+            shutdown: self.shutdown.clone(),
+        }
+    }
+}
+
+// === MakeFuture ===
+
+impl<F, Taps, I> Future for MakeFuture<F, Taps, I>
+where
+    F: Future,
+    Taps: Stream,
+{
+    type Item = Service<I, Taps, Taps::Item, F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        let (tap_rx, inspect) = self.next.take().expect("poll more than once");
+        Ok(Service {
+            inner,
+            tap_rx,
+            taps: Vec::default(),
+            inspect,
+            shutdown: self.shutdown.clone(),
+        }
+        .into())
+    }
+}
+
+// === Service ===
+
+impl<I, R, T, S> Service<I, R, T, S> {
+    #[cfg(test)]
+    pub(super) fn for_test(tap_rx: R, inner: S, inspect: I) -> Self {
+        Self {
+            tap_rx,
+            taps: Vec::default(),
+            inner,
+            inspect,
+            shutdown: Shutdown::default(),
+        }
+    }
+}
+
+impl<I, R, S, T, A, B> svc::Service<http::Request<A>> for Service<I, R, T, S>
+where
+    I: Inspect + Clone + Send + Sync + 'static,
+    R: Stream<Item = T>,
+    T: Tap,
+    T::TapResponsePayload: Send + 'static,
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    B: HyperPayload,
+    B::Error: HasH2Reason,
+{
+    type Response = http::Response<Payload<B, T::TapResponsePayload>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, I, T>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        // Load new taps from the tap server.
+        while let Ok(Async::Ready(Some(t))) = self.tap_rx.poll() {
+            self.taps.push(t);
+        }
+
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        self.taps.retain(|t| t.can_tap_more());
+
+        let is_outbound = self.inspect.is_outbound(&req);
+        let taps = self
+            .taps
+            .iter()
+            .filter(|t| t.may_match(is_outbound))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let rejected = if taps.is_empty() {
+            None
+        } else {
+            let observed = Observed::default();
+            req.extensions_mut().insert(observed.clone());
+            let mut head = head(&req);
+            head.extensions_mut().insert(self.inspect.clone());
+            Some(Rejected {
+                req: head,
+                inspect: self.inspect.clone(),
+                taps,
+                observed,
+            })
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            rejected,
+            shutdown: self.shutdown.clone(),
+        }
+    }
+}
+
+impl<F, I, T, B> Future for ResponseFuture<F, I, T>
+where
+    F: Future<Item = http::Response<B>>,
+    I: Inspect,
+    T: Tap,
+    T::TapResponsePayload: Send + 'static,
+    B: HyperPayload,
+    B::Error: HasH2Reason,
+{
+    type Item = http::Response<Payload<B, T::TapResponsePayload>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = try_ready!(self.inner.poll());
+        let taps = self
+            .rejected
+            .take()
+            .map(|r| r.tap(&rsp))
+            .unwrap_or_default();
+        let shutdown = self.shutdown.clone();
+        let rsp = rsp.map(move |inner| Payload::new(inner, taps, shutdown));
+        Ok(rsp.into())
+    }
+}
+
+// === Rejected ===
+
+impl<I: Inspect, T: Tap> Rejected<I, T> {
+    /// Taps the request if the proxy responded to it without it being tapped
+    /// by an endpoint, returning the taps for the response's body.
+    fn tap<B: HyperPayload>(self, rsp: &http::Response<B>) -> Vec<T::TapResponsePayload> {
+        if !ProxyResponse::is_marked(rsp) || self.observed.is_observed() {
+            return Vec::new();
+        }
+
+        let Rejected {
+            req, inspect, taps, ..
+        } = self;
+        taps.into_iter()
+            .filter_map(|mut tap| {
+                let (req_tap, rsp_tap) = isolate(|| tap.tap(&req, &inspect))??;
+                // The request's body is never read by the proxy.
+                isolate(|| req_tap.eos(None))?;
+                isolate(|| rsp_tap.tap(rsp))
+            })
+            .collect()
+    }
+}
+
+// === Observed ===
+
+impl Observed {
+    /// Marks a request as tapped, if it may otherwise be tapped when rejected.
+    pub(super) fn mark<B>(req: &http::Request<B>) {
+        if let Some(observed) = req.extensions().get::<Observed>() {
+            observed.0.store(true, Ordering::Release);
+        }
+    }
+
+    fn is_observed(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Copies a request's head, so that it may be tapped after the request has
+/// been dispatched.
+fn head<B>(req: &http::Request<B>) -> http::Request<::hyper::Body> {
+    let mut head = http::Request::new(::hyper::Body::empty());
+    *head.method_mut() = req.method().clone();
+    *head.uri_mut() = req.uri().clone();
+    *head.version_mut() = req.version();
+    *head.headers_mut() = req.headers().clone();
+    head
+}
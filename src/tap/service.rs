@@ -2,9 +2,11 @@ use bytes::IntoBuf;
 use futures::{Async, Future, Poll, Stream};
 use http;
 use hyper::body::Payload as HyperPayload;
+use std::panic::{self, AssertUnwindSafe};
 
 use super::iface::{Register, Tap, TapPayload, TapResponse};
-use super::Inspect;
+use super::rejections::{self, Observed};
+use super::{Inspect, Shutdown};
 use proxy::http::HasH2Reason;
 use svc;
 
@@ -12,12 +14,14 @@ use svc;
 #[derive(Clone, Debug)]
 pub struct Layer<R: Register> {
     registry: R,
+    shutdown: Shutdown,
 }
 
 /// Makes wrapped Services to record taps.
 #[derive(Clone, Debug)]
 pub struct Stack<R: Register, T> {
     registry: R,
+    shutdown: Shutdown,
     inner: T,
 }
 
@@ -25,6 +29,7 @@ pub struct Stack<R: Register, T> {
 pub struct MakeFuture<F, R, T> {
     inner: F,
     next: Option<(R, T)>,
+    shutdown: Shutdown,
 }
 
 /// A middleware that records HTTP taps.
@@ -32,13 +37,17 @@ pub struct MakeFuture<F, R, T> {
 pub struct Service<I, R, T, S> {
     tap_rx: R,
     taps: Vec<T>,
+    /// Caches whether any of `taps` may match this service's requests.
+    may_tap: Option<bool>,
     inner: S,
     inspect: I,
+    shutdown: Shutdown,
 }
 
 pub struct ResponseFuture<F, T> {
     inner: F,
     taps: Vec<T>,
+    shutdown: Shutdown,
 }
 
 // A `Payload` instrumented with taps.
@@ -51,6 +60,14 @@ where
 {
     inner: B,
     taps: Vec<T>,
+    /// Set once the inner payload has yielded all of its data, after which
+    /// the payload is complete even if its trailers are never polled.
+    data_complete: bool,
+    /// Set once the taps have been notified that the payload has ended, so
+    /// that they are notified only once, however its data and trailers are
+    /// polled.
+    done: bool,
+    shutdown: Shutdown,
 }
 
 // === Layer ===
@@ -59,8 +76,15 @@ impl<R> Layer<R>
 where
     R: Register + Clone,
 {
-    pub(super) fn new(registry: R) -> Self {
-        Self { registry }
+    pub(super) fn new(registry: R, shutdown: Shutdown) -> Self {
+        Self { registry, shutdown }
+    }
+
+    /// Builds a layer that taps the requests that the proxy rejects before
+    /// they reach an endpoint's stack, describing each target with the
+    /// `Inspect` that `inspect` builds from it.
+    pub fn rejections<F>(&self, inspect: F) -> rejections::Layer<R, F> {
+        rejections::Layer::new(self.registry.clone(), inspect, self.shutdown.clone())
     }
 }
 
@@ -74,6 +98,7 @@ where
         Stack {
             inner,
             registry: self.registry.clone(),
+            shutdown: self.shutdown.clone(),
         }
     }
 }
@@ -101,6 +126,7 @@ where
         MakeFuture {
             inner,
             next: Some((tap_rx, inspect)),
+            shutdown: self.shutdown.clone(),
         }
     }
 }
@@ -122,7 +148,9 @@ where
             inner,
             tap_rx,
             taps: Vec::default(),
+            may_tap: None,
             inspect,
+            shutdown: self.shutdown.clone(),
         }
         .into())
     }
@@ -152,10 +180,13 @@ where
         // Load new taps from the tap server.
         while let Ok(Async::Ready(Some(t))) = self.tap_rx.poll() {
             self.taps.push(t);
+            self.may_tap = None;
         }
-        // Drop taps that have been canceled or completed.
-        self.taps.retain(|t| t.can_tap_more());
 
+        // Taps that have been canceled or completed are dropped lazily, when
+        // they fail to tap a request, so that active taps need not be checked
+        // on every poll. Taps only apply backpressure to the streams that
+        // they have tapped, so the service's readiness never depends on them.
         self.inner.poll_ready()
     }
 
@@ -164,28 +195,67 @@ where
         let mut req_taps = Vec::new();
         let mut rsp_taps = Vec::new();
 
-        for t in &mut self.taps {
-            if let Some((req_tap, rsp_tap)) = t.tap(&req, &self.inspect) {
-                req_taps.push(req_tap);
-                rsp_taps.push(rsp_tap);
+        if self.may_tap(&req) {
+            let mut i = 0;
+            while i < self.taps.len() {
+                // A tap that panics is dropped, and the request proceeds
+                // without it.
+                let tapped = isolate(|| self.taps[i].tap(&req, &self.inspect));
+                match tapped {
+                    Some(Some((req_tap, rsp_tap))) => {
+                        req_taps.push(req_tap);
+                        rsp_taps.push(rsp_tap);
+                        i += 1;
+                    }
+                    Some(None) if self.taps[i].can_tap_more() => i += 1,
+                    _ => {
+                        self.taps.swap_remove(i);
+                    }
+                }
             }
         }
 
+        // Requests tapped here need not be tapped again if they fail.
+        if !req_taps.is_empty() {
+            Observed::mark(&req);
+        }
+
         // Install the request taps into the request body.
-        let req = req.map(move |inner| Payload {
-            inner,
-            taps: req_taps,
-        });
+        let shutdown = self.shutdown.clone();
+        let req = req.map(move |inner| Payload::new(inner, req_taps, shutdown));
 
         let inner = self.inner.call(req);
 
         ResponseFuture {
             inner,
             taps: rsp_taps,
+            shutdown: self.shutdown.clone(),
         }
     }
 }
 
+impl<I: Inspect, R, T: Tap, S> Service<I, R, T, S> {
+    /// Returns `false` if no installed tap can match this service's requests,
+    /// so that requests need not be matched against each tap.
+    ///
+    /// All of a service's requests are in the same direction, so this is only
+    /// evaluated again when a tap is installed. Dropping taps cannot cause a
+    /// request to match, so the cached value remains safe to use.
+    fn may_tap<B>(&mut self, req: &http::Request<B>) -> bool {
+        if self.taps.is_empty() {
+            return false;
+        }
+        if let Some(may_tap) = self.may_tap {
+            return may_tap;
+        }
+
+        let is_outbound = self.inspect.is_outbound(req);
+        let may_tap = self.taps.iter().any(|t| t.may_match(is_outbound));
+        self.may_tap = Some(may_tap);
+        may_tap
+    }
+}
+
 impl<F, T, B> Future for ResponseFuture<F, T>
 where
     F: Future<Item = http::Response<B>>,
@@ -199,24 +269,27 @@ where
     type Error = F::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if !self.taps.iter_mut().all(|t| t.poll_ready().is_ready()) {
+            return Ok(Async::NotReady);
+        }
+
         match self.inner.poll() {
             Ok(Async::NotReady) => Ok(Async::NotReady),
             Ok(Async::Ready(rsp)) => {
                 // Tap the response headers and use the response
                 // body taps to decorate the response body.
-                let taps = self.taps.drain(..).map(|t| t.tap(&rsp)).collect();
-                let rsp = rsp.map(move |inner| {
-                    let mut body = Payload { inner, taps };
-                    if body.is_end_stream() {
-                        body.eos(None);
-                    }
-                    body
-                });
+                let taps = self
+                    .taps
+                    .drain(..)
+                    .filter_map(|t| isolate(|| t.tap(&rsp)))
+                    .collect();
+                let shutdown = self.shutdown.clone();
+                let rsp = rsp.map(move |inner| Payload::new(inner, taps, shutdown));
                 Ok(Async::Ready(rsp))
             }
             Err(e) => {
                 for tap in self.taps.drain(..) {
-                    tap.fail(&e);
+                    isolate(|| tap.fail(&e));
                 }
                 Err(e)
             }
@@ -237,6 +310,9 @@ where
         Self {
             inner: B::default(),
             taps: Vec::default(),
+            data_complete: false,
+            done: false,
+            shutdown: Shutdown::default(),
         }
     }
 }
@@ -255,13 +331,22 @@ where
     }
 
     fn poll_data(&mut self) -> Poll<Option<Self::Data>, B::Error> {
+        if !self.taps_ready() {
+            return Ok(Async::NotReady);
+        }
+
         let poll_frame = self.inner.poll_data().map_err(|e| self.err(e));
         let frame = try_ready!(poll_frame).map(|f| f.into_buf());
+        self.data_complete = frame.is_none();
         self.data(frame.as_ref());
         Ok(Async::Ready(frame))
     }
 
     fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, B::Error> {
+        if !self.taps_ready() {
+            return Ok(Async::NotReady);
+        }
+
         let trailers = try_ready!(self.inner.poll_trailers().map_err(|e| self.err(e)));
         self.eos(trailers.as_ref());
         Ok(Async::Ready(trailers))
@@ -274,10 +359,36 @@ where
     B::Error: HasH2Reason,
     T: TapPayload,
 {
+    pub(super) fn new(inner: B, taps: Vec<T>, shutdown: Shutdown) -> Self {
+        let mut body = Self {
+            inner,
+            taps,
+            data_complete: false,
+            done: false,
+            shutdown,
+        };
+        if body.inner.is_end_stream() {
+            body.eos(None);
+        }
+        body
+    }
+
+    /// Taps that apply backpressure may delay the body from being read until
+    /// they have capacity to record its events.
+    fn taps_ready(&mut self) -> bool {
+        self.taps.iter_mut().all(|t| t.poll_ready().is_ready())
+    }
+
     fn data(&mut self, frame: Option<&<B::Data as IntoBuf>::Buf>) {
         if let Some(ref f) = frame {
-            for ref mut tap in self.taps.iter_mut() {
-                tap.data::<<B::Data as IntoBuf>::Buf>(f);
+            let mut i = 0;
+            while i < self.taps.len() {
+                let recorded = isolate(|| self.taps[i].data::<<B::Data as IntoBuf>::Buf>(f));
+                if recorded.is_some() {
+                    i += 1;
+                } else {
+                    self.taps.swap_remove(i);
+                }
             }
         }
 
@@ -287,14 +398,20 @@ where
     }
 
     fn eos(&mut self, trailers: Option<&http::HeaderMap>) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+
         for tap in self.taps.drain(..) {
-            tap.eos(trailers);
+            isolate(|| tap.eos(trailers));
         }
     }
 
     fn err(&mut self, error: B::Error) -> B::Error {
+        self.done = true;
         for tap in self.taps.drain(..) {
-            tap.fail(&error);
+            isolate(|| tap.fail(&error));
         }
 
         error
@@ -308,6 +425,716 @@ where
     T: TapPayload,
 {
     fn drop(&mut self) {
-        self.eos(None);
+        // If the payload is dropped before all of its data has been read, it
+        // was abandoned rather than completed, e.g. because the proxy is
+        // shutting down.
+        if self.done {
+            return;
+        }
+        if self.data_complete {
+            self.eos(None);
+        } else if self.shutdown.is_shutdown() {
+            for tap in self.taps.drain(..) {
+                isolate(|| tap.shutdown());
+            }
+        } else {
+            for tap in self.taps.drain(..) {
+                isolate(|| tap.cancel());
+            }
+        }
+    }
+}
+
+/// Invokes a tap callback, returning `None` if it panics, so that a faulty
+/// tap cannot disrupt the traffic that it observes.
+///
+/// Because the panic is recovered, taps must not rely on lock poisoning to
+/// guard state that is shared with other streams.
+pub(super) fn isolate<F, T>(f: F) -> Option<T>
+where
+    F: FnOnce() -> T,
+{
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .map_err(|_| warn!("tap panicked; it will no longer observe this stream"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Buf;
+    use futures::{future, stream};
+    use hyper;
+    use indexmap::IndexMap;
+    use std::collections::VecDeque;
+    use std::net;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use identity;
+    use proxy::http::ProxyResponse;
+    use svc::Service as _;
+    use transport::tls::ReasonForNoIdentity;
+    use Conditional;
+
+    type Events = Arc<Mutex<Vec<&'static str>>>;
+
+    #[derive(Clone)]
+    struct MockTap {
+        events: Events,
+        active: Arc<AtomicBool>,
+        may_match: bool,
+    }
+
+    struct MockTapResponse(Events);
+
+    struct MockTapPayload {
+        events: Events,
+        data: &'static str,
+        eos: &'static str,
+        cancel: &'static str,
+        /// Set when the request's `x-panic` header is `data`.
+        panics: bool,
+    }
+
+    #[derive(Clone)]
+    struct MockInspect;
+
+    /// Reads the request body before responding.
+    struct Echo;
+
+    /// Fails every request.
+    struct Unavailable;
+
+    /// Responds to every request as the proxy does when it rejects a request,
+    /// after marking the request as tapped if `observed` is set.
+    struct Reject {
+        observed: bool,
+    }
+
+    /// Responds with a `Truncated` body.
+    struct Truncate;
+
+    /// Yields a single frame and then fails.
+    #[derive(Default)]
+    struct Truncated(bool);
+
+    /// Yields its frames and then its trailers. If `inline_eos` is set, the
+    /// payload ends with its last frame.
+    #[derive(Default)]
+    struct Scripted {
+        frames: VecDeque<&'static [u8]>,
+        trailers: Option<http::HeaderMap>,
+        inline_eos: bool,
+    }
+
+    type Error = Box<dyn::std::error::Error + Send + Sync>;
+
+    impl Tap for MockTap {
+        type TapRequestPayload = MockTapPayload;
+        type TapResponse = MockTapResponse;
+        type TapResponsePayload = MockTapPayload;
+
+        fn can_tap_more(&self) -> bool {
+            self.active.load(Ordering::SeqCst)
+        }
+
+        fn may_match(&self, _: bool) -> bool {
+            self.may_match
+        }
+
+        /// Panics when the request's `x-panic` header is `tap`.
+        fn tap<B: HyperPayload, I: Inspect>(
+            &mut self,
+            req: &http::Request<B>,
+            _: &I,
+        ) -> Option<(MockTapPayload, MockTapResponse)> {
+            if !self.can_tap_more() {
+                return None;
+            }
+
+            let panic = req.headers().get("x-panic");
+            if panic.map(|v| v == "tap").unwrap_or(false) {
+                panic!("tap");
+            }
+
+            self.events.lock().unwrap().push("request_init");
+            let req = MockTapPayload {
+                events: self.events.clone(),
+                data: "request_data",
+                eos: "request_end",
+                cancel: "request_cancel",
+                panics: panic.map(|v| v == "data").unwrap_or(false),
+            };
+            Some((req, MockTapResponse(self.events.clone())))
+        }
+    }
+
+    impl TapResponse for MockTapResponse {
+        type TapPayload = MockTapPayload;
+
+        fn tap<B: HyperPayload>(self, _: &http::Response<B>) -> MockTapPayload {
+            self.0.lock().unwrap().push("response_init");
+            MockTapPayload {
+                events: self.0,
+                data: "response_data",
+                eos: "response_end",
+                cancel: "response_cancel",
+                panics: false,
+            }
+        }
+
+        fn fail<E: HasH2Reason>(self, _: &E) {
+            self.0.lock().unwrap().push("response_fail");
+        }
+    }
+
+    impl TapPayload for MockTapPayload {
+        fn data<B: Buf>(&mut self, _: &B) {
+            if self.panics {
+                panic!("data");
+            }
+            self.events.lock().unwrap().push(self.data);
+        }
+
+        fn eos(self, _: Option<&http::HeaderMap>) {
+            self.events.lock().unwrap().push(self.eos);
+        }
+
+        fn fail<E: HasH2Reason>(self, _: &E) {
+            self.events.lock().unwrap().push("fail");
+        }
+
+        fn cancel(self) {
+            self.events.lock().unwrap().push(self.cancel);
+        }
+
+        fn shutdown(self) {
+            self.events.lock().unwrap().push("shutdown");
+        }
+    }
+
+    impl Inspect for MockInspect {
+        fn src_addr<B>(&self, _: &http::Request<B>) -> Option<net::SocketAddr> {
+            None
+        }
+
+        fn src_tls<'a, B>(
+            &self,
+            _: &'a http::Request<B>,
+        ) -> Conditional<&'a identity::Name, ReasonForNoIdentity> {
+            Conditional::None(ReasonForNoIdentity::Disabled)
+        }
+
+        fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<net::SocketAddr> {
+            None
+        }
+
+        fn dst_labels<B>(&self, _: &http::Request<B>) -> Option<&IndexMap<String, String>> {
+            None
+        }
+
+        fn dst_tls<B>(
+            &self,
+            _: &http::Request<B>,
+        ) -> Conditional<&identity::Name, ReasonForNoIdentity> {
+            Conditional::None(ReasonForNoIdentity::Disabled)
+        }
+
+        fn route_labels<B>(&self, _: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
+            None
+        }
+
+        fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
+            false
+        }
+    }
+
+    impl svc::Service<http::Request<Payload<hyper::Body, MockTapPayload>>> for Echo {
+        type Response = http::Response<hyper::Body>;
+        type Error = hyper::Error;
+        type Future = future::FutureResult<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(
+            &mut self,
+            req: http::Request<Payload<hyper::Body, MockTapPayload>>,
+        ) -> Self::Future {
+            let mut body = req.into_body();
+            while let Async::Ready(Some(_)) = body.poll_data().expect("request body") {}
+            future::ok(http::Response::new(hyper::Body::from("pong")))
+        }
+    }
+
+    impl svc::Service<http::Request<Payload<hyper::Body, MockTapPayload>>> for Unavailable {
+        type Response = http::Response<hyper::Body>;
+        type Error = Error;
+        type Future = future::FutureResult<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: http::Request<Payload<hyper::Body, MockTapPayload>>) -> Self::Future {
+            future::err("unavailable".into())
+        }
+    }
+
+    impl svc::Service<http::Request<hyper::Body>> for Reject {
+        type Response = http::Response<hyper::Body>;
+        type Error = hyper::Error;
+        type Future = future::FutureResult<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, req: http::Request<hyper::Body>) -> Self::Future {
+            if self.observed {
+                Observed::mark(&req);
+            }
+            let mut rsp = http::Response::new(hyper::Body::empty());
+            ProxyResponse::mark(&mut rsp);
+            future::ok(rsp)
+        }
+    }
+
+    impl svc::Service<http::Request<Payload<hyper::Body, MockTapPayload>>> for Truncate {
+        type Response = http::Response<Truncated>;
+        type Error = Error;
+        type Future = future::FutureResult<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: http::Request<Payload<hyper::Body, MockTapPayload>>) -> Self::Future {
+            future::ok(http::Response::new(Truncated::default()))
+        }
+    }
+
+    impl HyperPayload for Truncated {
+        type Data = ::std::io::Cursor<&'static [u8]>;
+        type Error = Error;
+
+        fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+            if self.0 {
+                return Err("truncated".into());
+            }
+            self.0 = true;
+            Ok(Async::Ready(Some(::std::io::Cursor::new(&b"po"[..]))))
+        }
+    }
+
+    impl HyperPayload for Scripted {
+        type Data = ::std::io::Cursor<&'static [u8]>;
+        type Error = Error;
+
+        fn is_end_stream(&self) -> bool {
+            self.inline_eos && self.frames.is_empty()
+        }
+
+        fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+            Ok(Async::Ready(
+                self.frames.pop_front().map(::std::io::Cursor::new),
+            ))
+        }
+
+        fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+            Ok(Async::Ready(self.trailers.take()))
+        }
+    }
+
+    /// Instruments `inner` with a single tap.
+    fn new_payload(events: &Events, inner: Scripted) -> Payload<Scripted, MockTapPayload> {
+        let tap = MockTapPayload {
+            events: events.clone(),
+            data: "data",
+            eos: "end",
+            cancel: "cancel",
+            panics: false,
+        };
+        Payload::new(inner, vec![tap], Shutdown::default())
+    }
+
+    /// Builds a service that has registered a single tap.
+    fn new_service(
+        events: &Events,
+    ) -> Service<MockInspect, stream::IterOk<::std::vec::IntoIter<MockTap>, ()>, MockTap, Echo>
+    {
+        new_service_with_tap(MockTap {
+            events: events.clone(),
+            active: Arc::new(AtomicBool::new(true)),
+            may_match: true,
+        })
+    }
+
+    fn new_service_with_tap(
+        tap: MockTap,
+    ) -> Service<MockInspect, stream::IterOk<::std::vec::IntoIter<MockTap>, ()>, MockTap, Echo>
+    {
+        let mut service = Service {
+            tap_rx: stream::iter_ok(vec![tap]),
+            taps: Vec::default(),
+            may_tap: None,
+            inner: Echo,
+            inspect: MockInspect,
+            shutdown: Shutdown::default(),
+        };
+        let ready = svc::Service::<http::Request<hyper::Body>>::poll_ready(&mut service);
+        assert!(ready.expect("ready").is_ready());
+        service
+    }
+
+    #[test]
+    fn taps_request_and_response_in_order() {
+        let events = Events::default();
+        let mut service = new_service(&events);
+
+        let req = http::Request::new(hyper::Body::from("ping"));
+        let rsp = service.call(req).wait().expect("response");
+
+        let mut body = rsp.into_body();
+        while let Async::Ready(Some(_)) = body.poll_data().expect("response body") {}
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "request_init",
+                "request_data",
+                "request_end",
+                "response_init",
+                "response_data",
+                "response_end",
+            ]
+        );
+    }
+
+    /// Builds a service that has installed a single tap and is served by
+    /// `inner`.
+    fn new_service_with_inner<S>(
+        events: &Events,
+        inner: S,
+    ) -> Service<MockInspect, stream::IterOk<::std::vec::IntoIter<MockTap>, ()>, MockTap, S> {
+        Service {
+            tap_rx: stream::iter_ok(vec![]),
+            taps: vec![MockTap {
+                events: events.clone(),
+                active: Arc::new(AtomicBool::new(true)),
+                may_match: true,
+            }],
+            may_tap: None,
+            inner,
+            inspect: MockInspect,
+            shutdown: Shutdown::default(),
+        }
+    }
+
+    #[test]
+    fn ends_completed_responses_once() {
+        let events = Events::default();
+        let mut service = new_service(&events);
+
+        let rsp = service
+            .call(http::Request::new(hyper::Body::from("ping")))
+            .wait()
+            .expect("response");
+        let mut body = rsp.into_body();
+        while let Async::Ready(Some(_)) = body.poll_data().expect("response body") {}
+        drop(body);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "request_init",
+                "request_data",
+                "request_end",
+                "response_init",
+                "response_data",
+                "response_end",
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_failed_responses_once() {
+        let events = Events::default();
+        let mut service = new_service_with_inner(&events, Unavailable);
+
+        let rsp = service.call(http::Request::new(hyper::Body::empty()));
+        assert!(rsp.wait().is_err(), "response must fail");
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["request_init", "request_end", "response_fail"]
+        );
+    }
+
+    #[test]
+    fn ends_failed_response_bodies_once() {
+        let events = Events::default();
+        let mut service = new_service_with_inner(&events, Truncate);
+
+        let rsp = service
+            .call(http::Request::new(hyper::Body::empty()))
+            .wait()
+            .expect("response");
+        let mut body = rsp.into_body();
+        assert!(body.poll_data().expect("first frame").is_ready());
+        body.poll_data().expect_err("body must fail");
+        drop(body);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "request_init",
+                "request_end",
+                "response_init",
+                "response_data",
+                "fail",
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_payloads_with_trailers_once() {
+        let events = Events::default();
+        let mut body = new_payload(
+            &events,
+            Scripted {
+                frames: vec![&b"po"[..], &b"ng"[..]].into(),
+                trailers: Some(http::HeaderMap::new()),
+                inline_eos: false,
+            },
+        );
+        while let Async::Ready(Some(_)) = body.poll_data().expect("data") {}
+        assert_eq!(*events.lock().unwrap(), vec!["data", "data"]);
+
+        assert!(body.poll_trailers().expect("trailers").is_ready());
+        assert!(body.poll_trailers().expect("trailers").is_ready());
+        drop(body);
+        assert_eq!(*events.lock().unwrap(), vec!["data", "data", "end"]);
+    }
+
+    #[test]
+    fn ends_trailers_only_payloads_once() {
+        let events = Events::default();
+        let mut body = new_payload(
+            &events,
+            Scripted {
+                trailers: Some(http::HeaderMap::new()),
+                ..Scripted::default()
+            },
+        );
+        assert!(body.poll_trailers().expect("trailers").is_ready());
+        drop(body);
+        assert_eq!(*events.lock().unwrap(), vec!["end"]);
+    }
+
+    #[test]
+    fn ends_payloads_with_inline_eos_once() {
+        let events = Events::default();
+        let mut body = new_payload(
+            &events,
+            Scripted {
+                frames: vec![&b"pong"[..]].into(),
+                inline_eos: true,
+                ..Scripted::default()
+            },
+        );
+        assert!(body.poll_data().expect("data").is_ready());
+        assert_eq!(*events.lock().unwrap(), vec!["data", "end"]);
+
+        assert!(body.poll_data().expect("data").is_ready());
+        assert!(body.poll_trailers().expect("trailers").is_ready());
+        drop(body);
+        assert_eq!(*events.lock().unwrap(), vec!["data", "end"]);
+    }
+
+    #[test]
+    fn taps_empty_request_body_as_complete() {
+        let events = Events::default();
+        let mut service = new_service(&events);
+
+        let rsp = service
+            .call(http::Request::new(hyper::Body::empty()))
+            .wait()
+            .expect("response");
+        drop(rsp);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "request_init",
+                "request_end",
+                "response_init",
+                "response_cancel",
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_completed_taps_when_they_fail_to_tap() {
+        let events = Events::default();
+        let active = Arc::new(AtomicBool::new(true));
+        let mut service = new_service_with_tap(MockTap {
+            events: events.clone(),
+            active: active.clone(),
+            may_match: true,
+        });
+        assert_eq!(service.taps.len(), 1);
+
+        active.store(false, Ordering::SeqCst);
+        let ready = svc::Service::<http::Request<hyper::Body>>::poll_ready(&mut service);
+        assert!(ready.expect("ready").is_ready());
+        assert_eq!(service.taps.len(), 1, "ready taps are not checked");
+
+        drop(service.call(http::Request::new(hyper::Body::empty())));
+        assert!(service.taps.is_empty());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn taps_rejected_requests() {
+        let events = Events::default();
+        let tap = MockTap {
+            events: events.clone(),
+            active: Arc::new(AtomicBool::new(true)),
+            may_match: true,
+        };
+        let inner = Reject { observed: false };
+        let mut service =
+            rejections::Service::for_test(stream::iter_ok(vec![tap]), inner, MockInspect);
+        let ready = svc::Service::<http::Request<hyper::Body>>::poll_ready(&mut service);
+        assert!(ready.expect("ready").is_ready());
+
+        let rsp = service
+            .call(http::Request::new(hyper::Body::from("ping")))
+            .wait()
+            .expect("response");
+        drop(rsp);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "request_init",
+                "request_end",
+                "response_init",
+                "response_end",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_rejected_requests_tapped_by_endpoints() {
+        let events = Events::default();
+        let tap = MockTap {
+            events: events.clone(),
+            active: Arc::new(AtomicBool::new(true)),
+            may_match: true,
+        };
+        let inner = Reject { observed: true };
+        let mut service =
+            rejections::Service::for_test(stream::iter_ok(vec![tap]), inner, MockInspect);
+        let ready = svc::Service::<http::Request<hyper::Body>>::poll_ready(&mut service);
+        assert!(ready.expect("ready").is_ready());
+
+        let rsp = service
+            .call(http::Request::new(hyper::Body::from("ping")))
+            .wait()
+            .expect("response");
+        drop(rsp);
+
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn skips_taps_that_cannot_match() {
+        let events = Events::default();
+        let mut service = new_service_with_tap(MockTap {
+            events: events.clone(),
+            active: Arc::new(AtomicBool::new(true)),
+            may_match: false,
+        });
+
+        let rsp = service
+            .call(http::Request::new(hyper::Body::empty()))
+            .wait()
+            .expect("response");
+        drop(rsp);
+
+        assert_eq!(service.may_tap, Some(false));
+        assert_eq!(service.taps.len(), 1);
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn drops_taps_that_panic() {
+        let events = Events::default();
+        let mut service = new_service(&events);
+
+        let req = http::Request::builder()
+            .header("x-panic", "tap")
+            .body(hyper::Body::from("ping"))
+            .unwrap();
+        let rsp = service.call(req).wait().expect("response");
+        let mut body = rsp.into_body();
+        while let Async::Ready(Some(_)) = body.poll_data().expect("response body") {}
+
+        assert!(service.taps.is_empty());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn drops_payload_taps_that_panic() {
+        let events = Events::default();
+        let mut service = new_service(&events);
+
+        let req = http::Request::builder()
+            .header("x-panic", "data")
+            .body(hyper::Body::from("ping"))
+            .unwrap();
+        let rsp = service.call(req).wait().expect("response");
+        let mut body = rsp.into_body();
+        while let Async::Ready(Some(_)) = body.poll_data().expect("response body") {}
+
+        // The request body's tap is dropped without ending, but the response
+        // is still tapped.
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "request_init",
+                "response_init",
+                "response_data",
+                "response_end",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_streams_abandoned_on_shutdown() {
+        let events = Events::default();
+        let mut service = new_service(&events);
+
+        let rsp = service
+            .call(http::Request::new(hyper::Body::from("ping")))
+            .wait()
+            .expect("response");
+        service.shutdown.shutdown();
+        drop(rsp);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "request_init",
+                "request_data",
+                "request_end",
+                "response_init",
+                "shutdown",
+            ]
+        );
     }
 }
@@ -0,0 +1,436 @@
+//! Writes tapped streams as newline-delimited spans, in the JSON encoding of
+//! the OpenTelemetry protocol, so that they may be ingested by tracing
+//! backends.
+//!
+//! Each stream's lifecycle events are combined into a single span, which is
+//! written when the stream's response ends.
+//!
+//! A stream's `ResponseEnd` may never be seen, e.g. if its response is dropped
+//! before it is initiated or if the event is dropped because the sink's buffer
+//! is full, so only a bounded number of unended streams are held. Once that
+//! many are held, the oldest stream is discarded as each new stream begins.
+
+use futures::{Future, Stream};
+use rand;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use api::tap as api;
+
+use super::json::{self, Object};
+use super::{Match, Server};
+
+/// `SPAN_KIND_SERVER` and `SPAN_KIND_CLIENT`, respectively.
+const KIND_INBOUND: i32 = 2;
+const KIND_OUTBOUND: i32 = 3;
+
+/// `STATUS_CODE_UNSET` and `STATUS_CODE_ERROR`, respectively.
+const STATUS_UNSET: i32 = 0;
+const STATUS_ERROR: i32 = 2;
+
+/// The number of streams that may be held until their responses end.
+const MAX_OPEN_SPANS: usize = 10_000;
+
+/// Writes a span to `writer` for every tapped stream, one JSON object per
+/// line.
+///
+/// Spans are written synchronously, so `writer` must not block for long, e.g.
+/// a local file.
+pub fn sink<W: Write>(mut server: Server, mut writer: W) -> impl Future<Item = (), Error = ()> {
+    let mut spans = Spans::default();
+    server
+        .tap(Match::All(vec![]), ::std::usize::MAX)
+        .map_err(|e| error!("tap span sink failed: {}", e))
+        .and_then(move |events| {
+            events.for_each(move |ev| match spans.record(&ev) {
+                Some(span) => writeln!(writer, "{}", span.encode(&trace_id()))
+                    .map_err(|e| error!("failed to write tap span: {}", e)),
+                None => Ok(()),
+            })
+        })
+}
+
+/// Tapped streams that have not yet ended, by stream ID.
+///
+/// A tap numbers its streams in the order that they are tapped, so the first
+/// entry is always the oldest.
+#[derive(Debug)]
+struct Spans {
+    open: BTreeMap<(u32, u64), Span>,
+    capacity: usize,
+}
+
+/// Describes a tapped stream.
+#[derive(Debug, Default, PartialEq)]
+struct Span {
+    base: u32,
+    stream: u64,
+    outbound: bool,
+    method: Option<String>,
+    authority: String,
+    path: String,
+    http_status: Option<u32>,
+    start_unix_us: u64,
+    end_unix_us: u64,
+    response_bytes: u64,
+    grpc_status: Option<u32>,
+    reset_error_code: Option<u32>,
+}
+
+impl Default for Spans {
+    fn default() -> Self {
+        Self::with_capacity(MAX_OPEN_SPANS)
+    }
+}
+
+impl Spans {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            open: BTreeMap::new(),
+            capacity,
+        }
+    }
+
+    /// Records a tap event, returning the stream's span once it has ended.
+    fn record(&mut self, ev: &api::TapEvent) -> Option<Span> {
+        let http = match ev.event {
+            Some(api::tap_event::Event::Http(ref http)) => http.event.as_ref()?,
+            _ => return None,
+        };
+        match *http {
+            api::tap_event::http::Event::RequestInit(ref init) => {
+                let id = init.id.as_ref()?;
+                let span = self.span(id, ev);
+                span.method = init.method.as_ref().and_then(json::method);
+                span.authority = init.authority.clone();
+                span.path = init.path.clone();
+                None
+            }
+            api::tap_event::http::Event::ResponseInit(ref init) => {
+                let id = init.id.as_ref()?;
+                self.span(id, ev).http_status = Some(init.http_status);
+                None
+            }
+            api::tap_event::http::Event::ResponseEnd(ref end) => {
+                let id = end.id.as_ref()?;
+                let mut span = self.open.remove(&(id.base, id.stream)).unwrap_or_else(|| {
+                    // Streams whose earlier events were dropped are still
+                    // reported, if only partially.
+                    Span::new(id, ev)
+                });
+
+                // The stream's start is derived from its end, so that it is
+                // known even if its `RequestInit` was not emitted.
+                span.end_unix_us = timestamp_us(ev)?;
+                let elapsed = end.since_request_init.as_ref().map(json::micros);
+                span.start_unix_us = span
+                    .end_unix_us
+                    .saturating_sub(elapsed.unwrap_or(0).max(0) as u64);
+                span.response_bytes = end.response_bytes;
+                match end.eos.as_ref().and_then(|eos| eos.end.as_ref()) {
+                    Some(api::eos::End::GrpcStatusCode(code)) => span.grpc_status = Some(*code),
+                    Some(api::eos::End::ResetErrorCode(code)) => {
+                        span.reset_error_code = Some(*code)
+                    }
+                    None => {}
+                }
+                Some(span)
+            }
+        }
+    }
+
+    fn span(&mut self, id: &api::tap_event::http::StreamId, ev: &api::TapEvent) -> &mut Span {
+        let key = (id.base, id.stream);
+        if !self.open.contains_key(&key) && self.open.len() >= self.capacity {
+            let oldest = self.open.keys().next().cloned();
+            if let Some(oldest) = oldest {
+                trace!("discarding unended tap span; id={:?}", oldest);
+                self.open.remove(&oldest);
+            }
+        }
+        self.open.entry(key).or_insert_with(|| Span::new(id, ev))
+    }
+}
+
+impl Span {
+    fn new(id: &api::tap_event::http::StreamId, ev: &api::TapEvent) -> Self {
+        Self {
+            base: id.base,
+            stream: id.stream,
+            outbound: ev.proxy_direction == api::tap_event::ProxyDirection::Outbound as i32,
+            ..Self::default()
+        }
+    }
+
+    fn is_error(&self) -> bool {
+        self.reset_error_code.is_some()
+            || self.grpc_status.map(|s| s != 0).unwrap_or(false)
+            || self.http_status.map(|s| s >= 500).unwrap_or(false)
+    }
+
+    fn encode(&self, trace_id: &str) -> String {
+        let mut obj = Object::new();
+        obj.string("traceId", trace_id);
+        obj.string(
+            "spanId",
+            &format!("{:08x}{:08x}", self.base, self.stream as u32),
+        );
+        obj.string(
+            "name",
+            self.method.as_ref().map(|m| m.as_str()).unwrap_or("HTTP"),
+        );
+        obj.number(
+            "kind",
+            if self.outbound {
+                KIND_OUTBOUND
+            } else {
+                KIND_INBOUND
+            },
+        );
+        obj.string(
+            "startTimeUnixNano",
+            &(self.start_unix_us * 1_000).to_string(),
+        );
+        obj.string("endTimeUnixNano", &(self.end_unix_us * 1_000).to_string());
+
+        let mut attrs = Vec::new();
+        if let Some(ref m) = self.method {
+            attrs.push(attribute("http.method", "stringValue", m));
+        }
+        attrs.push(attribute("http.host", "stringValue", &self.authority));
+        attrs.push(attribute("http.target", "stringValue", &self.path));
+        if let Some(s) = self.http_status {
+            attrs.push(attribute("http.status_code", "intValue", &s.to_string()));
+        }
+        if let Some(s) = self.grpc_status {
+            attrs.push(attribute(
+                "rpc.grpc.status_code",
+                "intValue",
+                &s.to_string(),
+            ));
+        }
+        attrs.push(attribute(
+            "http.response_content_length",
+            "intValue",
+            &self.response_bytes.to_string(),
+        ));
+        obj.array("attributes", attrs);
+
+        let mut status = Object::new();
+        status.number(
+            "code",
+            if self.is_error() {
+                STATUS_ERROR
+            } else {
+                STATUS_UNSET
+            },
+        );
+        obj.object("status", status);
+
+        obj.finish()
+    }
+}
+
+/// Encodes a span attribute. Integers are encoded as strings, as in the JSON
+/// encoding of protobuf.
+fn attribute(key: &str, kind: &str, value: &str) -> Object {
+    let mut v = Object::new();
+    v.string(kind, value);
+    let mut o = Object::new();
+    o.string("key", key);
+    o.object("value", v);
+    o
+}
+
+/// Reads the wall-clock time at which an event was emitted.
+fn timestamp_us(ev: &api::TapEvent) -> Option<u64> {
+    ev.route_meta
+        .as_ref()?
+        .labels
+        .get(super::grpc::TIMESTAMP_LABEL)?
+        .parse()
+        .ok()
+}
+
+/// Tapped streams carry no trace context, so each span is its own trace.
+fn trace_id() -> String {
+    format!(
+        "{:016x}{:016x}",
+        rand::random::<u64>(),
+        rand::random::<u64>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(outbound: bool, timestamp_us: u64, ev: api::tap_event::http::Event) -> api::TapEvent {
+        let mut meta = api::tap_event::RouteMeta::default();
+        meta.labels.insert(
+            super::super::grpc::TIMESTAMP_LABEL.to_owned(),
+            timestamp_us.to_string(),
+        );
+        api::TapEvent {
+            proxy_direction: if outbound {
+                api::tap_event::ProxyDirection::Outbound.into()
+            } else {
+                api::tap_event::ProxyDirection::Inbound.into()
+            },
+            route_meta: Some(meta),
+            event: Some(api::tap_event::Event::Http(api::tap_event::Http {
+                event: Some(ev),
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn id() -> Option<api::tap_event::http::StreamId> {
+        stream_id(2)
+    }
+
+    fn stream_id(stream: u64) -> Option<api::tap_event::http::StreamId> {
+        Some(api::tap_event::http::StreamId { base: 1, stream })
+    }
+
+    #[test]
+    fn maps_lifecycle_events_to_spans() {
+        use api::tap_event::http::{Event, RequestInit, ResponseEnd, ResponseInit};
+
+        let mut spans = Spans::default();
+        let init = event(
+            true,
+            1_000,
+            Event::RequestInit(RequestInit {
+                id: id(),
+                method: Some((&::http::Method::GET).into()),
+                authority: "web.example.com".to_owned(),
+                path: "/index.html".to_owned(),
+                ..Default::default()
+            }),
+        );
+        assert_eq!(spans.record(&init), None);
+
+        let rsp = event(
+            true,
+            1_500,
+            Event::ResponseInit(ResponseInit {
+                id: id(),
+                http_status: 503,
+                ..Default::default()
+            }),
+        );
+        assert_eq!(spans.record(&rsp), None);
+
+        let end = event(
+            true,
+            4_000,
+            Event::ResponseEnd(ResponseEnd {
+                id: id(),
+                since_request_init: Some(::prost_types::Duration {
+                    seconds: 0,
+                    nanos: 3_000_000,
+                }),
+                response_bytes: 42,
+                ..Default::default()
+            }),
+        );
+        let span = spans.record(&end).expect("span must end");
+        assert!(spans.open.is_empty());
+
+        assert_eq!(
+            span,
+            Span {
+                base: 1,
+                stream: 2,
+                outbound: true,
+                method: Some("GET".to_owned()),
+                authority: "web.example.com".to_owned(),
+                path: "/index.html".to_owned(),
+                http_status: Some(503),
+                start_unix_us: 1_000,
+                end_unix_us: 4_000,
+                response_bytes: 42,
+                grpc_status: None,
+                reset_error_code: None,
+            }
+        );
+        assert!(span.is_error());
+
+        let encoded = span.encode("0af7651916cd43dd8448eb211c80319c");
+        assert!(encoded.starts_with(
+            r#"{"traceId":"0af7651916cd43dd8448eb211c80319c","spanId":"0000000100000002","name":"GET","kind":3,"startTimeUnixNano":"1000000","endTimeUnixNano":"4000000","#
+        ));
+        assert!(encoded.contains(r#"{"key":"http.status_code","value":{"intValue":"503"}}"#));
+        assert!(encoded.ends_with(r#""status":{"code":2}}"#));
+    }
+
+    #[test]
+    fn reports_streams_without_earlier_events() {
+        let mut spans = Spans::default();
+        let end = event(
+            false,
+            2_000,
+            api::tap_event::http::Event::ResponseEnd(api::tap_event::http::ResponseEnd {
+                id: id(),
+                eos: Some(api::Eos {
+                    end: Some(api::eos::End::GrpcStatusCode(0)),
+                }),
+                ..Default::default()
+            }),
+        );
+        let span = spans.record(&end).expect("span must end");
+        assert!(!span.outbound);
+        assert_eq!(span.start_unix_us, 2_000);
+        assert_eq!(span.grpc_status, Some(0));
+        assert!(!span.is_error());
+    }
+
+    #[test]
+    fn discards_the_oldest_unended_streams() {
+        use api::tap_event::http::{Event, RequestInit, ResponseEnd};
+
+        let mut spans = Spans::with_capacity(2);
+        for stream in 1..5 {
+            let init = event(
+                false,
+                1_000,
+                Event::RequestInit(RequestInit {
+                    id: stream_id(stream),
+                    path: format!("/{}", stream),
+                    ..Default::default()
+                }),
+            );
+            assert_eq!(spans.record(&init), None);
+            assert!(spans.open.len() <= 2);
+        }
+        let open = spans.open.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(open, vec![(1, 3), (1, 4)]);
+
+        // The streams that were discarded are still reported when they end,
+        // if only partially.
+        let end = event(
+            false,
+            2_000,
+            Event::ResponseEnd(ResponseEnd {
+                id: stream_id(1),
+                ..Default::default()
+            }),
+        );
+        let span = spans.record(&end).expect("span must end");
+        assert_eq!(span.path, "");
+        assert_eq!(spans.open.len(), 2);
+
+        let end = event(
+            false,
+            2_000,
+            Event::ResponseEnd(ResponseEnd {
+                id: stream_id(4),
+                ..Default::default()
+            }),
+        );
+        let span = spans.record(&end).expect("span must end");
+        assert_eq!(span.path, "/4");
+        assert_eq!(spans.open.len(), 1);
+    }
+}
@@ -5,6 +5,7 @@ pub mod keepalive;
 pub mod metrics;
 mod peek;
 mod prefixed;
+pub mod retry;
 pub mod tls;
 
 pub use self::{
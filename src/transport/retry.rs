@@ -0,0 +1,217 @@
+use futures::{Async, Future, Poll};
+use rand;
+use std::fmt;
+use tokio_timer::{clock, Delay};
+
+use proxy::reconnect::Backoff;
+use svc;
+
+/// Retries failed connection attempts up to `max_retries` times, waiting
+/// according to `backoff` between attempts.
+///
+/// This layer does not bound the total time spent connecting; it is expected
+/// to be wrapped by a connect timeout.
+pub fn layer(max_retries: usize, backoff: Backoff) -> Layer {
+    Layer {
+        max_retries,
+        backoff,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    max_retries: usize,
+    backoff: Backoff,
+}
+
+#[derive(Clone, Debug)]
+pub struct Connect<C> {
+    inner: C,
+    max_retries: usize,
+    backoff: Backoff,
+}
+
+pub struct ConnectFuture<C, T>
+where
+    C: svc::MakeConnection<T>,
+{
+    inner: C,
+    target: T,
+    backoff: Backoff,
+    retries_remaining: usize,
+    failures: u32,
+    state: State<C::Future>,
+}
+
+enum State<F> {
+    Connecting(F),
+    Backoff(Option<Delay>),
+}
+
+// === impl Layer ===
+
+impl<C> svc::Layer<C> for Layer {
+    type Service = Connect<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        Connect {
+            inner,
+            max_retries: self.max_retries,
+            backoff: self.backoff.clone(),
+        }
+    }
+}
+
+// === impl Connect ===
+
+/// impl MakeConnection
+impl<C, T> svc::Service<T> for Connect<C>
+where
+    C: svc::MakeConnection<T> + Clone,
+    C::Error: fmt::Display,
+    T: Clone + fmt::Debug,
+{
+    type Response = C::Connection;
+    type Error = C::Error;
+    type Future = ConnectFuture<C, T>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let future = self.inner.make_connection(target.clone());
+        ConnectFuture {
+            inner: self.inner.clone(),
+            target,
+            backoff: self.backoff.clone(),
+            retries_remaining: self.max_retries,
+            failures: 0,
+            state: State::Connecting(future),
+        }
+    }
+}
+
+// === impl ConnectFuture ===
+
+impl<C, T> Future for ConnectFuture<C, T>
+where
+    C: svc::MakeConnection<T>,
+    C::Error: fmt::Display,
+    T: Clone + fmt::Debug,
+{
+    type Item = C::Connection;
+    type Error = C::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Connecting(ref mut future) => match future.poll() {
+                    Ok(ready) => return Ok(ready),
+                    Err(e) => {
+                        if self.retries_remaining == 0 {
+                            return Err(e);
+                        }
+                        self.retries_remaining -= 1;
+                        debug!(
+                            "connect error to {:?}: {}; retrying ({} remaining)",
+                            self.target, e, self.retries_remaining
+                        );
+
+                        let delay = self
+                            .backoff
+                            .for_failures(self.failures, rand::thread_rng())
+                            .map(|backoff| Delay::new(clock::now() + backoff));
+                        self.failures += 1;
+                        State::Backoff(delay)
+                    }
+                },
+                State::Backoff(ref mut delay) => {
+                    if let Some(ref mut delay) = delay {
+                        // If the timer fails, retry immediately rather than
+                        // failing the connection.
+                        if let Ok(Async::NotReady) = delay.poll() {
+                            return Ok(Async::NotReady);
+                        }
+                    }
+                    try_ready!(self.inner.poll_ready());
+                    State::Connecting(self.inner.make_connection(self.target.clone()))
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, FutureResult};
+    use futures::{Future, Poll};
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use proxy::reconnect::Backoff;
+    use svc::{self, Layer, Service};
+
+    /// Fails the first `fails` connection attempts.
+    #[derive(Clone)]
+    struct Flaky {
+        fails: usize,
+        attempts: Arc<AtomicUsize>,
+    }
+
+    impl svc::Service<()> for Flaky {
+        type Response = usize;
+        type Error = io::Error;
+        type Future = FutureResult<usize, io::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            let attempt = self.attempts.fetch_add(1, Ordering::SeqCst);
+            if attempt < self.fails {
+                future::err(io::ErrorKind::ConnectionRefused.into())
+            } else {
+                future::ok(attempt)
+            }
+        }
+    }
+
+    fn flaky(fails: usize) -> (Flaky, Arc<AtomicUsize>) {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let svc = Flaky {
+            fails,
+            attempts: attempts.clone(),
+        };
+        (svc, attempts)
+    }
+
+    #[test]
+    fn does_not_retry_by_default() {
+        let (inner, attempts) = flaky(1);
+        let mut svc = super::layer(0, Backoff::None).layer(inner);
+
+        assert!(svc.call(()).wait().is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retries_until_connected() {
+        let (inner, attempts) = flaky(2);
+        let mut svc = super::layer(3, Backoff::None).layer(inner);
+
+        assert_eq!(svc.call(()).wait().unwrap(), 2);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn fails_once_retries_are_exhausted() {
+        let (inner, attempts) = flaky(3);
+        let mut svc = super::layer(2, Backoff::None).layer(inner);
+
+        assert!(svc.call(()).wait().is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+}
@@ -66,11 +66,16 @@ impl Proxy {
     /// it will just affect what the proxy think is the so_original_dst.
     ///
     /// This address is bogus, but the proxy should properly ignored the IP
-    /// and only use the port combined with 127.0.0.1 to still connect to
-    /// the server.
+    /// and only use the port combined with the loopback address of the same
+    /// family (127.0.0.1 or ::1) to still connect to the server.
     pub fn inbound_fuzz_addr(self, mut s: server::Listening) -> Self {
         let old_addr = s.addr;
-        let new_addr = ([10, 1, 2, 3], old_addr.port()).into();
+        let new_addr = if old_addr.is_ipv4() {
+            ([10, 1, 2, 3], old_addr.port()).into()
+        } else {
+            let ip = ::std::net::Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0x0102, 0x0304);
+            (ip, old_addr.port()).into()
+        };
         s.addr = new_addr;
         self.inbound(s)
     }
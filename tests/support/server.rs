@@ -136,7 +136,20 @@ impl Server {
         self.run_inner(None)
     }
 
+    /// Runs the server on the IPv6 loopback address, rather than `127.0.0.1`.
+    pub fn run_ipv6(self) -> Listening {
+        self.run_inner_on(SocketAddr::from((::std::net::Ipv6Addr::LOCALHOST, 0)), None)
+    }
+
     fn run_inner(self, delay: Option<Box<Future<Item = (), Error = ()> + Send>>) -> Listening {
+        self.run_inner_on(SocketAddr::from(([127, 0, 0, 1], 0)), delay)
+    }
+
+    fn run_inner_on(
+        self,
+        addr: SocketAddr,
+        delay: Option<Box<Future<Item = (), Error = ()> + Send>>,
+    ) -> Listening {
         let (tx, rx) = shutdown_signal();
         let (listening_tx, listening_rx) = oneshot::channel();
         let mut listening_tx = Some(listening_tx);
@@ -145,8 +158,11 @@ impl Server {
         let version = self.version;
         let tname = format!("support {:?} server (test={})", version, thread_name(),);
 
-        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
-        let listener = net2::TcpBuilder::new_v4().expect("Tcp::new_v4");
+        let listener = if addr.is_ipv4() {
+            net2::TcpBuilder::new_v4().expect("Tcp::new_v4")
+        } else {
+            net2::TcpBuilder::new_v6().expect("Tcp::new_v6")
+        };
         listener.bind(addr).expect("Tcp::bind");
         let addr = listener.local_addr().expect("Tcp::local_addr");
 
@@ -30,6 +30,17 @@ fn inbound_http1() {
     assert_eq!(client.get("/"), "hello h1");
 }
 
+#[test]
+fn inbound_http1_ipv6_orig_dst() {
+    let _ = env_logger_init();
+
+    let srv = server::http1().route("/", "hello h1").run_ipv6();
+    let proxy = proxy::new().inbound_fuzz_addr(srv).run();
+    let client = client::http1(proxy.inbound, "transparency.test.svc.cluster.local");
+
+    assert_eq!(client.get("/"), "hello h1");
+}
+
 #[test]
 fn outbound_tcp() {
     let _ = env_logger_init();
@@ -1002,6 +1013,65 @@ fn http1_one_connection_per_host() {
     run_request("quuuux.com", 3);
 }
 
+#[test]
+fn http1_idle_connections_are_pooled_when_enabled() {
+    let _ = env_logger_init();
+
+    let srv = server::http1().route("/", "hello").run();
+    let mut env = app::config::TestEnv::new();
+    env.put(
+        app::config::ENV_INBOUND_MAX_IDLE_CONNECTIONS_PER_ENDPOINT,
+        "1".to_owned(),
+    );
+    let proxy = proxy::new().inbound(srv).run_with_test_env(env);
+
+    let client = client::http1(proxy.inbound, "foo.bar");
+
+    let inbound = &proxy.inbound_server.as_ref().expect("no inbound server!");
+
+    // Each request to the same endpoint must reuse the pooled connection.
+    for _ in 0..3 {
+        let res = client.request(
+            client
+                .request_builder("/")
+                .version(http::Version::HTTP_11)
+                .header("host", "foo.bar"),
+        );
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(inbound.connections(), 1);
+    }
+}
+
+#[test]
+fn http1_idle_connections_are_not_pooled_when_disabled() {
+    let _ = env_logger_init();
+
+    let srv = server::http1().route("/", "hello").run();
+    let mut env = app::config::TestEnv::new();
+    env.put(
+        app::config::ENV_INBOUND_MAX_IDLE_CONNECTIONS_PER_ENDPOINT,
+        "0".to_owned(),
+    );
+    let proxy = proxy::new().inbound(srv).run_with_test_env(env);
+
+    let client = client::http1(proxy.inbound, "foo.bar");
+
+    let inbound = &proxy.inbound_server.as_ref().expect("no inbound server!");
+
+    // With no idle connections pooled, each request to the same endpoint
+    // must use a new connection.
+    for expected_conn_cnt in 1..3 {
+        let res = client.request(
+            client
+                .request_builder("/")
+                .version(http::Version::HTTP_11)
+                .header("host", "foo.bar"),
+        );
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(inbound.connections(), expected_conn_cnt);
+    }
+}
+
 #[test]
 fn http1_requests_without_host_have_unique_connections() {
     let _ = env_logger_init();